@@ -0,0 +1,49 @@
+public class PrimArrays {
+    public static int bytes() {
+        byte[] b = new byte[3];
+        b[0] = (byte) 200;
+        b[1] = 100;
+        return b[0] + b[1] + b[2] + b.length;
+    }
+
+    public static int chars() {
+        char[] c = new char[1];
+        c[0] = (char) -1;
+        return c[0];
+    }
+
+    public static int shorts() {
+        short[] s = new short[2];
+        s[1] = (short) 40000;
+        return s[1];
+    }
+
+    public static int booleans() {
+        boolean[] z = new boolean[4];
+        z[3] = true;
+        return z.length;
+    }
+
+    public static long longs() {
+        long[] l = new long[3];
+        l[2] = 1L;
+        return l[2] + l[1] + l.length;
+    }
+
+    public static float floats() {
+        float[] f = new float[2];
+        f[0] = 2.0f;
+        return f[0] + f[1];
+    }
+
+    public static double doubles() {
+        double[] d = new double[2];
+        d[1] = 2.5;
+        return d[0] + d[1];
+    }
+
+    public static int outOfBounds() {
+        int[] i = new int[2];
+        return i[2];
+    }
+}
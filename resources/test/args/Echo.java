@@ -0,0 +1,5 @@
+public class Echo {
+    public static int main2(String[] args) {
+        return args.length * 100 + args[1].length();
+    }
+}
@@ -0,0 +1,44 @@
+public class RefArrays {
+    public static int lengths() {
+        RefArrays[] a = new RefArrays[4];
+        a[2] = new RefArrays();
+        return a.length;
+    }
+
+    public static int multi() {
+        int[][] m = new int[3][4];
+        m[1][2] = 7;
+        return m.length + m[1].length + m[1][2];
+    }
+
+    public static int partial() {
+        String[][][] m = new String[2][3][];
+        return m[1].length;
+    }
+
+    public static int covariant() {
+        Object[] o = new Object[3];
+        o[0] = new RefArrays();
+        o[1] = new int[1];
+        o[2] = new String[0];
+        Runnable[] r = new Runnable[1];
+        r[0] = new Task();
+        return o.length + r.length;
+    }
+
+    public static int badStore() {
+        Object[] o = new RefArrays[1];
+        o[0] = new Object();
+        return 0;
+    }
+
+    public static int missingElement() {
+        Task[] t = new Task[2];
+        return t.length;
+    }
+}
+
+class Task implements Runnable {
+    public void run() {
+    }
+}
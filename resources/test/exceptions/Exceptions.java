@@ -0,0 +1,100 @@
+// Throwing and catching, within a frame and across frames.
+// Compile with: javac --release 8 Exceptions.java
+public class Exceptions {
+    static Oops thrown;
+    static Oops caught;
+    static int finallyRan;
+
+    static int thrower() {
+        throw new Oops();
+    }
+
+    static int throwIt(Oops o) {
+        throw o;
+    }
+
+    static int middle() {
+        return thrower() + 100;
+    }
+
+    static int withFinally() {
+        try {
+            return thrower();
+        } finally {
+            finallyRan = 1;
+        }
+    }
+
+    public static int sameFrame() {
+        try {
+            throw new Oops();
+        } catch (Oops e) {
+            return 2;
+        }
+    }
+
+    public static int callerCatches() {
+        try {
+            return thrower();
+        } catch (Oops e) {
+            return 3;
+        }
+    }
+
+    public static int superclassCatches() {
+        try {
+            return thrower();
+        } catch (RuntimeException e) {
+            return 4;
+        }
+    }
+
+    public static int firstMatchingHandler() {
+        try {
+            return thrower();
+        } catch (IllegalStateException e) {
+            return 5;
+        } catch (Oops e) {
+            return 6;
+        } catch (RuntimeException e) {
+            return 7;
+        }
+    }
+
+    public static int unwindsTwoFrames() {
+        try {
+            return middle();
+        } catch (Oops e) {
+            return 8;
+        }
+    }
+
+    public static int finallyRuns() {
+        try {
+            return withFinally();
+        } catch (Oops e) {
+            return finallyRan + 10;
+        }
+    }
+
+    public static int catchesThrownObject() {
+        thrown = new Oops();
+        try {
+            throwIt(thrown);
+        } catch (Oops e) {
+            caught = e;
+        }
+        return 12;
+    }
+
+    public static int uncaught() {
+        return middle();
+    }
+
+    public static int main2(String[] args) {
+        return uncaught();
+    }
+}
+
+class Oops extends RuntimeException {
+}
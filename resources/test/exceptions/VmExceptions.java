@@ -0,0 +1,110 @@
+// Exceptions the VM raises itself, rather than by ATHROW.
+// Compile with: javac --release 8 VmExceptions.java
+public class VmExceptions {
+    static int zero;
+    static int[] nums;
+    static VmExceptions none;
+    int field;
+
+    static int divide() {
+        return 1 / zero;
+    }
+
+    public static int divideByZero() {
+        try {
+            return 7 / zero;
+        } catch (ArithmeticException e) {
+            return 1;
+        }
+    }
+
+    public static int remainderByZero() {
+        try {
+            return (int) (1L % zero);
+        } catch (ArithmeticException e) {
+            return 2;
+        }
+    }
+
+    public static int nullField() {
+        try {
+            return none.field;
+        } catch (NullPointerException e) {
+            return 3;
+        }
+    }
+
+    public static int nullArray() {
+        try {
+            return nums[0];
+        } catch (NullPointerException e) {
+            return 4;
+        }
+    }
+
+    public static int outOfBounds() {
+        int[] a = new int[2];
+        try {
+            return a[2];
+        } catch (ArrayIndexOutOfBoundsException e) {
+            return 5;
+        }
+    }
+
+    public static int negativeSize() {
+        try {
+            int[] a = new int[zero + -1];
+            return a.length;
+        } catch (NegativeArraySizeException e) {
+            return 6;
+        }
+    }
+
+    public static int negativeObjectSize() {
+        try {
+            String[] a = new String[zero + -1];
+            return a.length;
+        } catch (NegativeArraySizeException e) {
+            return 9;
+        }
+    }
+
+    public static int negativeMultiSize() {
+        try {
+            int[][] a = new int[2][zero + -1];
+            return a.length;
+        } catch (NegativeArraySizeException e) {
+            return 10;
+        }
+    }
+
+    public static int tooLarge() {
+        try {
+            int[] a = new int[zero + Integer.MAX_VALUE];
+            return a.length;
+        } catch (OutOfMemoryError e) {
+            return 11;
+        }
+    }
+
+    public static int nullThrow() {
+        RuntimeException r = null;
+        try {
+            throw r;
+        } catch (NullPointerException e) {
+            return 7;
+        }
+    }
+
+    public static int callerCatches() {
+        try {
+            return divide();
+        } catch (RuntimeException e) {
+            return 8;
+        }
+    }
+
+    public static int uncaught() {
+        return divide();
+    }
+}
@@ -0,0 +1,52 @@
+public class Finalizable {
+    static int finalized;
+
+    protected void finalize() {
+        finalized++;
+    }
+
+    // Three objects that are garbage straight away, one of them finalized
+    // through the finalize() it inherits
+    public static int make() {
+        new Finalizable();
+        new Finalizable();
+        new Heir();
+        return 3;
+    }
+}
+
+class Heir extends Finalizable {
+}
+
+// Saves itself from being collected the first time round
+class Phoenix {
+    static int finalized;
+    static Phoenix saved;
+
+    protected void finalize() {
+        finalized++;
+        saved = this;
+    }
+
+    public static int make() {
+        new Phoenix();
+        return 1;
+    }
+}
+
+// Throws from its finalizer, which must not stop the others running
+class Faulty {
+    static int finalized;
+
+    protected void finalize() {
+        finalized++;
+        int[] none = null;
+        none[0] = 1;
+    }
+
+    public static int make() {
+        new Faulty();
+        new Faulty();
+        return 2;
+    }
+}
@@ -0,0 +1,6 @@
+public class Node {
+    static Node keep;
+
+    Node next;
+    int value;
+}
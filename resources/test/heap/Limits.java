@@ -0,0 +1,29 @@
+public class Limits {
+    // Three 400k arrays, all reachable at once
+    public static int held() {
+        int[] a = new int[100000];
+        int[] b = new int[100000];
+        int[] c = new int[100000];
+        return a.length + b.length + c.length;
+    }
+
+    // The same three arrays, but each one is garbage by the time the next is
+    // allocated
+    public static int garbage() {
+        int[] a = new int[100000];
+        a = new int[100000];
+        a = new int[100000];
+        return a.length;
+    }
+
+    // Once it has been thrown the arrays held so far are garbage, so there
+    // is room to allocate again
+    public static int caught() {
+        try {
+            return held();
+        } catch (OutOfMemoryError e) {
+            int[] d = new int[100000];
+            return d.length + 1;
+        }
+    }
+}
@@ -0,0 +1,6 @@
+public class Huge {
+    public static int allocate() {
+        int[] a = new int[Integer.MAX_VALUE];
+        return a.length;
+    }
+}
@@ -0,0 +1,153 @@
+// Compiled with javac --release 11, so lambdas, method refs and string
+// concatenation all go through INVOKEDYNAMIC
+interface IntOp {
+    int apply(int a, int b);
+}
+
+interface IntSource {
+    int get();
+}
+
+interface PtMaker {
+    Pt make(int x);
+}
+
+interface PtGetter {
+    int get(Pt p);
+}
+
+interface Mixer {
+    long mix(long a, int b, double c);
+}
+
+interface Action {
+    void run();
+}
+
+class Pt {
+    int x;
+
+    Pt(int x) {
+        this.x = x;
+    }
+
+    int getX() {
+        return x;
+    }
+
+    public String toString() {
+        return "Pt(" + x + ")";
+    }
+}
+
+public class Lambdas {
+    static int counter;
+
+    int base;
+
+    static int times(int a, int b) {
+        return a * b;
+    }
+
+    static int bump() {
+        counter = counter + 3;
+        return counter;
+    }
+
+    public static int nonCapturing() {
+        IntOp op = (a, b) -> a + b;
+        return op.apply(3, 4);
+    }
+
+    public static int capturing() {
+        int k = 10;
+        IntSource s = () -> k + 5;
+        return s.get();
+    }
+
+    public static int capturingThis() {
+        Lambdas l = new Lambdas();
+        l.base = 20;
+        return l.viaThis();
+    }
+
+    int viaThis() {
+        IntSource s = () -> base + 2;
+        return s.get();
+    }
+
+    public static int staticRef() {
+        IntOp op = Lambdas::times;
+        return op.apply(6, 7);
+    }
+
+    public static int constructorRef() {
+        PtMaker m = Pt::new;
+        return m.make(9).x;
+    }
+
+    public static int unboundRef() {
+        PtGetter g = Pt::getX;
+        return g.get(new Pt(11));
+    }
+
+    public static int wideArgs() {
+        long extra = 1L;
+        Mixer m = (a, b, c) -> a * b + (long) c + extra;
+        long one = 1L;
+        return (int) m.mix(one, 6, 7.0);
+    }
+
+    public static int voidSam() {
+        counter = 0;
+        Action r = Lambdas::bump;
+        r.run();
+        r.run();
+        return counter;
+    }
+
+    public static int sameSiteTwice() {
+        return capturing() + capturing();
+    }
+
+    public static String concatInt() {
+        int n = 42;
+        return "n=" + n;
+    }
+
+    public static String concatStrings() {
+        String a = "foo";
+        return a + "-" + a;
+    }
+
+    public static String concatChar() {
+        char c = 'x';
+        return "c" + c;
+    }
+
+    public static String concatNull() {
+        String s = null;
+        Object o = null;
+        return "s=" + s + ",o=" + o;
+    }
+
+    public static String concatPrims() {
+        boolean b = true;
+        long l = 1L;
+        float f = 2.0f;
+        double d = 1.5;
+        return b + "," + l + "," + f + "," + d;
+    }
+
+    public static String concatDoubles() {
+        double big = 1.0e10;
+        double small = 0.001;
+        double tiny = 1.0e-4;
+        return big + "," + small + "," + tiny;
+    }
+
+    public static String concatConstant() {
+        int n = 7;
+        return "a\u0001b" + n;
+    }
+}
@@ -0,0 +1,144 @@
+// Interface calls - selection through the receiver's klass, default methods
+// and static and private interface methods. Some klasses here must not match
+// the interfaces they were compiled against, so build in three steps:
+//   javac --release 9 -d . before/Right.java before/Later.java Mismatched.java
+//   javac --release 9 -d . -cp . Right.java Later.java before/Quitter.java Interfaces.java
+//   javac --release 9 -d . -cp . Quitter.java
+// Private interface methods need release 9, which still calls them with INVOKESPECIAL
+import java.util.Enumeration;
+import java.util.Observable;
+import java.util.Observer;
+import java.util.Vector;
+
+public class Interfaces {
+    static Shape none;
+
+    public static int implemented() {
+        Shape s = new Square();
+        return s.sides();
+    }
+
+    public static int defaultMethod() {
+        Shape s = new Square();
+        return s.corners();
+    }
+
+    public static int classBeatsDefault() {
+        Shape s = new Triangle();
+        return s.corners();
+    }
+
+    public static int fromSuperclass() {
+        Shape s = new Pentagon();
+        return s.sides();
+    }
+
+    public static int mostSpecificDefault() {
+        Named n = new Square();
+        return n.name();
+    }
+
+    public static int staticMethod() {
+        return Shape.unit();
+    }
+
+    public static int privateMethod() {
+        Shape s = new Square();
+        return s.doubled();
+    }
+
+    public static int bootstrapInterfaces() {
+        Observer o = new Watcher();
+        o.update(null, o);
+        Enumeration e = new Vector().elements();
+        return Watcher.updates * 10 + (e.hasMoreElements() ? 1 : 0);
+    }
+
+    public static int nullReceiver() {
+        try {
+            return none.sides();
+        } catch (NullPointerException e) {
+            return 9;
+        }
+    }
+
+    public static int conflictingDefaults() {
+        Left c = new Conflict();
+        return c.pick();
+    }
+
+    public static int notImplemented() {
+        Later l = new Early();
+        return l.late();
+    }
+
+    public static int notAnImplementor() {
+        Named n = new Quitter();
+        return n.name();
+    }
+}
+
+interface Shape {
+    int sides();
+
+    default int corners() {
+        return sides();
+    }
+
+    default int doubled() {
+        return twice(sides());
+    }
+
+    private int twice(int n) {
+        return n * 2;
+    }
+
+    static int unit() {
+        return 1;
+    }
+}
+
+interface Named {
+    default int name() {
+        return 1;
+    }
+}
+
+interface Renamed extends Named {
+    default int name() {
+        return 2;
+    }
+}
+
+class Square implements Shape, Named, Renamed {
+    public int sides() {
+        return 4;
+    }
+}
+
+class Triangle implements Shape {
+    public int sides() {
+        return 3;
+    }
+
+    public int corners() {
+        return 30;
+    }
+}
+
+class Base {
+    public int sides() {
+        return 5;
+    }
+}
+
+class Pentagon extends Base implements Shape {
+}
+
+class Watcher implements Observer {
+    static int updates;
+
+    public void update(Observable o, Object arg) {
+        updates++;
+    }
+}
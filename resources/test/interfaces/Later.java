@@ -0,0 +1,4 @@
+// Early was compiled before this method was added
+interface Later {
+    int late();
+}
@@ -0,0 +1,12 @@
+// Compiled against the interfaces in before/ - see Interfaces.java
+interface Left {
+    default int pick() {
+        return 1;
+    }
+}
+
+class Conflict implements Left, Right {
+}
+
+class Early implements Later {
+}
@@ -0,0 +1,3 @@
+// Interfaces was compiled when this still implemented Named
+class Quitter {
+}
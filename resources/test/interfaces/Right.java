@@ -0,0 +1,6 @@
+// Conflict was compiled before this default was added
+interface Right {
+    default int pick() {
+        return 2;
+    }
+}
@@ -0,0 +1,2 @@
+class Quitter implements Named {
+}
@@ -0,0 +1,52 @@
+// Arguments laid out in the callee's local vars as its descriptor says.
+// Compile with: javac --release 8 Args.java
+public class Args {
+    static Args none;
+    int field;
+
+    Args(int field) {
+        this.field = field;
+    }
+
+    static int digits(int a, int b, int c) {
+        return a * 100 + b * 10 + c;
+    }
+
+    // Longs and doubles take two slots, so c and d are in slots 3 and 4
+    static int wide(int a, long b, int c, double d) {
+        return a * 1000 + (int) b * 100 + c * 10 + (int) d;
+    }
+
+    int scaled(int m, long n) {
+        return field * m + (int) n;
+    }
+
+    public static int staticArgs() {
+        return digits(1, 2, 3);
+    }
+
+    public static int wideArgs() {
+        int one = 1;
+        return wide(4, one + 2, 2, 1.5);
+    }
+
+    public static int instanceArgs() {
+        Args a = new Args(7);
+        int three = 3;
+        return a.scaled(6, three);
+    }
+
+    public static int nativeArgs() {
+        int base = 2;
+        int exp = 5;
+        return (int) Math.pow(base, exp);
+    }
+
+    public static int nullReceiver() {
+        try {
+            return none.scaled(1, 1L);
+        } catch (NullPointerException e) {
+            return 9;
+        }
+    }
+}
@@ -0,0 +1,6 @@
+// Parent.class is deliberately not kept, so Orphan can never be resolved
+public class Orphan extends Parent {
+}
+
+class Parent {
+}
@@ -0,0 +1,7 @@
+public class Plugin {
+
+    public static int version() {
+        return PluginHelper.base() + 1;
+    }
+
+}
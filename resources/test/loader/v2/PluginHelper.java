@@ -0,0 +1,7 @@
+public class PluginHelper {
+
+    public static int base() {
+        return 20;
+    }
+
+}
@@ -0,0 +1,21 @@
+public class Hashes {
+    public static int stable() {
+        Object o = new Object();
+        return o.hashCode() - o.hashCode();
+    }
+
+    public static int distinct() {
+        Object a = new Object();
+        Object b = new Object();
+        return a.hashCode() - b.hashCode();
+    }
+
+    public static int locked() {
+        Object o = new Object();
+        synchronized (o) {
+            synchronized (o) {
+                return 2;
+            }
+        }
+    }
+}
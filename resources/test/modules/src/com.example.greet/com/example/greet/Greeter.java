@@ -0,0 +1,9 @@
+package com.example.greet;
+
+public class Greeter {
+
+    public static int answer() {
+        return Helper.half() * 2;
+    }
+
+}
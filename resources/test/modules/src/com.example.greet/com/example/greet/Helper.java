@@ -0,0 +1,9 @@
+package com.example.greet;
+
+class Helper {
+
+    static int half() {
+        return 21;
+    }
+
+}
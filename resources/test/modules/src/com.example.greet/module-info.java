@@ -0,0 +1,3 @@
+module com.example.greet {
+    exports com.example.greet;
+}
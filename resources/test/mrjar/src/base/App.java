@@ -0,0 +1,7 @@
+public class App {
+
+    public static int main2(String[] args) {
+        return Version.id() + LibValue.value();
+    }
+
+}
@@ -0,0 +1,7 @@
+public class Version {
+
+    public static int id() {
+        return 8;
+    }
+
+}
@@ -0,0 +1,7 @@
+public class LibValue {
+
+    public static int value() {
+        return 100;
+    }
+
+}
@@ -0,0 +1,17 @@
+public class Counter {
+    public static int value() {
+        return 3;
+    }
+
+    public static int other() {
+        return 300;
+    }
+
+    public static int next() {
+        return value() + 30;
+    }
+
+    public static int added() {
+        return 0;
+    }
+}
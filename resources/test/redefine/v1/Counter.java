@@ -0,0 +1,13 @@
+public class Counter {
+    public static int value() {
+        return 1;
+    }
+
+    public static int other() {
+        return 100;
+    }
+
+    public static int next() {
+        return value() + 10;
+    }
+}
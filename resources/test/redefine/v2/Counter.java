@@ -0,0 +1,13 @@
+public class Counter {
+    public static int value() {
+        return 2;
+    }
+
+    public static int other() {
+        return 200;
+    }
+
+    public static int next() {
+        return other() + value() + 20;
+    }
+}
@@ -0,0 +1,17 @@
+public class Literals {
+    // A NUL, a two byte char and a char outside the BMP
+    public static String greeting() {
+        return "h\u00e9llo\u0000\ud83d\ude00";
+    }
+
+    public static int length() {
+        return "hello, world".length();
+    }
+}
+
+// The same literal in another class, so from another constant pool
+class Echo {
+    public static String greeting() {
+        return "h\u00e9llo\u0000\ud83d\ude00";
+    }
+}
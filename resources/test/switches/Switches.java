@@ -0,0 +1,114 @@
+public class Switches {
+    // Dense keys give a TABLESWITCH
+    public static int table(int i) {
+        switch (i) {
+            case 1:
+                return 10;
+            case 2:
+                return 20;
+            case 3:
+                return 30;
+            case 4:
+                return 40;
+            default:
+                return -1;
+        }
+    }
+
+    // Sparse keys, some negative, give a LOOKUPSWITCH
+    public static int lookup(int i) {
+        switch (i) {
+            case -100000:
+                return 1;
+            case -5:
+                return 2;
+            case 7:
+                return 3;
+            case 1000:
+                return 4;
+            case 100000:
+                return 5;
+            default:
+                return 0;
+        }
+    }
+
+    public static int fallThrough(int i) {
+        int total = 0;
+        switch (i) {
+            case 1:
+                total = total + 1;
+            case 2:
+                total = total + 10;
+                break;
+            case 3:
+                total = total + 100;
+            default:
+                total = total + 1000;
+        }
+        return total;
+    }
+
+    public static int denseChars(char c) {
+        switch (c) {
+            case 'a':
+                return 1;
+            case 'b':
+                return 2;
+            case 'c':
+                return 3;
+            default:
+                return 0;
+        }
+    }
+
+    public static int sparseChars(char c) {
+        switch (c) {
+            case '0':
+                return 100;
+            case 'A':
+                return 200;
+            case 'z':
+                return 300;
+            default:
+                return 0;
+        }
+    }
+
+    // "Aa" and "BB" have the same hashCode, so equals() tells them apart
+    public static int strings(String s) {
+        switch (s) {
+            case "foo":
+                return 1;
+            case "bar":
+                return 2;
+            case "Aa":
+                return 3;
+            case "BB":
+                return 4;
+            default:
+                return 0;
+        }
+    }
+
+    // A switch in a loop, so the GOTO back to it has a negative offset
+    public static int machine(int start) {
+        int state = start;
+        int steps = 0;
+        while (true) {
+            switch (state) {
+                case 0:
+                    state = 2;
+                    break;
+                case 1:
+                    return steps;
+                case 2:
+                    state = 1;
+                    break;
+                default:
+                    return -1;
+            }
+            steps = steps + 1;
+        }
+    }
+}
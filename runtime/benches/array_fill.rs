@@ -0,0 +1,46 @@
+// Fills an int[] one IASTORE at a time and sums it back, at doubling sizes.
+// Run with: cargo bench --bench array_fill
+// Time per element should stay flat as the array grows - stores are in place,
+// so filling is linear in the length
+use std::time::{Duration, Instant};
+
+use ocelotter_runtime::copying_heap::CopyingHeap;
+use ocelotter_runtime::heap::Heap;
+use ocelotter_runtime::otklass::OtKlass;
+use ocelotter_runtime::simple_heap::SharedSimpleHeap;
+use ocelotter_runtime::JvmValue;
+
+const SIZES: [i32; 4] = [125_000, 250_000, 500_000, 1_000_000];
+
+fn fill_and_sum(heap: &mut dyn Heap, size: i32) -> (Duration, i64) {
+    let ints = OtKlass::of("[I".to_string(), "java/lang/Object".to_string(), 0, &Vec::new(), &Vec::new(), &Vec::new());
+    let start = Instant::now();
+    let arr = heap.allocate_prim_arr(&ints, size).unwrap();
+    for i in 0..size {
+        heap.array_store(arr, i, JvmValue::Int(i));
+    }
+    let mut sum: i64 = 0;
+    for i in 0..size {
+        sum += heap.get_obj(arr).array_load(i).as_int().unwrap() as i64;
+    }
+    (start.elapsed(), sum)
+}
+
+fn run(name: &str, make_heap: fn() -> Box<dyn Heap>) {
+    println!("{}:", name);
+    for size in SIZES.iter() {
+        let (elapsed, sum) = fill_and_sum(make_heap().as_mut(), *size);
+        assert_eq!((*size as i64) * (*size as i64 - 1) / 2, sum);
+        println!(
+            "  {:>9} elements {:>10.3} ms {:>8.2} ns/element",
+            size,
+            elapsed.as_secs_f64() * 1000.0,
+            elapsed.as_nanos() as f64 / *size as f64
+        );
+    }
+}
+
+fn main() {
+    run("Mark-sweep", || Box::new(SharedSimpleHeap::of()));
+    run("Copying", || Box::new(CopyingHeap::of()));
+}
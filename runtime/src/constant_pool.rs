@@ -39,6 +39,17 @@ pub const CP_METHODHANDLE: u8 = 15;
 pub const CP_METHODTYPE: u8 = 16;
 pub const CP_INVOKEDYNAMIC: u8 = 18;
 
+// The kinds of method handle (JVMS 4.4.8)
+pub const REF_GETFIELD: u8 = 1;
+pub const REF_GETSTATIC: u8 = 2;
+pub const REF_PUTFIELD: u8 = 3;
+pub const REF_PUTSTATIC: u8 = 4;
+pub const REF_INVOKEVIRTUAL: u8 = 5;
+pub const REF_INVOKESTATIC: u8 = 6;
+pub const REF_INVOKESPECIAL: u8 = 7;
+pub const REF_NEWINVOKESPECIAL: u8 = 8;
+pub const REF_INVOKEINTERFACE: u8 = 9;
+
 #[derive(Clone,Debug)]
 pub struct ClassRef(pub u16);
 #[derive(Clone,Debug)]
@@ -80,6 +91,42 @@ impl InterfaceMethodRef {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct MethodHandleRef {
+    pub kind: u8,
+    pub ref_idx: u16,
+}
+
+impl MethodHandleRef {
+    pub fn new(kind: u8, ref_idx: u16) -> Self {
+        MethodHandleRef { kind, ref_idx }
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct MethodTypeRef(pub u16);
+
+// bsm_idx indexes the klass's BootstrapMethods attribute, not the constant pool
+#[derive(Clone, Copy, Debug)]
+pub struct InvokeDynamicRef {
+    pub bsm_idx: u16,
+    pub nt_idx:  u16,
+}
+
+impl InvokeDynamicRef {
+    pub fn new(bsm_idx: u16, nt_idx: u16) -> Self {
+        InvokeDynamicRef { bsm_idx, nt_idx }
+    }
+}
+
+// An entry in the BootstrapMethods attribute - the method handle to call and
+// its static arguments, all as constant pool indices
+#[derive(Clone, Debug, PartialEq)]
+pub struct BootstrapMethod {
+    pub method_idx: u16,
+    pub arg_idxs: Vec<u16>,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct NameAndType {
     pub name_idx: u16,
@@ -105,6 +152,9 @@ pub enum CpEntry {
     MethodRef(MethodRef),
     InterfaceMethodRef(InterfaceMethodRef),
     NameAndType(NameAndType),
+    MethodHandle(MethodHandleRef),
+    MethodType(MethodTypeRef),
+    InvokeDynamic(InvokeDynamicRef),
 }
 
 impl CpEntry {
@@ -130,6 +180,9 @@ impl CpEntry {
             CpEntry::MethodRef(_) => "Methodref".to_string(),
             CpEntry::InterfaceMethodRef(_) => "Instance_Methodref".to_string(),
             CpEntry::NameAndType(_) => "NameAndType".to_string(),
+            CpEntry::MethodHandle(_) => "MethodHandle".to_string(),
+            CpEntry::MethodType(_) => "MethodType".to_string(),
+            CpEntry::InvokeDynamic(_) => "InvokeDynamic".to_string(),
         }
     }
 
@@ -0,0 +1,175 @@
+#![deny(unreachable_patterns)]
+
+use std::mem;
+
+use crate::heap::{Heap, HeapStats};
+use crate::mark_word::HashGenerator;
+use crate::OtObj;
+
+// Initial size of a semispace, in objects
+const SEMISPACE_SIZE: usize = 4096;
+
+// A semispace collector. Objects are bump allocated into the current space,
+// and a collection copies the reachable ones into a fresh space (Cheney's
+// algorithm) and drops the old one. Object ids are handles into the space,
+// so moving an object never changes its id
+#[derive(Clone)]
+pub struct CopyingHeap {
+    // Object id -> position in space, None for a free or reserved id
+    handles: Vec<Option<usize>>,
+    // Free list of ids reclaimed by the collector
+    free: Vec<usize>,
+    space: Vec<OtObj>,
+    // Size the space may grow to before a collection is due
+    capacity: usize,
+    stats: HeapStats,
+    hashes: HashGenerator,
+}
+
+impl CopyingHeap {
+    pub fn of() -> CopyingHeap {
+        let mut out = CopyingHeap {
+            handles: Vec::new(),
+            free: Vec::new(),
+            space: Vec::with_capacity(SEMISPACE_SIZE),
+            capacity: SEMISPACE_SIZE,
+            stats: HeapStats::of(),
+            hashes: HashGenerator::default(),
+        };
+        out.handles.push(Some(0));
+        out.space.push(OtObj::get_null());
+        out
+    }
+
+    // Move an object into to_space, unless it is already there
+    fn evacuate(&mut self, from_space: &mut [Option<OtObj>], to_space: &mut Vec<OtObj>, copied: &mut [bool], id: usize) {
+        if copied[id] {
+            return;
+        }
+        let from = self.handles[id].unwrap_or_else(|| panic!("Error: object {} not found", id));
+        let mut obj = from_space[from].take().unwrap();
+        if id != 0 {
+            obj.set_mark(obj.get_mark().aged());
+        }
+        self.handles[id] = Some(to_space.len());
+        to_space.push(obj);
+        copied[id] = true;
+    }
+}
+
+impl Heap for CopyingHeap {
+    fn name(&self) -> &'static str {
+        "copying"
+    }
+
+    fn box_clone(&self) -> Box<dyn Heap> {
+        Box::new(self.clone())
+    }
+
+    fn reserve(&mut self) -> usize {
+        match self.free.pop() {
+            Some(obj_id) => obj_id,
+            None => {
+                self.handles.push(None);
+                self.handles.len() - 1
+            }
+        }
+    }
+
+    fn fill(&mut self, id: usize, obj: OtObj) {
+        if self.handles[id].is_some() {
+            panic!("Error: slot {} is already in use", id);
+        }
+        self.stats.allocated(obj.shallow_size());
+        self.handles[id] = Some(self.space.len());
+        self.space.push(obj);
+    }
+
+    fn get_obj(&self, id: usize) -> &OtObj {
+        match self.handles.get(id) {
+            Some(Some(pos)) => &self.space[*pos],
+            _ => panic!("Error: object {} not found", id),
+        }
+    }
+
+    fn get_obj_mut(&mut self, id: usize) -> &mut OtObj {
+        match self.handles.get(id) {
+            Some(Some(pos)) => &mut self.space[*pos],
+            _ => panic!("Error: object {} not found", id),
+        }
+    }
+
+    fn replace_obj(&mut self, id: usize, obj: OtObj) {
+        match self.handles.get(id) {
+            Some(Some(pos)) => {
+                self.stats.resized(self.space[*pos].shallow_size(), obj.shallow_size());
+                self.space[*pos] = obj;
+            }
+            _ => panic!("Error: object {} not found", id),
+        }
+    }
+
+    fn is_live(&self, id: usize) -> bool {
+        matches!(self.handles.get(id), Some(Some(_)))
+    }
+
+    fn live_count(&self) -> usize {
+        self.space.len() - 1
+    }
+
+    fn live_ids(&self) -> Vec<usize> {
+        (1..self.handles.len()).filter(|id| self.handles[*id].is_some()).collect()
+    }
+
+    fn stats(&self) -> &HeapStats {
+        &self.stats
+    }
+
+    fn hashes(&mut self) -> &mut HashGenerator {
+        &mut self.hashes
+    }
+
+    fn set_limits(&mut self, initial_bytes: u64, max_bytes: u64) {
+        self.stats.set_limits(initial_bytes, max_bytes);
+    }
+
+    fn should_collect(&self) -> bool {
+        self.space.len() >= self.capacity
+    }
+
+    fn collect(&mut self, roots: &[usize]) -> usize {
+        let mut from_space: Vec<Option<OtObj>> = mem::take(&mut self.space).into_iter().map(Some).collect();
+        let mut to_space = Vec::with_capacity(self.capacity);
+        let mut copied = vec![false; self.handles.len()];
+
+        // Null stays at the front of every space
+        self.evacuate(&mut from_space, &mut to_space, &mut copied, 0);
+        for id in roots {
+            self.evacuate(&mut from_space, &mut to_space, &mut copied, *id);
+        }
+        // Everything behind scan has had its references copied too
+        let mut scan = 0;
+        while scan < to_space.len() {
+            for id in to_space[scan].references() {
+                self.evacuate(&mut from_space, &mut to_space, &mut copied, id);
+            }
+            scan += 1;
+        }
+
+        let mut freed = 0;
+        for (id, handle) in self.handles.iter_mut().enumerate() {
+            if !copied[id] && handle.is_some() {
+                *handle = None;
+                self.free.push(id);
+                freed += 1;
+            }
+        }
+        self.space = to_space;
+        self.stats.collected(self.space.iter().skip(1).map(|o| o.shallow_size() as u64).sum());
+        // Keep at least half the space free for new objects
+        while self.space.len() * 2 > self.capacity {
+            self.capacity *= 2;
+        }
+        freed
+    }
+}
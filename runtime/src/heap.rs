@@ -0,0 +1,277 @@
+#![deny(unreachable_patterns)]
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::mark_word::HashGenerator;
+use crate::JvmValue;
+use crate::OtField;
+use crate::OtKlass;
+use crate::OtObj;
+use crate::VmException;
+
+// What the interpreter needs from a heap. Object ids handed out by a heap stay
+// valid for as long as the object is reachable, whatever the collector does
+// with the object itself
+pub trait Heap: Send {
+    // Short name of the collector, for logging
+    fn name(&self) -> &'static str;
+
+    // A copy of the heap and everything in it, under the same ids
+    fn box_clone(&self) -> Box<dyn Heap>;
+
+    // Hands out the id of an empty slot, which must be filled before anything
+    // else touches the heap
+    fn reserve(&mut self) -> usize;
+
+    fn fill(&mut self, id: usize, obj: OtObj);
+
+    fn get_obj(&self, id: usize) -> &OtObj;
+
+    fn get_obj_mut(&mut self, id: usize) -> &mut OtObj;
+
+    // Swaps a new version of an object into an existing id
+    fn replace_obj(&mut self, id: usize, obj: OtObj);
+
+    fn is_live(&self, id: usize) -> bool;
+
+    // Number of objects currently in the heap, not counting null
+    fn live_count(&self) -> usize;
+
+    // Ids of every object in the heap, not counting null, in id order
+    fn live_ids(&self) -> Vec<usize>;
+
+    fn should_collect(&self) -> bool;
+
+    // Running totals of what has been allocated and what is still in use
+    fn stats(&self) -> &HeapStats;
+
+    // Where this heap's identity hashes come from
+    fn hashes(&mut self) -> &mut HashGenerator;
+
+    // Sets the initial and maximum sizes, as for -Xms and -Xmx
+    fn set_limits(&mut self, initial_bytes: u64, max_bytes: u64);
+
+    // Reclaim everything not reachable from roots. Returns the number of
+    // objects reclaimed
+    fn collect(&mut self, roots: &[usize]) -> usize;
+
+    // Ids of everything reachable from roots, not counting null. Collectors
+    // trace for themselves - this is for deciding what to do before collecting
+    fn reachable(&self, roots: &[usize]) -> HashSet<usize> {
+        let mut seen = HashSet::new();
+        let mut pending = roots.to_vec();
+        while let Some(id) = pending.pop() {
+            if id != 0 && seen.insert(id) {
+                pending.extend(self.get_obj(id).references());
+            }
+        }
+        seen
+    }
+
+    fn allocate_obj(&mut self, klass: &OtKlass) -> usize {
+        let obj_id = self.reserve();
+        self.fill(obj_id, OtObj::obj_of(klass.get_id(), obj_id, klass.make_default_values()));
+        obj_id
+    }
+
+    // Arrays of a negative size are refused before anything is reserved - klass
+    // is the array klass, [I
+    fn allocate_int_arr(&mut self, klass: &OtKlass, size: i32) -> Result<usize, VmException> {
+        check_array_length(size)?;
+        let obj_id = self.reserve();
+        self.fill(obj_id, OtObj::int_arr_of(klass.get_id(), size, obj_id));
+        Ok(obj_id)
+    }
+
+    // An array of zeroes - klass is a primitive array klass, e.g. [J
+    fn allocate_prim_arr(&mut self, klass: &OtKlass, size: i32) -> Result<usize, VmException> {
+        check_array_length(size)?;
+        let elem_type = klass.get_name().chars().nth(1).unwrap();
+        let obj_id = self.reserve();
+        self.fill(obj_id, OtObj::new_prim_arr(klass.get_id(), elem_type, size, obj_id));
+        Ok(obj_id)
+    }
+
+    // An array of nulls - klass is the array klass, e.g. [Ljava/lang/String;
+    fn allocate_obj_arr(&mut self, klass: &OtKlass, size: i32) -> Result<usize, VmException> {
+        check_array_length(size)?;
+        let obj_id = self.reserve();
+        self.fill(obj_id, OtObj::obj_arr_of(klass.get_id(), vec![0; size as usize], obj_id));
+        Ok(obj_id)
+    }
+
+    fn allocate_byte_arr_from(&mut self, klass: &OtKlass, bytes: &[u8]) -> usize {
+        let obj_id = self.reserve();
+        self.fill(obj_id, OtObj::byte_arr_of(klass.get_id(), bytes.iter().map(|b| *b as i8).collect(), obj_id));
+        obj_id
+    }
+
+    fn allocate_char_arr_from(&mut self, klass: &OtKlass, chars: &[u16]) -> usize {
+        let obj_id = self.reserve();
+        self.fill(obj_id, OtObj::char_arr_of(klass.get_id(), chars.to_vec(), obj_id));
+        obj_id
+    }
+
+    // FIXME Handle storage properly
+    fn put_field(&self, id: usize, f: OtField, v: JvmValue) {
+        // Get object from heap
+        self.get_obj(id).put_field(f.get_offset() as usize, v);
+    }
+
+    fn get_field(&self, id: usize, offset: u16) -> JvmValue {
+        // Get object from heap
+        self.get_obj(id).get_field_value(offset as usize)
+    }
+
+    // Stores go straight into the array - elements never change its size
+    fn array_store(&mut self, id: usize, pos: i32, v: JvmValue) {
+        self.get_obj_mut(id).array_store(pos, v);
+    }
+
+    // Assigned on first request and kept in the mark word from then on. Null
+    // hashes to 0, as System.identityHashCode(null) does
+    fn identity_hash(&mut self, id: usize) -> i32 {
+        if id == 0 {
+            return 0;
+        }
+        let mark = self.get_obj(id).get_mark().with_hash(self.hashes());
+        self.get_obj_mut(id).set_mark(mark);
+        mark.hash().unwrap()
+    }
+
+    // Returns false, leaving the monitor alone, if it is already entered as
+    // many times as the mark word can count
+    fn monitor_enter(&mut self, id: usize) -> bool {
+        let obj = self.get_obj_mut(id);
+        match obj.get_mark().locked() {
+            Some(mark) => {
+                obj.set_mark(mark);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Returns false if the monitor isn't held
+    fn monitor_exit(&mut self, id: usize) -> bool {
+        let obj = self.get_obj_mut(id);
+        match obj.get_mark().unlocked() {
+            Some(mark) => {
+                obj.set_mark(mark);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_byte_arr_region(&self, id: usize, offset: i32, len: i32) -> Vec<u8> {
+        match self.get_obj(id) {
+            OtObj::VmArrByte {
+                id: _,
+                mark: _,
+                klassid: _,
+                length: _,
+                elements: elts,
+            } => elts[offset as usize..(offset + len) as usize]
+                .iter()
+                .map(|b| *b as u8)
+                .collect(),
+            _ => panic!("Non-byte[] seen in heap at {}", id),
+        }
+    }
+
+    fn get_char_arr_region(&self, id: usize, offset: i32, len: i32) -> Vec<u16> {
+        match self.get_obj(id) {
+            OtObj::VmArrChar {
+                id: _,
+                mark: _,
+                klassid: _,
+                length: _,
+                elements: elts,
+            } => elts[offset as usize..(offset + len) as usize].to_vec(),
+            _ => panic!("Non-char[] seen in heap at {}", id),
+        }
+    }
+}
+
+fn check_array_length(size: i32) -> Result<(), VmException> {
+    if size < 0 {
+        return Err(VmException::of("java/lang/NegativeArraySizeException", size.to_string()));
+    }
+    Ok(())
+}
+
+impl fmt::Debug for dyn Heap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} heap of {} objects", self.name(), self.live_count())
+    }
+}
+
+// Byte counts are shallow sizes, as given by OtObj::shallow_size, and never
+// include null
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeapStats {
+    // Everything ever allocated, whether or not it has since been collected
+    pub allocated_bytes: u64,
+    pub used_bytes: u64,
+    // What the heap has grown to - it starts at the initial size (-Xms) and
+    // doubles whenever the objects in use no longer fit, up to max_bytes, but
+    // never shrinks
+    pub committed_bytes: u64,
+    // The most the objects in use may add up to (-Xmx)
+    pub max_bytes: u64,
+}
+
+pub const INITIAL_COMMITTED_BYTES: u64 = 1024 * 1024;
+pub const DEFAULT_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+impl HeapStats {
+    pub fn of() -> HeapStats {
+        HeapStats { allocated_bytes: 0, used_bytes: 0, committed_bytes: INITIAL_COMMITTED_BYTES, max_bytes: DEFAULT_MAX_BYTES }
+    }
+
+    pub fn set_limits(&mut self, initial_bytes: u64, max_bytes: u64) {
+        if initial_bytes > max_bytes {
+            panic!("Initial heap size set to a larger value than the maximum heap size");
+        }
+        self.committed_bytes = initial_bytes;
+        self.max_bytes = max_bytes;
+        self.grow();
+    }
+
+    // Objects the VM allocates for itself, such as Class mirrors, don't wait
+    // for room, so used can briefly go past committed
+    pub fn free_bytes(&self) -> u64 {
+        self.committed_bytes.saturating_sub(self.used_bytes)
+    }
+
+    // Whether size more bytes of objects would stay within max_bytes
+    pub fn has_room_for(&self, size: u64) -> bool {
+        self.used_bytes.saturating_add(size) <= self.max_bytes
+    }
+
+    pub fn allocated(&mut self, size: usize) {
+        self.allocated_bytes += size as u64;
+        self.used_bytes += size as u64;
+        self.grow();
+    }
+
+    // An object was swapped for a version of a different size
+    pub fn resized(&mut self, old_size: usize, new_size: usize) {
+        self.used_bytes = self.used_bytes - old_size as u64 + new_size as u64;
+        self.grow();
+    }
+
+    // After a collection, with the total size of the survivors
+    pub fn collected(&mut self, live_bytes: u64) {
+        self.used_bytes = live_bytes;
+    }
+
+    fn grow(&mut self) {
+        while self.used_bytes > self.committed_bytes && self.committed_bytes < self.max_bytes {
+            // An initial size of 0 grows straight to what is in use
+            self.committed_bytes = (self.committed_bytes * 2).max(self.used_bytes).min(self.max_bytes);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::fmt;
+
+// One line of a class histogram - every live instance of a klass, and the
+// total of their shallow sizes
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistogramEntry {
+    pub klass_name: String,
+    pub instances: usize,
+    pub bytes: u64,
+}
+
+// The live heap broken down by klass, biggest first, as jmap -histo prints it
+#[derive(Clone, Debug, Default)]
+pub struct ClassHistogram {
+    pub entries: Vec<HistogramEntry>,
+}
+
+impl ClassHistogram {
+    // objects are (klass name, shallow size) of each object in the heap
+    pub fn of(objects: impl IntoIterator<Item = (String, usize)>) -> ClassHistogram {
+        let mut by_klass: HashMap<String, HistogramEntry> = HashMap::new();
+        for (klass_name, size) in objects {
+            let entry = by_klass.entry(klass_name.clone()).or_insert(HistogramEntry { klass_name, instances: 0, bytes: 0 });
+            entry.instances += 1;
+            entry.bytes += size as u64;
+        }
+        let mut entries: Vec<HistogramEntry> = by_klass.into_values().collect();
+        // Ties are broken by name, so the order is stable
+        entries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.klass_name.cmp(&b.klass_name)));
+        ClassHistogram { entries }
+    }
+
+    pub fn get(&self, klass_name: &str) -> Option<&HistogramEntry> {
+        self.entries.iter().find(|e| e.klass_name == klass_name)
+    }
+
+    pub fn total_instances(&self) -> usize {
+        self.entries.iter().map(|e| e.instances).sum()
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.entries.iter().map(|e| e.bytes).sum()
+    }
+}
+
+// Class names are shown dotted, as jmap does - arrays keep their descriptor
+// form, e.g. [Ljava.lang.String;
+impl fmt::Display for ClassHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, " num     #instances         #bytes  class name")?;
+        writeln!(f, "----------------------------------------------")?;
+        for (i, e) in self.entries.iter().enumerate() {
+            writeln!(f, "{:>4}: {:>14} {:>14}  {}", i + 1, e.instances, e.bytes, e.klass_name.replace('/', "."))?;
+        }
+        writeln!(f, "Total {:>14} {:>14}", self.total_instances(), self.total_bytes())
+    }
+}
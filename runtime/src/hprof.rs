@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+use crate::heap::Heap;
+use crate::JvmValue;
+use crate::OtKlass;
+use crate::OtObj;
+
+// Heap dumps in the HPROF binary format (JAVA PROFILE 1.0.2) that HotSpot
+// writes, so the usual heap analysis tools can read them. Identifiers are 8
+// bytes - an object's identifier is its heap id, and a klass is identified by
+// its klass id with CLASS_ID_TAG set, so the two never collide.
+const HPROF_HEADER: &[u8] = b"JAVA PROFILE 1.0.2\0";
+const ID_SIZE: u32 = 8;
+pub const CLASS_ID_TAG: u64 = 1 << 62;
+
+// Top level record tags
+pub const TAG_STRING: u8 = 0x01;
+pub const TAG_LOAD_CLASS: u8 = 0x02;
+pub const TAG_STACK_TRACE: u8 = 0x05;
+pub const TAG_HEAP_DUMP_SEGMENT: u8 = 0x1c;
+pub const TAG_HEAP_DUMP_END: u8 = 0x2c;
+
+// Heap dump sub-record tags
+pub const ROOT_JNI_GLOBAL: u8 = 0x01;
+pub const ROOT_JAVA_FRAME: u8 = 0x03;
+pub const ROOT_STICKY_CLASS: u8 = 0x05;
+pub const CLASS_DUMP: u8 = 0x20;
+pub const INSTANCE_DUMP: u8 = 0x21;
+pub const OBJ_ARRAY_DUMP: u8 = 0x22;
+pub const PRIM_ARRAY_DUMP: u8 = 0x23;
+
+// Every record points at a stack trace - ours is a single empty one, on the
+// one interpreter thread
+const STACK_TRACE_SERIAL: u32 = 1;
+const THREAD_SERIAL: u32 = 1;
+
+// What keeps objects alive, other than statics - those are found through the
+// class dumps. Frames are innermost last, as the repo keeps them
+pub struct HeapDumpRoots {
+    pub sticky_klasses: Vec<usize>,
+    pub globals: Vec<usize>,
+    pub frames: Vec<Vec<usize>>,
+}
+
+// The HPROF basic type of a field descriptor or array element letter
+pub fn basic_type(desc: char) -> u8 {
+    match desc {
+        'L' | '[' | 'A' => 2,
+        'Z' => 4,
+        'C' => 5,
+        'F' => 6,
+        'D' => 7,
+        'B' => 8,
+        'S' => 9,
+        'I' => 10,
+        'J' => 11,
+        _ => panic!("Illegal type {} seen in heap dump", desc),
+    }
+}
+
+// Size in bytes of a value of a basic type
+pub fn basic_type_size(t: u8) -> usize {
+    match t {
+        2 => ID_SIZE as usize,
+        4 | 8 => 1,
+        5 | 9 => 2,
+        6 | 10 => 4,
+        7 | 11 => 8,
+        _ => panic!("Illegal basic type {} seen in heap dump", t),
+    }
+}
+
+fn klass_id(id: usize) -> u64 {
+    match id {
+        0 => 0,
+        _ => CLASS_ID_TAG | id as u64,
+    }
+}
+
+// Names are written as STRING records and referred to by their id
+struct Strings {
+    ids: HashMap<String, u64>,
+    out: Vec<u8>,
+}
+
+impl Strings {
+    fn id(&mut self, s: &str) -> u64 {
+        if let Some(id) = self.ids.get(s) {
+            return *id;
+        }
+        let id = self.ids.len() as u64 + 1;
+        write_record(&mut self.out, TAG_STRING, &{
+            let mut body = Vec::new();
+            body.write_u64::<BigEndian>(id).unwrap();
+            body.extend_from_slice(s.as_bytes());
+            body
+        });
+        self.ids.insert(s.to_string(), id);
+        id
+    }
+}
+
+fn write_record(out: &mut Vec<u8>, tag: u8, body: &[u8]) {
+    out.write_u8(tag).unwrap();
+    // Microseconds since the header timestamp
+    out.write_u32::<BigEndian>(0).unwrap();
+    out.write_u32::<BigEndian>(body.len() as u32).unwrap();
+    out.extend_from_slice(body);
+}
+
+// Values are converted to the declared type, as stores into fields don't
+// narrow booleans, bytes, shorts or chars
+fn write_value(out: &mut Vec<u8>, t: u8, v: JvmValue) {
+    let int_bits = match v {
+        JvmValue::Boolean(b) => b as i32,
+        JvmValue::Byte(b) => b as i32,
+        JvmValue::Short(s) => s as i32,
+        JvmValue::Char(c) => c as i32,
+        JvmValue::Int(i) => i,
+        _ => 0,
+    };
+    match t {
+        2 => out.write_u64::<BigEndian>(v.as_objref().unwrap_or(0) as u64),
+        4 => out.write_u8((int_bits != 0) as u8),
+        5 => out.write_u16::<BigEndian>(int_bits as u16),
+        6 => out.write_f32::<BigEndian>(v.as_float().unwrap_or(0.0)),
+        7 => out.write_f64::<BigEndian>(v.as_double().unwrap_or(0.0)),
+        8 => out.write_i8(int_bits as i8),
+        9 => out.write_i16::<BigEndian>(int_bits as i16),
+        10 => out.write_i32::<BigEndian>(int_bits),
+        11 => out.write_i64::<BigEndian>(v.as_long().unwrap_or(0)),
+        _ => panic!("Illegal basic type {} seen in heap dump", t),
+    }
+    .unwrap();
+}
+
+fn field_type(desc: &str) -> u8 {
+    basic_type(desc.chars().next().unwrap_or_else(|| panic!("Empty field descriptor seen in heap dump")))
+}
+
+// klasses are (klass, klass id of its superclass or 0), and every klass named
+// by an object in the heap must be among them
+pub fn encode(klasses: &[(OtKlass, usize)], roots: &HeapDumpRoots, heap: &dyn Heap, timestamp_ms: u64) -> Vec<u8> {
+    let by_id: HashMap<usize, &(OtKlass, usize)> = klasses.iter().map(|k| (k.0.get_id(), k)).collect();
+    let mut strings = Strings { ids: HashMap::new(), out: Vec::new() };
+    let mut klass_records = Vec::new();
+    let mut dump = Vec::new();
+
+    for (serial, (k, _)) in klasses.iter().enumerate() {
+        let mut body = Vec::new();
+        body.write_u32::<BigEndian>(serial as u32 + 1).unwrap();
+        body.write_u64::<BigEndian>(klass_id(k.get_id())).unwrap();
+        body.write_u32::<BigEndian>(STACK_TRACE_SERIAL).unwrap();
+        body.write_u64::<BigEndian>(strings.id(&k.get_name())).unwrap();
+        write_record(&mut klass_records, TAG_LOAD_CLASS, &body);
+    }
+
+    // Roots
+    for id in &roots.sticky_klasses {
+        dump.write_u8(ROOT_STICKY_CLASS).unwrap();
+        dump.write_u64::<BigEndian>(klass_id(*id)).unwrap();
+    }
+    for id in &roots.globals {
+        dump.write_u8(ROOT_JNI_GLOBAL).unwrap();
+        dump.write_u64::<BigEndian>(*id as u64).unwrap();
+        dump.write_u64::<BigEndian>(*id as u64).unwrap();
+    }
+    // Frame numbers count up from the innermost frame
+    for (depth, frame) in roots.frames.iter().rev().enumerate() {
+        for id in frame {
+            dump.write_u8(ROOT_JAVA_FRAME).unwrap();
+            dump.write_u64::<BigEndian>(*id as u64).unwrap();
+            dump.write_u32::<BigEndian>(THREAD_SERIAL).unwrap();
+            dump.write_u32::<BigEndian>(depth as u32).unwrap();
+        }
+    }
+
+    // Klasses
+    for (k, super_id) in klasses {
+        let i_fields = k.get_instance_fields();
+        dump.write_u8(CLASS_DUMP).unwrap();
+        dump.write_u64::<BigEndian>(klass_id(k.get_id())).unwrap();
+        dump.write_u32::<BigEndian>(STACK_TRACE_SERIAL).unwrap();
+        dump.write_u64::<BigEndian>(klass_id(*super_id)).unwrap();
+        dump.write_u64::<BigEndian>(k.get_loader() as u64).unwrap();
+        // Signers, protection domain and two reserved ids
+        for _ in 0..4 {
+            dump.write_u64::<BigEndian>(0).unwrap();
+        }
+        let instance_size: usize = i_fields.iter().map(|f| basic_type_size(field_type(&f.get_desc()))).sum();
+        dump.write_u32::<BigEndian>(instance_size as u32).unwrap();
+        // No constant pool entries
+        dump.write_u16::<BigEndian>(0).unwrap();
+
+        let s_fields = k.get_static_fields();
+        dump.write_u16::<BigEndian>(s_fields.len() as u16).unwrap();
+        for f in &s_fields {
+            let t = field_type(&f.get_desc());
+            dump.write_u64::<BigEndian>(strings.id(&f.get_name())).unwrap();
+            dump.write_u8(t).unwrap();
+            write_value(&mut dump, t, k.get_static(f));
+        }
+        dump.write_u16::<BigEndian>(i_fields.len() as u16).unwrap();
+        for f in &i_fields {
+            dump.write_u64::<BigEndian>(strings.id(&f.get_name())).unwrap();
+            dump.write_u8(field_type(&f.get_desc())).unwrap();
+        }
+    }
+
+    // Objects
+    for id in heap.live_ids() {
+        match heap.get_obj(id) {
+            OtObj::VmObj { klassid, fields, .. } => {
+                // FIXME Objects only hold the fields their own klass declares,
+                // so inherited fields are dumped with their default values
+                let mut values = Vec::new();
+                let mut current = by_id.get(klassid).copied();
+                let mut own = true;
+                while let Some((k, super_id)) = current {
+                    for (i, f) in k.get_instance_fields().iter().enumerate() {
+                        let t = field_type(&f.get_desc());
+                        let v = if own { fields[i].get() } else { f.get_default() };
+                        write_value(&mut values, t, v);
+                    }
+                    own = false;
+                    current = by_id.get(super_id).copied();
+                }
+                dump.write_u8(INSTANCE_DUMP).unwrap();
+                dump.write_u64::<BigEndian>(id as u64).unwrap();
+                dump.write_u32::<BigEndian>(STACK_TRACE_SERIAL).unwrap();
+                dump.write_u64::<BigEndian>(klass_id(*klassid)).unwrap();
+                dump.write_u32::<BigEndian>(values.len() as u32).unwrap();
+                dump.extend_from_slice(&values);
+            }
+            OtObj::VmArrObj { klassid, elements, .. } => {
+                dump.write_u8(OBJ_ARRAY_DUMP).unwrap();
+                dump.write_u64::<BigEndian>(id as u64).unwrap();
+                dump.write_u32::<BigEndian>(STACK_TRACE_SERIAL).unwrap();
+                dump.write_u32::<BigEndian>(elements.len() as u32).unwrap();
+                dump.write_u64::<BigEndian>(klass_id(*klassid)).unwrap();
+                for e in elements {
+                    dump.write_u64::<BigEndian>(*e as u64).unwrap();
+                }
+            }
+            arr => {
+                let t = basic_type(arr.elem_type());
+                dump.write_u8(PRIM_ARRAY_DUMP).unwrap();
+                dump.write_u64::<BigEndian>(id as u64).unwrap();
+                dump.write_u32::<BigEndian>(STACK_TRACE_SERIAL).unwrap();
+                dump.write_u32::<BigEndian>(arr.length() as u32).unwrap();
+                dump.write_u8(t).unwrap();
+                for i in 0..arr.length() {
+                    write_value(&mut dump, t, arr.array_load(i));
+                }
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(HPROF_HEADER);
+    out.write_u32::<BigEndian>(ID_SIZE).unwrap();
+    out.write_u32::<BigEndian>((timestamp_ms >> 32) as u32).unwrap();
+    out.write_u32::<BigEndian>(timestamp_ms as u32).unwrap();
+    out.extend_from_slice(&strings.out);
+    out.extend_from_slice(&klass_records);
+    write_record(&mut out, TAG_STACK_TRACE, &{
+        let mut body = Vec::new();
+        body.write_u32::<BigEndian>(STACK_TRACE_SERIAL).unwrap();
+        body.write_u32::<BigEndian>(THREAD_SERIAL).unwrap();
+        // No frames
+        body.write_u32::<BigEndian>(0).unwrap();
+        body
+    });
+    write_record(&mut out, TAG_HEAP_DUMP_SEGMENT, &dump);
+    write_record(&mut out, TAG_HEAP_DUMP_END, &[]);
+    out
+}
@@ -2,11 +2,16 @@
 use std::cmp::Ordering;
 
 use crate::JvmValue;
+use crate::VmException;
 
 pub struct InterpEvalStack {
     stack: Vec<JvmValue>,
 }
 
+fn divide_by_zero() -> VmException {
+    VmException::of("java/lang/ArithmeticException", "/ by zero".to_string())
+}
+
 fn ordering(o: Ordering) -> JvmValue {
     JvmValue::Int(match o {
         Ordering::Less => -1,
@@ -33,6 +38,24 @@ impl InterpEvalStack {
         }
     }
 
+    // The value depth entries below the top, left where it is
+    pub fn peek(&self, depth: usize) -> JvmValue {
+        match self.stack.len().checked_sub(depth + 1) {
+            Some(i) => self.stack[i],
+            None => panic!("peek({}) on stack of {}", depth, self.stack.len()),
+        }
+    }
+
+    // Entering an exception handler throws away whatever the frame had on its stack
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+
+    // Heap ids of the objects on the stack, for the collector
+    pub fn obj_refs(&self) -> Vec<usize> {
+        self.stack.iter().filter_map(|v| v.as_objref()).filter(|id| *id != 0).collect()
+    }
+
     pub fn aconst_null(&mut self) {
         self.push(JvmValue::ObjRef(0)); // OtObj::get_null(),
     }
@@ -97,18 +120,27 @@ impl InterpEvalStack {
         self.push(JvmValue::Int(i1 * i2));
     }
 
-    pub fn irem(&mut self) {
+    pub fn irem(&mut self) -> Result<(), VmException> {
         // For a runtime checking interpreter - type checks would go here...
         let i1 = self.pop().as_int().expect("Unexpected, non-integer value encountered");
         let i2 = self.pop().as_int().expect("Unexpected, non-integer value encountered");
-        self.push(JvmValue::Int(i2 % i1));
+        if i1 == 0 {
+            return Err(divide_by_zero());
+        }
+        self.push(JvmValue::Int(i2.wrapping_rem(i1)));
+        Ok(())
     }
 
-    pub fn idiv(&mut self) {
+    // MIN_VALUE / -1 overflows back to MIN_VALUE, as in Java
+    pub fn idiv(&mut self) -> Result<(), VmException> {
         // For a runtime checking interpreter - type checks would go here...
         let i1 = self.pop().as_int().expect("Unexpected, non-integer value encountered");
         let i2 = self.pop().as_int().expect("Unexpected, non-integer value encountered");
-        self.push(JvmValue::Int(i2 / i1));
+        if i1 == 0 {
+            return Err(divide_by_zero());
+        }
+        self.push(JvmValue::Int(i2.wrapping_div(i1)));
+        Ok(())
     }
 
     pub fn iand(&mut self) {
@@ -190,18 +222,26 @@ impl InterpEvalStack {
         self.push(JvmValue::Long(i1 - i2));
     }
 
-    pub fn lrem(&mut self) {
+    pub fn lrem(&mut self) -> Result<(), VmException> {
         // For a runtime checking interpreter - type checks would go here...
         let i1 = self.pop().as_long().expect("Unexpected, non-long value encountered");
         let i2 = self.pop().as_long().expect("Unexpected, non-long value encountered");
-        self.push(JvmValue::Long(i2 % i1));
+        if i1 == 0 {
+            return Err(divide_by_zero());
+        }
+        self.push(JvmValue::Long(i2.wrapping_rem(i1)));
+        Ok(())
     }
 
-    pub fn ldiv(&mut self) {
+    pub fn ldiv(&mut self) -> Result<(), VmException> {
         // For a runtime checking interpreter - type checks would go here...
         let i1 = self.pop().as_long().expect("Unexpected, non-long value encountered");
         let i2 = self.pop().as_long().expect("Unexpected, non-long value encountered");
-        self.push(JvmValue::Long(i2 / i1));
+        if i1 == 0 {
+            return Err(divide_by_zero());
+        }
+        self.push(JvmValue::Long(i2.wrapping_div(i1)));
+        Ok(())
     }
 
     pub fn lmul(&mut self) {
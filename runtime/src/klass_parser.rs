@@ -2,13 +2,13 @@
 
 use byteorder::{BigEndian, ByteOrder};
 use std::io::Read;
-use std::str;
 
 use crate::constant_pool::*;
 
 use crate::OtField;
 use crate::OtKlass;
 use crate::OtMethod;
+use crate::otmethod::ExceptionHandler;
 
 pub struct OtKlassParser {
     clz_read: Vec<u8>,
@@ -25,7 +25,7 @@ pub struct OtKlassParser {
     interfaces: Vec<u16>,
     fields: Vec<OtField>,
     methods: Vec<OtMethod>,
-    // attributes: Vec<CpAttr>,
+    bootstrap_methods: Vec<BootstrapMethod>,
 }
 
 impl OtKlassParser {
@@ -44,18 +44,35 @@ impl OtKlassParser {
             interfaces: Vec::new(),
             fields: Vec::new(),
             methods: Vec::new(),
+            bootstrap_methods: Vec::new(),
         }
     }
 
     pub fn klass(&mut self) -> OtKlass {
-        OtKlass::of(
+        let mut k = OtKlass::of(
             self.klass_name().to_string(),
             self.super_name().to_string(),
             self.flags,
             &self.cp_entries,
             &self.methods,
             &self.fields,
-        )
+        );
+        k.set_interfaces(self.interface_names());
+        k.set_bootstrap_methods(self.bootstrap_methods.clone());
+        k
+    }
+
+    fn interface_names(&self) -> Vec<String> {
+        self.interfaces
+            .iter()
+            .map(|idx| match &self.cp_entries[*idx as usize] {
+                CpEntry::Class(ClassRef(icl)) => match &self.cp_entries[*icl as usize] {
+                    CpEntry::Utf8(s) => s.clone(),
+                    _ => panic!("Interface index {} does not point at utf8 string in constant pool", icl),
+                },
+                _ => panic!("Interface index {} does not point at class element in constant pool", idx),
+            })
+            .collect()
     }
 
     fn klass_name(&self) -> &String {
@@ -113,7 +130,7 @@ impl OtKlassParser {
         self.parse_basic_type_info();
         self.parse_fields();
         self.parse_methods();
-        //        self.parseAttributes();
+        self.parse_attributes();
     }
 
     // CP is 1-indexed
@@ -167,11 +184,12 @@ impl OtKlassParser {
                         Ok(v) => {
                             self.current += len as usize;
 
-                            let str_c = match str::from_utf8(&buf) {
-                                Ok(v) => v,
-                                Err(e) => panic!("{}", e),
-                            }
-                            .to_owned();
+                            // Unpaired surrogates can't be held in a Rust String,
+                            // so they come out as U+FFFD
+                            let str_c = match decode_modified_utf8(&buf) {
+                                Ok(chars) => String::from_utf16_lossy(&chars),
+                                Err(e) => panic!("{} in constant pool of {}", e, self.filename),
+                            };
                             // dbg!(str_c.clone());
                             CpEntry::Utf8(str_c)
                         }
@@ -292,6 +310,23 @@ impl OtKlassParser {
                         ((b3 as u16) << 8) + b4 as u16
                     ))
                 }
+                CP_METHODHANDLE => {
+                    let kind = self.clz_read[self.current];
+                    let ref_idx = BigEndian::read_u16(&self.clz_read[self.current + 1..]);
+                    self.current += 3;
+                    CpEntry::MethodHandle(MethodHandleRef::new(kind, ref_idx))
+                }
+                CP_METHODTYPE => {
+                    let desc_idx = BigEndian::read_u16(&self.clz_read[self.current..]);
+                    self.current += 2;
+                    CpEntry::MethodType(MethodTypeRef(desc_idx))
+                }
+                CP_INVOKEDYNAMIC => {
+                    let bsm_idx = BigEndian::read_u16(&self.clz_read[self.current..]);
+                    let nt_idx = BigEndian::read_u16(&self.clz_read[self.current + 2..]);
+                    self.current += 4;
+                    CpEntry::InvokeDynamic(InvokeDynamicRef::new(bsm_idx, nt_idx))
+                }
                 _ => panic!("Unsupported Constant Pool type {} at {} of {}", tag, self.current, self.filename),
             };
             self.cp_entries[current_cp as usize] = item;
@@ -328,7 +363,11 @@ impl OtKlassParser {
             ((self.clz_read[self.current] as u16) << 8) + self.clz_read[self.current + 1] as u16;
         self.current += 2;
 
-        for idx in 0..f_count {
+        // Offsets count static and instance fields separately, so that an
+        // instance field's offset is its slot in an object
+        let mut statics = 0;
+        let mut instances = 0;
+        for _ in 0..f_count {
             let f_flags = ((self.clz_read[self.current] as u16) << 8)
                 + self.clz_read[self.current + 1] as u16;
             let name_idx = ((self.clz_read[self.current + 2] as u16) << 8)
@@ -354,9 +393,13 @@ impl OtKlassParser {
                 ),
             };
 
+            let counter = if f_flags & ACC_STATIC == ACC_STATIC { &mut statics } else { &mut instances };
+            let offset = *counter;
+            *counter += 1;
+
             let k_name = &self.klass_name();
             let f = OtField::of(
-                idx,
+                offset,
                 k_name.to_string(),
                 f_name.to_string(),
                 f_desc.to_string(),
@@ -460,6 +503,40 @@ impl OtKlassParser {
         }
     }
 
+    // The klass's own attributes - only BootstrapMethods matters to us
+    fn parse_attributes(&mut self) {
+        let attr_count = BigEndian::read_u16(&self.clz_read[self.current..]);
+        self.current += 2;
+        for _ in 0..attr_count {
+            let name_idx = BigEndian::read_u16(&self.clz_read[self.current..]);
+            let attr_len = BigEndian::read_u32(&self.clz_read[self.current + 2..]);
+            self.current += 6;
+            let end_index = self.current + attr_len as usize;
+
+            if self.stringref_from_cp(name_idx) == "BootstrapMethods" {
+                //    u2 num_bootstrap_methods;
+                //    {   u2 bootstrap_method_ref;
+                //        u2 num_bootstrap_arguments;
+                //        u2 bootstrap_arguments[num_bootstrap_arguments];
+                //    } bootstrap_methods[num_bootstrap_methods];
+                let count = BigEndian::read_u16(&self.clz_read[self.current..]);
+                self.current += 2;
+                for _ in 0..count {
+                    let method_idx = BigEndian::read_u16(&self.clz_read[self.current..]);
+                    let arg_count = BigEndian::read_u16(&self.clz_read[self.current + 2..]);
+                    self.current += 4;
+                    let arg_idxs = (0..arg_count as usize)
+                        .map(|i| BigEndian::read_u16(&self.clz_read[self.current + 2 * i..]))
+                        .collect();
+                    self.current += 2 * arg_count as usize;
+                    self.bootstrap_methods.push(BootstrapMethod { method_idx, arg_idxs });
+                }
+            }
+            // Anything else, e.g. SourceFile or InnerClasses, is skipped
+            self.current = end_index;
+        }
+    }
+
     fn parse_method_attribute(&mut self, method: &mut OtMethod) -> CpAttr {
         let name_idx =
             ((self.clz_read[self.current] as u16) << 8) + self.clz_read[self.current + 1] as u16;
@@ -479,7 +556,8 @@ impl OtKlassParser {
             "Code" => {
                 //    u2 max_stack;
                 //    u2 max_locals;
-                //    FIXME: Currently Don't care about stack depth or locals
+                //    FIXME: Currently Don't care about stack depth
+                method.set_max_locals(BigEndian::read_u16(&self.clz_read[self.current + 2..]));
                 self.current += 4;
                 // //    u4 code_length;
                 // //    u1 code[code_length];
@@ -503,19 +581,33 @@ impl OtKlassParser {
                     }
                     Err(e) => panic!("error parsing file: {:?}", e),
                 };
+
+                //    u2 exception_table_length;
+                //    {   u2 start_pc;
+                //        u2 end_pc;
+                //        u2 handler_pc;
+                //        u2 catch_type;
+                //    } exception_table[exception_table_length];
+                let handler_count = BigEndian::read_u16(&self.clz_read[self.current..]);
+                self.current += 2;
+                let mut handlers = Vec::with_capacity(handler_count as usize);
+                for _ in 0..handler_count {
+                    let entry = &self.clz_read[self.current..];
+                    handlers.push(ExceptionHandler {
+                        start_pc: BigEndian::read_u16(entry),
+                        end_pc: BigEndian::read_u16(&entry[2..]),
+                        handler_pc: BigEndian::read_u16(&entry[4..]),
+                        catch_type: BigEndian::read_u16(&entry[6..]),
+                    });
+                    self.current += 8;
+                }
+                method.set_exception_table(handlers);
+                // The Code attribute's own attributes, e.g. LineNumberTable, are skipped
             }
             "Signature" => {
                 dbg!("Encountered signature in bytecode - skipping");
                 ()
             }
-            //    u2 exception_table_length;
-            //    {   u2 start_pc;
-            //        u2 end_pc;
-            //        u2 handler_pc;
-            //        u2 catch_type;
-            //    } exception_table[exception_table_length];
-            //    u2 attributes_count;
-            //    attribute_info attributes[attributes_count];
             "Exceptions" => {
                 // dbg!("Encountered exception handlers in bytecode - skipping");
                 ()
@@ -603,3 +695,34 @@ impl OtKlassParser {
 
     //         return new CPAttr(nameCPIdx);
 }
+
+// The chars of a CONSTANT_Utf8 entry (JVMS 4.4.7). This is UTF-8, except that
+// char 0 takes two bytes, and chars outside the BMP are written as the three
+// byte forms of their two surrogates rather than as four bytes
+pub fn decode_modified_utf8(bytes: &[u8]) -> Result<Vec<u16>, String> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    let cont = |j: usize| match bytes.get(j) {
+        Some(b) if b & 0xc0 == 0x80 => Ok((b & 0x3f) as u16),
+        _ => Err(format!("Malformed modified UTF-8 at byte {}", j)),
+    };
+    while i < bytes.len() {
+        let b = bytes[i] as u16;
+        match b {
+            0x01..=0x7f => {
+                out.push(b);
+                i += 1;
+            }
+            0xc0..=0xdf => {
+                out.push((b & 0x1f) << 6 | cont(i + 1)?);
+                i += 2;
+            }
+            0xe0..=0xef => {
+                out.push((b & 0x0f) << 12 | cont(i + 1)? << 6 | cont(i + 2)?);
+                i += 3;
+            }
+            _ => return Err(format!("Malformed modified UTF-8 at byte {}", i)),
+        }
+    }
+    Ok(out)
+}
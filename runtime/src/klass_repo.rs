@@ -1,18 +1,32 @@
+use std::any::Any;
 use std::fmt;
+use std::fs;
+use std::io;
+use std::panic;
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Instant, SystemTime};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use regex::Regex;
 
-use crate::JvmValue;
+use crate::{JvmResult, JvmValue, Thrown, VmException};
 use crate::InterpLocalVars;
+use crate::constant_pool::{ACC_ABSTRACT, ACC_FINAL, ACC_INTERFACE, ACC_PUBLIC};
+use crate::object::OtObj;
+use crate::heap::Heap;
+use crate::histogram::ClassHistogram;
+use crate::simple_heap::SharedSimpleHeap;
 use crate::otfield::OtField;
 use crate::otmethod::OtMethod;
+use crate::klass_parser::OtKlassParser;
 use crate::otklass::OtKlass;
+use crate::hprof;
+use crate::snapshot;
 
 use ocelotter_util::file_to_bytes;
-use ocelotter_util::ZipFiles;
+use ocelotter_util::{parallel_map, worker_count, ClassSource, JarSource};
 
 //////////// SHARED RUNTIME KLASS REPO
 
@@ -23,9 +37,113 @@ pub enum KlassLoadingStatus {
     Live { klass: OtKlass }
 }
 
+// The bootstrap loader is the null loader - every other loader is identified
+// by the heap id of the java/lang/ClassLoader object that defines its classes
+pub const BOOTSTRAP_LOADER: usize = 0;
+
+// Where the bootstrap classes come from, and where the snapshot of the
+// bootstrapped repo is kept
+pub const BOOTSTRAP_JAR: &str = "resources/lib/classes.jar";
+pub const BOOTSTRAP_SNAPSHOT: &str = "resources/lib/classes.snapshot";
+
+// The release we claim to be when choosing entries from multi-release jars
+pub const JAVA_RELEASE: u16 = 11;
+
+// Distinguishes the temporary files of snapshots being written at the same time
+static SNAPSHOT_WRITES: AtomicUsize = AtomicUsize::new(0);
+
+// A handle on an object that doesn't keep it alive - see new_weak_ref
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WeakRef(usize);
+
+// A class file that could not be loaded, and why
+#[derive(Debug, Clone)]
+pub struct KlassLoadError {
+    pub entry: String,
+    pub message: String,
+}
+
+impl KlassLoadError {
+    // The parser reports problems by panicking, so the message is the panic payload
+    fn of(entry: &str, payload: Box<dyn Any + Send>) -> KlassLoadError {
+        let message = match payload.downcast_ref::<String>() {
+            Some(s) => s.clone(),
+            None => match payload.downcast_ref::<&str>() {
+                Some(s) => s.to_string(),
+                None => "unknown error".to_string(),
+            },
+        };
+        KlassLoadError { entry: entry.to_string(), message }
+    }
+}
+
+impl fmt::Display for KlassLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.entry, self.message)
+    }
+}
+
+// What an INVOKEDYNAMIC call site was linked to by its bootstrap method
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallSite {
+    // Each call makes a new instance of the klass, holding the captured args
+    Lambda { klass_id: usize },
+    // Each call makes a string from the recipe, where \u{1} takes the next arg
+    // and \u{2} the next constant
+    Concat { recipe: Vec<u16>, constants: Vec<Vec<u16>> },
+}
+
+// How the repo calls back into the interpreter, e.g. to run a finalizer
+pub type Interpreter = fn(&mut SharedKlassRepo, &OtMethod, &mut InterpLocalVars) -> JvmResult;
+
 #[derive(Debug)]
 pub struct SharedKlassRepo {
-    klass_lookup: HashMap<String, RefCell<KlassLoadingStatus>>,
+    klass_lookup: HashMap<(usize, String), RefCell<KlassLoadingStatus>>,
+    // Index is the klass id handed out when a klass is defined, id 0 is never used
+    klass_ids: Vec<(usize, String)>,
+    loaders: Vec<usize>,
+    // Pending requirements (JVMS 5.3.4) that a klass name means the same klass
+    // when it is resolved by either of a pair of loaders, lower loader first
+    loader_constraints: RefCell<HashMap<String, HashSet<(usize, usize)>>>,
+    // Klass id -> heap id of its java/lang/Class object, and back again
+    mirrors: HashMap<usize, usize>,
+    mirror_klasses: HashMap<usize, usize>,
+    // The chars of every interned string -> heap id of its java/lang/String
+    interned: HashMap<Vec<u16>, usize>,
+    // Klasses as they were before being redefined, keyed by (loader, name, version)
+    obsolete_klasses: HashMap<(usize, String, u32), OtKlass>,
+    // Every object this VM allocates - object ids only mean anything to the
+    // repo that handed them out
+    heap: Box<dyn Heap>,
+    // Heap references held by each interpreter frame and native call, innermost last
+    frames: Vec<Vec<usize>>,
+    // Objects whose klass overrides finalize(), until a collection finds them
+    // unreachable and moves them onto the finalize queue. They stay roots
+    // from then until their finalizer has run
+    finalizable: HashSet<usize>,
+    finalize_queue: VecDeque<usize>,
+    finalizing: bool,
+    // Weak reference handle -> referent, 0 once the referent has been collected
+    weak_refs: HashMap<usize, usize>,
+    next_weak_ref: usize,
+    // Runs Java code on behalf of the VM, e.g. finalizers - bootstrap sets it
+    interpreter: Option<Interpreter>,
+    // Log every collection to stderr
+    print_gc: bool,
+    // Where to write a heap dump when OutOfMemoryError is thrown, if anywhere
+    heap_dump_path: Option<String>,
+    // Klass id -> the method INVOKEINTERFACE selects for each interface
+    // name:desc, filled in as calls are made. Redefining a klass throws them
+    // all away
+    itables: HashMap<usize, HashMap<String, Result<OtMethod, VmException>>>,
+    // (Klass id, klass version, constant pool index) -> what the INVOKEDYNAMIC
+    // there is linked to. Instructions that share an entry link the same way,
+    // so there is no need to tell them apart
+    call_sites: HashMap<(usize, u32, u16), CallSite>,
+    // Allocated up front, so that throwing OutOfMemoryError needs no heap space
+    out_of_memory_error: usize,
+    // What the native running now wants thrown once it returns
+    pending_exception: Option<VmException>,
 }
 
 impl SharedKlassRepo {
@@ -53,19 +171,68 @@ impl SharedKlassRepo {
         caps.get(1).map_or("".to_string(), |m| m.as_str().to_string())
     }
 
+    // Every class named by a field or method descriptor, including the element
+    // classes of array types - these are what loader constraints are placed on
+    pub fn klass_names_from_desc(desc: &str) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut chars = desc.chars();
+        while let Some(c) = chars.next() {
+            if c == 'L' {
+                let name: String = chars.by_ref().take_while(|c| *c != ';').collect();
+                if !out.contains(&name) {
+                    out.push(name);
+                }
+            }
+        }
+        out
+    }
+
     //////////////////////////////////////////////
 
     pub fn of() -> SharedKlassRepo {
         SharedKlassRepo {
             klass_lookup: HashMap::new(),
+            klass_ids: vec![(BOOTSTRAP_LOADER, "".to_string())],
+            loaders: vec![BOOTSTRAP_LOADER],
+            loader_constraints: RefCell::new(HashMap::new()),
+            mirrors: HashMap::new(),
+            mirror_klasses: HashMap::new(),
+            interned: HashMap::new(),
+            obsolete_klasses: HashMap::new(),
+            heap: Box::new(SharedSimpleHeap::of()),
+            frames: Vec::new(),
+            finalizable: HashSet::new(),
+            finalize_queue: VecDeque::new(),
+            finalizing: false,
+            weak_refs: HashMap::new(),
+            next_weak_ref: 1,
+            interpreter: None,
+            print_gc: false,
+            heap_dump_path: None,
+            itables: HashMap::new(),
+            call_sites: HashMap::new(),
+            out_of_memory_error: 0,
+            pending_exception: None,
         }
     }
 
     pub fn lookup_klass(&self, klass_name: &String) -> OtKlass {
+        self.lookup_klass_in(BOOTSTRAP_LOADER, klass_name)
+    }
+
+    // Resolve a klass name as seen from classes defined by loader - the loader's
+    // own namespace is searched first, and everything else is delegated to the
+    // bootstrap loader
+    pub fn lookup_klass_in(&self, loader: usize, klass_name: &String) -> OtKlass {
         // let s = format!("{}", self);
         // dbg!(s);
+        if loader != BOOTSTRAP_LOADER {
+            if let Some(k) = self.find_defined_klass(loader, klass_name) {
+                return k;
+            }
+        }
 
-        match self.klass_lookup.get(klass_name) {
+        match self.klass_lookup.get(&(BOOTSTRAP_LOADER, klass_name.clone())) {
             Some(cell) => match &*(cell.borrow()) {
                 KlassLoadingStatus::Mentioned {} => panic!("Klass {} is not loaded yet", klass_name),
                 KlassLoadingStatus::Loaded { klass : k } => k.clone(),
@@ -75,43 +242,147 @@ impl SharedKlassRepo {
         }
     }
 
+    // As for lookup_klass_in, but None rather than a panic if there is no such klass
+    pub fn find_klass_in(&self, loader: usize, klass_name: &str) -> Option<OtKlass> {
+        if loader != BOOTSTRAP_LOADER {
+            if let Some(k) = self.find_defined_klass(loader, klass_name) {
+                return Some(k);
+            }
+        }
+        self.find_defined_klass(BOOTSTRAP_LOADER, klass_name)
+    }
+
+    // The klass a method body was loaded with - after a redefinition, methods
+    // that were already running carry on against the old constant pool
+    pub fn lookup_klass_for_method(&self, meth: &OtMethod) -> OtKlass {
+        let k = self.lookup_klass_in(meth.get_loader(), &meth.get_klass_name());
+        if k.get_version() == meth.get_klass_version() {
+            return k;
+        }
+        let key = (k.get_loader(), k.get_name(), meth.get_klass_version());
+        match self.obsolete_klasses.get(&key) {
+            Some(old) => old.clone(),
+            None => panic!("No version {} of klass {} found in repo", key.2, key.1),
+        }
+    }
+
+    pub fn lookup_klass_by_id(&self, klass_id: usize) -> OtKlass {
+        match self.klass_ids.get(klass_id) {
+            Some((loader, klass_name)) if klass_id > 0 => self.lookup_klass_in(*loader, klass_name),
+            _ => panic!("No klass with ID {} found in repo", klass_id),
+        }
+    }
+
+    // Only finds klasses whose defining loader is exactly the one given
+    fn find_defined_klass(&self, loader: usize, klass_name: &str) -> Option<OtKlass> {
+        match self.klass_lookup.get(&(loader, klass_name.to_string())) {
+            Some(cell) => match &*(cell.borrow()) {
+                KlassLoadingStatus::Mentioned {} => None,
+                KlassLoadingStatus::Loaded { klass : k } => Some(k.clone()),
+                KlassLoadingStatus::Live { klass : k } => Some(k.clone())
+            },
+            None => None,
+        }
+    }
+
+    // The defining loader that klass_name resolves to from loader, if it has been loaded
+    fn resolve_defining_loader(&self, loader: usize, klass_name: &str) -> Option<usize> {
+        if loader != BOOTSTRAP_LOADER && self.find_defined_klass(loader, klass_name).is_some() {
+            return Some(loader);
+        }
+        self.find_defined_klass(BOOTSTRAP_LOADER, klass_name).map(|_| BOOTSTRAP_LOADER)
+    }
+
     pub fn add_klass(&mut self, k: &OtKlass) -> () {
+        self.install_klass(BOOTSTRAP_LOADER, k);
+    }
+
+    // Only the bootstrap loader may define java/ klasses, and no loader may
+    // define the same klass twice or break a loader constraint
+    pub fn define_klass(&mut self, loader: usize, k: &OtKlass) -> Result<(), VmException> {
+        let klass_name = k.get_name();
+        if loader != BOOTSTRAP_LOADER {
+            if !self.loaders.contains(&loader) {
+                return Err(VmException::of("java/lang/SecurityException", format!("ClassLoader {} not initialized", loader)));
+            }
+            if klass_name.starts_with("java/") {
+                return Err(VmException::of("java/lang/SecurityException", format!("Prohibited package name {}", klass_name)));
+            }
+            if self.find_defined_klass(loader, &klass_name).is_some() {
+                return Err(VmException::of(
+                    "java/lang/LinkageError",
+                    format!("duplicate class definition of {} in loader {}", klass_name, loader),
+                ));
+            }
+            self.check_pending_constraints(loader, &klass_name)
+                .map_err(|msg| VmException::of("java/lang/LinkageError", msg))?;
+        }
+        self.install_klass(loader, k);
+        Ok(())
+    }
+
+    // As for define_klass, but the bytes of a class file, e.g. from
+    // ClassLoader.defineClass(). Returns the new klass's id
+    pub fn define_klass_from_bytes(&mut self, loader: usize, bytes: Vec<u8>) -> Result<usize, VmException> {
+        let k = panic::catch_unwind(|| {
+            let mut parser = OtKlassParser::of(bytes, format!("<defined by loader {}>", loader));
+            parser.parse();
+            parser.klass()
+        })
+        .map_err(|e| VmException::of("java/lang/ClassFormatError", KlassLoadError::of("defined class", e).message))?;
+        self.define_klass(loader, &k)?;
+        Ok(self.lookup_klass_in(loader, &k.get_name()).get_id())
+    }
+
+    // Makes k a klass of loader without any checks - for the bootstrap loader,
+    // and for klasses the VM makes itself
+    fn install_klass(&mut self, loader: usize, k: &OtKlass) {
         // First check to see if we already have this class and which state it's in
         let klass_name = k.get_name();
-        let upgrade = match self.klass_lookup.get(&klass_name) {
+        let key = (loader, klass_name.clone());
+        let upgrade = match self.klass_lookup.get(&key) {
             Some(value) => match &*(value.borrow()) {
                 KlassLoadingStatus::Mentioned {} => true,
                 KlassLoadingStatus::Loaded { klass : _ } => false, 
                 KlassLoadingStatus::Live { klass : _ } => false 
             },
             None => {
-                let k2: OtKlass = (*k).to_owned();
+                let k2: OtKlass = self.prepare_klass(loader, k);
                 // Scan for every other class the newcomer mentions
                 let klasses_mentioned = k2.get_mentioned_klasses();
 
-                self.klass_lookup.insert(k.get_name().clone(), RefCell::new(KlassLoadingStatus::Loaded{ klass: k2 }));
+                self.klass_lookup.insert(key.clone(), RefCell::new(KlassLoadingStatus::Loaded{ klass: k2 }));
                 // Mention everything this class refers to
-                self.mention(klasses_mentioned);
+                self.mention(loader, klasses_mentioned);
                 false
             }
         };
         if upgrade {
-            let k2 = (*k).to_owned();
+            let k2 = self.prepare_klass(loader, k);
             // Load k into map
-            self.klass_lookup.get(&klass_name).unwrap().replace(KlassLoadingStatus::Loaded{ klass: k2 });
+            self.klass_lookup.get(&key).unwrap().replace(KlassLoadingStatus::Loaded{ klass: k2 });
         }
     }
 
-    fn mention(&mut self, mentions: Vec<String>) -> () {
+    // Stamp a copy of the newcomer with its defining loader and a fresh klass id
+    fn prepare_klass(&mut self, loader: usize, k: &OtKlass) -> OtKlass {
+        let mut k2: OtKlass = (*k).to_owned();
+        k2.set_loader(loader);
+        k2.set_id(self.klass_ids.len());
+        self.klass_ids.push((loader, k2.get_name()));
+        k2
+    }
+
+    fn mention(&mut self, loader: usize, mentions: Vec<String>) {
         // Loop over mentions
         let mut i = 0;
         while i < mentions.len() {
             // Check to see if we have this class already
-            let klass_name = mentions.get(i).unwrap();
-            match self.klass_lookup.get(klass_name) {
+            let key = (loader, mentions.get(i).unwrap().clone());
+            match self.klass_lookup.get(&key) {
                 // If not, add a mention
                 None => {
-                    self.klass_lookup.insert(klass_name.clone(), RefCell::new(KlassLoadingStatus::Mentioned{ }));
+                    self.klass_lookup.insert(key, RefCell::new(KlassLoadingStatus::Mentioned{ }));
                 },
                 Some(value) => (),
             }
@@ -119,7 +390,667 @@ impl SharedKlassRepo {
         }
     }
 
-    fn run_clinit_method(&mut self, klass_name: &String, i_callback: fn(&mut SharedKlassRepo, &OtMethod, &mut InterpLocalVars) -> Option<JvmValue>) {
+    // Replaces the method bodies of a loaded klass, as JVMTI RedefineClasses does.
+    // The schema must not change - see OtKlass::check_same_schema
+    pub fn redefine_klass(&mut self, bytes: Vec<u8>) -> Result<(), String> {
+        self.redefine_klass_in(BOOTSTRAP_LOADER, bytes)
+    }
+
+    pub fn redefine_klass_in(&mut self, loader: usize, bytes: Vec<u8>) -> Result<(), String> {
+        let new_klass = panic::catch_unwind(|| {
+            let mut parser = OtKlassParser::of(bytes, "redefined class".to_string());
+            parser.parse();
+            parser.klass()
+        })
+        .map_err(|e| KlassLoadError::of("redefined class", e).message)?;
+
+        let key = (loader, new_klass.get_name());
+        let cell = match self.klass_lookup.get(&key) {
+            Some(cell) => cell,
+            None => return Err(format!("{} is not loaded by loader {}", key.1, loader)),
+        };
+        let mut status = cell.borrow_mut();
+        let k = match &mut *status {
+            KlassLoadingStatus::Mentioned {} => return Err(format!("{} is not loaded yet", key.1)),
+            KlassLoadingStatus::Loaded { klass : k } => k,
+            KlassLoadingStatus::Live { klass : k } => k,
+        };
+        k.check_same_schema(&new_klass)?;
+
+        self.obsolete_klasses.insert((loader, k.get_name(), k.get_version()), k.clone());
+        k.redefine_from(&new_klass);
+        drop(status);
+        self.itables.clear();
+        Ok(())
+    }
+
+    //////////////////////////////////////////////
+    // Class loaders
+
+    pub fn register_loader(&mut self, loader: usize) {
+        if !self.loaders.contains(&loader) {
+            self.loaders.push(loader);
+        }
+    }
+
+    // Linking a klass requires its superclass and interfaces to be visible from
+    // its defining loader - once that holds the klass can go live, and until
+    // then NoClassDefFoundError is raised
+    pub fn resolve_klass(&mut self, klass_id: usize) -> Result<(), VmException> {
+        let k = self.lookup_klass_by_id(klass_id);
+        if k.get_name() != "java/lang/Object" {
+            let super_name = k.get_super_name();
+            if self.resolve_defining_loader(k.get_loader(), &super_name).is_none() {
+                return Err(VmException::of("java/lang/NoClassDefFoundError", format!("{} (superclass of {})", super_name, k.get_name())));
+            }
+        }
+        for i in k.get_interfaces() {
+            if self.resolve_defining_loader(k.get_loader(), &i).is_none() {
+                return Err(VmException::of("java/lang/NoClassDefFoundError", format!("{} (interface of {})", i, k.get_name())));
+            }
+        }
+        let key = (k.get_loader(), k.get_name());
+        self.klass_lookup.get(&key).unwrap().replace(KlassLoadingStatus::Live{ klass: k });
+        Ok(())
+    }
+
+    // Called when a klass defined by loader1 refers to a member of a klass
+    // defined by loader2 - every class in the member's descriptor must then
+    // resolve to the same klass from both loaders
+    pub fn check_loader_constraints(&self, loader1: usize, loader2: usize, desc: &str) -> Result<(), String> {
+        if loader1 == loader2 {
+            return Ok(());
+        }
+        for klass_name in SharedKlassRepo::klass_names_from_desc(desc) {
+            let l1 = self.resolve_defining_loader(loader1, &klass_name);
+            let l2 = self.resolve_defining_loader(loader2, &klass_name);
+            match (l1, l2) {
+                (Some(d1), Some(d2)) if d1 != d2 => return Err(format!(
+                    "loader constraint violation: {} is defined by loader {} when seen from loader {} but by loader {} when seen from loader {}",
+                    klass_name, d1, loader1, d2, loader2
+                )),
+                (Some(_), Some(_)) => (),
+                // Can't tell yet - remember the constraint and check it on definition
+                _ => {
+                    self.loader_constraints
+                        .borrow_mut()
+                        .entry(klass_name)
+                        .or_default()
+                        .insert((loader1.min(loader2), loader1.max(loader2)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Would defining klass_name in loader break a constraint recorded earlier?
+    fn check_pending_constraints(&self, loader: usize, klass_name: &String) -> Result<(), String> {
+        let constraints = self.loader_constraints.borrow();
+        for (loader1, loader2) in constraints.get(klass_name).into_iter().flatten() {
+            let other = if *loader1 == loader {
+                *loader2
+            } else if *loader2 == loader {
+                *loader1
+            } else {
+                continue;
+            };
+            match self.resolve_defining_loader(other, klass_name) {
+                Some(d) if d != loader => return Err(format!(
+                    "loader constraint violation: defining {} in loader {} conflicts with the version defined by loader {}",
+                    klass_name, loader, d
+                )),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    //////////////////////////////////////////////
+    // Array klasses
+
+    // The name of the klass of arrays of component, which is a class or array name
+    pub fn array_klass_name(component: &str) -> String {
+        if component.starts_with('[') {
+            format!("[{}", component)
+        } else {
+            format!("[L{};", component)
+        }
+    }
+
+    // The klass of the arrays called array_name, as seen from loader. Array klasses
+    // are made on first use, and belong to the defining loader of their element
+    // type (JVMS 5.3.3) - the bootstrap loader for arrays of primitives. An
+    // element klass that hasn't been loaded raises NoClassDefFoundError
+    pub fn array_klass(&mut self, loader: usize, array_name: &str) -> Result<OtKlass, VmException> {
+        let elem = array_name.trim_start_matches('[');
+        let defining_loader = match elem.strip_prefix('L').and_then(|e| e.strip_suffix(';')) {
+            Some(elem_name) => self
+                .resolve_defining_loader(loader, elem_name)
+                .ok_or_else(|| VmException::of("java/lang/NoClassDefFoundError", elem_name.to_string()))?,
+            None => BOOTSTRAP_LOADER,
+        };
+        if let Some(k) = self.find_defined_klass(defining_loader, array_name) {
+            return Ok(k);
+        }
+        let k = OtKlass::of(
+            array_name.to_string(),
+            "java/lang/Object".to_string(),
+            ACC_PUBLIC | ACC_FINAL | ACC_ABSTRACT,
+            &Vec::new(),
+            &Vec::new(),
+            &Vec::new(),
+        );
+        self.install_klass(defining_loader, &k);
+        let klass_id = self.find_defined_klass(defining_loader, array_name).unwrap().get_id();
+        self.resolve_klass(klass_id)?;
+        Ok(self.lookup_klass_by_id(klass_id))
+    }
+
+    // The klass of arrays of a primitive type, e.g. [I for 'I'. These only need
+    // java/lang/Object, so can't fail once bootstrap has loaded it
+    pub fn prim_array_klass(&mut self, elem_type: char) -> OtKlass {
+        self.array_klass(BOOTSTRAP_LOADER, &format!("[{}", elem_type))
+            .unwrap_or_else(|ex| panic!("No klass for primitive array [{}: {:?}", elem_type, ex))
+    }
+
+    pub fn klass_name_of(&self, obj_id: usize) -> String {
+        self.lookup_klass_by_id(self.heap.get_obj(obj_id).get_klassid()).get_name()
+    }
+
+    // Whether a value of klass from can be stored where a to is expected, as for
+    // AASTORE and CHECKCAST - both names are resolved through loader
+    pub fn is_assignable(&self, loader: usize, from: &str, to: &str) -> bool {
+        if from == to || to == "java/lang/Object" {
+            return true;
+        }
+        if let Some(from_elem) = from.strip_prefix('[') {
+            return match to.strip_prefix('[') {
+                Some(to_elem) => match (Self::elem_klass_name(from_elem), Self::elem_klass_name(to_elem)) {
+                    (Some(f), Some(t)) => self.is_assignable(loader, &f, &t),
+                    _ => from_elem == to_elem,
+                },
+                None => to == "java/lang/Cloneable" || to == "java/io/Serializable",
+            };
+        }
+        if to.starts_with('[') {
+            return false;
+        }
+        let k = self.lookup_klass_in(loader, &from.to_string());
+        if k.get_interfaces().iter().any(|i| self.is_assignable(k.get_loader(), i, to)) {
+            return true;
+        }
+        k.get_name() != "java/lang/Object" && self.is_assignable(k.get_loader(), &k.get_super_name(), to)
+    }
+
+    // The runtime check of AASTORE - can value_id go into the reference array array_id?
+    pub fn can_store(&self, array_id: usize, value_id: usize) -> bool {
+        if value_id == 0 {
+            return true;
+        }
+        let klass_id = self.heap.get_obj(array_id).get_klassid();
+        let array_klass = self.lookup_klass_by_id(klass_id);
+        let component = Self::elem_klass_name(&array_klass.get_name()[1..])
+            .unwrap_or_else(|| panic!("{} is not an array of references", array_klass.get_name()));
+        self.is_assignable(array_klass.get_loader(), &self.klass_name_of(value_id), &component)
+    }
+
+    // The class or array name of an array element descriptor, None for a primitive
+    fn elem_klass_name(elem_desc: &str) -> Option<String> {
+        if elem_desc.starts_with('[') {
+            Some(elem_desc.to_string())
+        } else {
+            elem_desc.strip_prefix('L').and_then(|e| e.strip_suffix(';')).map(|e| e.to_string())
+        }
+    }
+
+    // The java/lang/Class object for a klass, created on first use
+    pub fn get_mirror(&mut self, klass_id: usize) -> usize {
+        if let Some(obj_id) = self.mirrors.get(&klass_id) {
+            return *obj_id;
+        }
+        let class_klass = self.lookup_klass(&"java/lang/Class".to_string());
+        let obj_id = self.heap.allocate_obj(&class_klass);
+        self.mirrors.insert(klass_id, obj_id);
+        self.mirror_klasses.insert(obj_id, klass_id);
+        obj_id
+    }
+
+    pub fn klass_id_for_mirror(&self, obj_id: usize) -> usize {
+        match self.mirror_klasses.get(&obj_id) {
+            Some(klass_id) => *klass_id,
+            None => panic!("Object {} is not a java/lang/Class", obj_id),
+        }
+    }
+
+    //////////////////////////////////////////////
+    // Strings
+
+    // Strings in the bundled classes.jar are a char[] value plus the offset and
+    // count of the chars actually in use
+    fn string_field_offset(string_klass: &OtKlass, name_desc: &str) -> usize {
+        let f = string_klass
+            .get_instance_field_by_name_and_desc(&("java/lang/String.".to_owned() + name_desc))
+            .unwrap_or_else(|| panic!("java/lang/String has no field {}", name_desc));
+        string_klass.get_instance_field_offset(f)
+    }
+
+    // Bytes taken up by a new string of len chars, to pass to safepoint
+    pub fn string_size(&self, len: usize) -> u64 {
+        let string_klass = self.lookup_klass(&"java/lang/String".to_string());
+        (OtObj::obj_size(string_klass.get_instance_fields().len()) + OtObj::array_size('C', len as i32)) as u64
+    }
+
+    // A new java/lang/String holding chars. As with the heap's allocate methods,
+    // callers must have passed a safepoint
+    pub fn new_string(&mut self, chars: &[u16]) -> usize {
+        let string_klass = self.lookup_klass(&"java/lang/String".to_string());
+        let char_arr_klass = self.prim_array_klass('C');
+        let value = self.heap.allocate_char_arr_from(&char_arr_klass, chars);
+        let obj_id = self.heap.allocate_obj(&string_klass);
+        let obj = self.heap.get_obj(obj_id);
+        obj.put_field(Self::string_field_offset(&string_klass, "value:[C"), JvmValue::ObjRef(value));
+        obj.put_field(Self::string_field_offset(&string_klass, "offset:I"), JvmValue::Int(0));
+        obj.put_field(Self::string_field_offset(&string_klass, "count:I"), JvmValue::Int(chars.len() as i32));
+        obj_id
+    }
+
+    pub fn new_string_from_rust(&mut self, s: &str) -> usize {
+        self.new_string(&s.encode_utf16().collect::<Vec<u16>>())
+    }
+
+    // A String[] holding a new string for each of values, as passed to main
+    pub fn new_string_array(&mut self, values: &[String]) -> Result<usize, VmException> {
+        let strings = self.array_klass(BOOTSTRAP_LOADER, "[Ljava/lang/String;")?;
+        let arr_id = self.heap.allocate_obj_arr(&strings, values.len() as i32)?;
+        for (i, v) in values.iter().enumerate() {
+            let str_id = self.new_string_from_rust(v);
+            self.heap.array_store(arr_id, i as i32, JvmValue::ObjRef(str_id));
+        }
+        Ok(arr_id)
+    }
+
+    // The one java/lang/String for chars that every string literal and call to
+    // String.intern() shares. Interned strings are never collected
+    pub fn intern_string(&mut self, chars: &[u16]) -> usize {
+        if let Some(obj_id) = self.interned.get(chars) {
+            return *obj_id;
+        }
+        let obj_id = self.new_string(chars);
+        self.interned.insert(chars.to_vec(), obj_id);
+        obj_id
+    }
+
+    pub fn find_interned(&self, chars: &[u16]) -> Option<usize> {
+        self.interned.get(chars).copied()
+    }
+
+    // String.intern() - the string already pooled with the same chars, or
+    // obj_id itself, which is pooled from now on
+    pub fn intern(&mut self, obj_id: usize) -> usize {
+        *self.interned.entry(self.string_chars(obj_id)).or_insert(obj_id)
+    }
+
+    pub fn string_chars(&self, obj_id: usize) -> Vec<u16> {
+        let string_klass = self.lookup_klass(&"java/lang/String".to_string());
+        let obj = self.heap.get_obj(obj_id);
+        let field = |name_desc: &str| obj.get_field_value(Self::string_field_offset(&string_klass, name_desc));
+        let value = field("value:[C").as_objref().expect("String.value is not a reference");
+        let offset = field("offset:I").as_int().expect("String.offset is not an int");
+        let count = field("count:I").as_int().expect("String.count is not an int");
+        self.heap.get_char_arr_region(value, offset, count)
+    }
+
+    // Unpaired surrogates come out as U+FFFD
+    pub fn string_to_rust(&self, obj_id: usize) -> String {
+        String::from_utf16_lossy(&self.string_chars(obj_id))
+    }
+
+    //////////////////////////////////////////////
+    // Garbage collection
+
+    // Frames publish the references they hold, so that a collection can see them
+    pub fn push_frame(&mut self, roots: Vec<usize>) {
+        self.frames.push(roots);
+    }
+
+    pub fn update_frame(&mut self, roots: Vec<usize>) {
+        match self.frames.last_mut() {
+            Some(f) => *f = roots,
+            None => panic!("No frame to update"),
+        }
+    }
+
+    pub fn pop_frame(&mut self) {
+        if self.frames.pop().is_none() {
+            panic!("No frame to pop");
+        }
+    }
+
+    // Everything a collection must keep: static fields of every klass, class
+    // loaders, Class mirrors and whatever the live frames hold
+    pub fn gc_roots(&self) -> Vec<usize> {
+        let mut roots = Vec::new();
+        for status in self.klass_lookup.values() {
+            match &*status.borrow() {
+                KlassLoadingStatus::Loaded { klass } | KlassLoadingStatus::Live { klass } => {
+                    roots.extend(klass.get_static_values().iter().filter_map(|v| v.as_objref()))
+                }
+                KlassLoadingStatus::Mentioned {} => (),
+            }
+        }
+        roots.extend(self.loaders.iter().filter(|l| **l != BOOTSTRAP_LOADER));
+        roots.extend(self.mirror_klasses.keys());
+        roots.extend(self.interned.values());
+        roots.extend(self.finalize_queue.iter());
+        roots.extend(self.frames.iter().flatten());
+        roots.push(self.out_of_memory_error);
+        roots.retain(|id| *id != 0);
+        roots
+    }
+
+    pub fn heap(&self) -> &dyn Heap {
+        self.heap.as_ref()
+    }
+
+    pub fn heap_mut(&mut self) -> &mut dyn Heap {
+        self.heap.as_mut()
+    }
+
+    // Swaps in a different heap implementation - only before anything is
+    // allocated. The new heap takes over the size limits of the old one
+    pub fn use_heap(&mut self, mut heap: Box<dyn Heap>) {
+        if self.heap.live_count() != 0 {
+            panic!("Cannot switch to the {} heap, objects are already allocated", heap.name());
+        }
+        let stats = self.heap.stats();
+        heap.set_limits(stats.committed_bytes, stats.max_bytes);
+        self.heap = heap;
+    }
+
+    // The initial and maximum heap sizes, in bytes, as for -Xms and -Xmx
+    pub fn set_heap_limits(&mut self, initial_bytes: u64, max_bytes: u64) {
+        self.heap.set_limits(initial_bytes, max_bytes);
+    }
+
+    pub fn collect_garbage(&mut self) -> usize {
+        let mut roots = self.gc_roots();
+        if !self.finalizable.is_empty() || !self.weak_refs.is_empty() {
+            let reachable = self.heap.reachable(&roots);
+            // Weak references are cleared before finalizers get the chance to
+            // bring their referents back to life
+            for referent in self.weak_refs.values_mut() {
+                if !reachable.contains(referent) {
+                    *referent = 0;
+                }
+            }
+            let mut found: Vec<usize> = self.finalizable.iter().copied().filter(|id| !reachable.contains(id)).collect();
+            found.sort();
+            for id in found {
+                self.finalizable.remove(&id);
+                self.finalize_queue.push_back(id);
+                roots.push(id);
+            }
+        }
+        let heap = &mut self.heap;
+        let before = heap.live_count();
+        let start = Instant::now();
+        let freed = heap.collect(&roots);
+        if self.print_gc {
+            eprintln!(
+                "[GC ({}) {}->{} objects, {:.3}ms]",
+                heap.name(),
+                before,
+                heap.live_count(),
+                start.elapsed().as_secs_f64() * 1000.0
+            );
+        }
+        freed
+    }
+
+    pub fn set_print_gc(&mut self, print_gc: bool) {
+        self.print_gc = print_gc;
+    }
+
+    // Called before allocating bytes worth of objects - every reference the
+    // caller holds must already be published in its frame. If the objects
+    // would take the heap past its maximum size a collection is forced, and
+    // OutOfMemoryError raised if there is still no room for them. Whatever a
+    // collection finds to finalize is finalized straight away
+    pub fn safepoint(&mut self, bytes: u64) -> Result<(), VmException> {
+        if self.heap.should_collect() || !self.heap.stats().has_room_for(bytes) {
+            self.collect_garbage();
+            self.run_finalization();
+        }
+        if !self.heap.stats().has_room_for(bytes) {
+            return Err(self.out_of_memory("Java heap space"));
+        }
+        Ok(())
+    }
+
+    // Called on each new object, so that a collection notices when it becomes
+    // unreachable if its klass overrides finalize()
+    pub fn register_finalizable(&mut self, obj_id: usize) {
+        let klass = self.lookup_klass_by_id(self.heap.get_obj(obj_id).get_klassid());
+        if self.find_finalizer(&klass).is_some() {
+            self.finalizable.insert(obj_id);
+        }
+    }
+
+    // The finalize() a klass declares or inherits - Object's own does nothing,
+    // so it doesn't count
+    fn find_finalizer(&self, klass: &OtKlass) -> Option<OtMethod> {
+        let mut k = klass.clone();
+        while k.get_name() != "java/lang/Object" {
+            if let Some(m) = k.get_method_by_name_and_desc(&format!("{}.finalize:()V", k.get_name())) {
+                return Some(m.clone());
+            }
+            k = self.find_klass_in(k.get_loader(), &k.get_super_name())?;
+        }
+        None
+    }
+
+    // Runs the finalizers of everything the collector has queued, returning
+    // how many ran. Each object is finalized once - if it is unreachable
+    // again afterwards the next collection simply reclaims it. As with the
+    // JVM, anything a finalizer throws is ignored
+    pub fn run_finalization(&mut self) -> usize {
+        let interpreter = match self.interpreter {
+            Some(i) if !self.finalizing => i,
+            // A finalizer that triggers a collection leaves the objects that
+            // turn up for the pass already running
+            _ => return 0,
+        };
+        self.finalizing = true;
+        let mut ran = 0;
+        while let Some(obj_id) = self.finalize_queue.pop_front() {
+            let klass = self.lookup_klass_by_id(self.heap.get_obj(obj_id).get_klassid());
+            let finalizer = self.find_finalizer(&klass).unwrap();
+            // The object is a root again once it is in the finalizer's frame
+            let mut vars = InterpLocalVars::of(finalizer.get_local_var_size());
+            vars.store(0, JvmValue::ObjRef(obj_id));
+            // Only a Java exception is ignored - a panic is a VM bug, and isn't caught
+            let depth = self.frames.len();
+            if interpreter(self, &finalizer, &mut vars).is_err() {
+                self.frames.truncate(depth);
+            }
+            ran += 1;
+        }
+        self.finalizing = false;
+        ran
+    }
+
+    // A reference to obj_id that doesn't stop it being collected, e.g. for a
+    // cache. Weak references are cleared once nothing else can reach their
+    // referent, and stay cleared even if a finalizer resurrects it
+    pub fn new_weak_ref(&mut self, obj_id: usize) -> WeakRef {
+        let handle = self.next_weak_ref;
+        self.next_weak_ref += 1;
+        self.weak_refs.insert(handle, obj_id);
+        WeakRef(handle)
+    }
+
+    // The referent, or None if it has been collected
+    pub fn weak_ref_get(&self, weak: WeakRef) -> Option<usize> {
+        match self.weak_refs.get(&weak.0) {
+            Some(0) | None => None,
+            Some(obj_id) => Some(*obj_id),
+        }
+    }
+
+    // Handles that are no longer wanted should be dropped, as each one costs
+    // the collector a little work
+    pub fn drop_weak_ref(&mut self, weak: WeakRef) {
+        self.weak_refs.remove(&weak.0);
+    }
+
+    // Every live object, counted up by klass
+    pub fn class_histogram(&self) -> ClassHistogram {
+        ClassHistogram::of(self.heap.live_ids().into_iter().map(|id| (self.klass_name_of(id), self.heap.get_obj(id).shallow_size())))
+    }
+
+    //////////////////////////////////////////////
+    // Heap dumps
+
+    // Every loaded klass, in klass id order
+    pub fn loaded_klasses(&self) -> Vec<OtKlass> {
+        self.klass_ids
+            .iter()
+            .skip(1)
+            .filter_map(|(loader, klass_name)| self.find_defined_klass(*loader, klass_name))
+            .collect()
+    }
+
+    // Writes an HPROF heap dump of everything this repo knows about to path
+    pub fn dump_heap(&self, path: &str) -> io::Result<()> {
+        let klasses: Vec<(OtKlass, usize)> = self
+            .loaded_klasses()
+            .into_iter()
+            .map(|k| {
+                let super_id = match k.get_name().as_str() {
+                    "java/lang/Object" => 0,
+                    _ => self.find_klass_in(k.get_loader(), &k.get_super_name()).map_or(0, |s| s.get_id()),
+                };
+                (k, super_id)
+            })
+            .collect();
+        let roots = hprof::HeapDumpRoots {
+            sticky_klasses: klasses.iter().map(|(k, _)| k).filter(|k| k.get_loader() == BOOTSTRAP_LOADER).map(|k| k.get_id()).collect(),
+            globals: self.loaders.iter().chain(self.mirror_klasses.keys()).chain(self.interned.values()).copied().filter(|id| *id != 0).collect(),
+            frames: self.frames.clone(),
+        };
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        fs::write(path, hprof::encode(&klasses, &roots, self.heap.as_ref(), now))
+    }
+
+    // A path of None turns dumps on OutOfMemoryError off. As with HotSpot, a
+    // directory gets a java_pid<pid>.hprof file inside it
+    pub fn set_heap_dump_path(&mut self, path: Option<String>) {
+        self.heap_dump_path = path;
+    }
+
+    // The OutOfMemoryError to raise, after leaving a heap dump behind if asked to
+    pub fn out_of_memory(&self, detail: &str) -> VmException {
+        if let Some(path) = &self.heap_dump_path {
+            let file = if Path::new(path).is_dir() {
+                Path::new(path).join(format!("java_pid{}.hprof", std::process::id())).to_string_lossy().to_string()
+            } else {
+                path.clone()
+            };
+            eprintln!("Dumping heap to {} ...", file);
+            let start = Instant::now();
+            match self.dump_heap(&file).and_then(|_| fs::metadata(&file)) {
+                Ok(meta) => eprintln!("Heap dump file created [{} bytes in {:.3} secs]", meta.len(), start.elapsed().as_secs_f64()),
+                Err(e) => eprintln!("Unable to create {}: {}", file, e),
+            }
+        }
+        VmException::of("java/lang/OutOfMemoryError", detail.to_string())
+    }
+
+    // Natives can't return a Thrown, so they leave what they raise here for
+    // the interpreter to throw when they return
+    pub fn throw_from_native(&mut self, ex: VmException) {
+        self.pending_exception = Some(ex);
+    }
+
+    pub fn take_pending_exception(&mut self) -> Option<VmException> {
+        self.pending_exception.take()
+    }
+
+    // The slot a Throwable keeps its detail message in, if objects of klass
+    // have one. Objects only have slots for the fields their own klass
+    // declares, so for now only a plain Throwable can carry a message
+    fn detail_message_slot(klass: &OtKlass) -> Option<usize> {
+        klass
+            .get_instance_field_by_name_and_desc(&"java/lang/Throwable.detailMessage:Ljava/lang/String;".to_string())
+            .map(|f| klass.get_instance_field_offset(f))
+    }
+
+    // What Throwable.toString() gives, for reporting an uncaught exception - the
+    // dotted klass name, then the detail message if there is one
+    pub fn describe_throwable(&self, obj_id: usize) -> String {
+        let name = self.klass_name_of(obj_id).replace('/', ".");
+        let klass = self.lookup_klass_by_id(self.heap.get_obj(obj_id).get_klassid());
+        let message = Self::detail_message_slot(&klass)
+            .and_then(|slot| self.heap.get_obj(obj_id).get_field_value(slot).as_objref())
+            .filter(|id| *id != 0);
+        match message {
+            Some(message_id) => format!("{}: {}", name, self.string_to_rust(message_id)),
+            None => name,
+        }
+    }
+
+    // The object for an exception the VM raises, constructed as new K() would
+    // be. Callers must have published their roots. If the constructor throws,
+    // that is what gets thrown instead. OutOfMemoryError, and anything there is
+    // no longer room for, is the one allocated at bootstrap, without a message
+    pub fn new_exception(&mut self, ex: &VmException) -> Thrown {
+        if ex.klass_name == "java/lang/OutOfMemoryError" && self.out_of_memory_error != 0 {
+            return self.preallocated_out_of_memory_error();
+        }
+        let interpreter = self.interpreter.expect("No interpreter to construct exceptions with");
+        let klass = self.lookup_klass(&ex.klass_name.to_string());
+        let init = klass
+            .get_method_by_name_and_desc(&format!("{}.<init>:()V", ex.klass_name))
+            .unwrap_or_else(|| panic!("{} has no no-args constructor", ex.klass_name))
+            .clone();
+        if self.safepoint(OtObj::obj_size(klass.get_instance_fields().len()) as u64).is_err() {
+            return self.preallocated_out_of_memory_error();
+        }
+        let obj_id = self.heap.allocate_obj(&klass);
+        let mut vars = InterpLocalVars::of(init.get_local_var_size());
+        vars.store(0, JvmValue::ObjRef(obj_id));
+        if let Err(thrown) = interpreter(self, &init, &mut vars) {
+            return thrown;
+        }
+        if let Some(slot) = Self::detail_message_slot(&klass) {
+            // Only the exception's own frame kept it alive until now
+            self.push_frame(vec![obj_id]);
+            let room = self.safepoint(self.string_size(ex.message.encode_utf16().count()));
+            self.pop_frame();
+            if room.is_err() {
+                return self.preallocated_out_of_memory_error();
+            }
+            let message = self.new_string_from_rust(&ex.message);
+            self.heap.get_obj(obj_id).put_field(slot, JvmValue::ObjRef(message));
+        }
+        Thrown(obj_id)
+    }
+
+    fn preallocated_out_of_memory_error(&self) -> Thrown {
+        if self.out_of_memory_error == 0 {
+            panic!("java/lang/OutOfMemoryError before bootstrap finished");
+        }
+        Thrown(self.out_of_memory_error)
+    }
+
+    fn preallocate_out_of_memory_error(&mut self) {
+        let Thrown(obj_id) = self.new_exception(&VmException::of("java/lang/OutOfMemoryError", String::new()));
+        self.out_of_memory_error = obj_id;
+    }
+
+    //////////////////////////////////////////////
+
+    fn run_clinit_method(&mut self, klass_name: &String, i_callback: Interpreter) {
         let m_str = klass_name.to_owned() + ".<clinit>:()V";
         let k = self.lookup_klass(klass_name);
         let clinit = match k.get_method_by_name_and_desc(&m_str) {
@@ -127,18 +1058,30 @@ impl SharedKlassRepo {
             // FIXME Make this a clean exit
             None => panic!("Error: Clinit method not found {}", klass_name),
         };
-        // FIXME Parameter passing
-        let mut vars = InterpLocalVars::of(5);
-        i_callback(self, &clinit, &mut vars);
+        let mut vars = InterpLocalVars::of(clinit.get_local_var_size());
+        if let Err(Thrown(ex_id)) = i_callback(self, &clinit, &mut vars) {
+            panic!("java/lang/ExceptionInInitializerError: {} in {}", self.describe_throwable(ex_id), clinit);
+        }
     }
 
-    fn install_native_method(&mut self, klass_name: &String, name_desc: &String,
-        n_code: fn(&InterpLocalVars) -> Option<JvmValue> ) -> () {
+    // symbol is the name of the native's function in native_methods
+    fn install_native_method(&mut self, klass_name: &String, name_desc: &String, symbol: &str) {
         let k = self.lookup_klass(klass_name);
         let fq_name = klass_name.to_owned() +"."+ &name_desc;
 
-        k.set_native_method(fq_name, n_code);
-        self.klass_lookup.get(klass_name).unwrap().replace(KlassLoadingStatus::Live{ klass: k });
+        k.set_native_method(fq_name, symbol);
+        self.klass_lookup.get(&(BOOTSTRAP_LOADER, klass_name.clone())).unwrap().replace(KlassLoadingStatus::Live{ klass: k });
+    }
+
+    // As for install_native_method, but in place of the method's bytecode
+    fn install_intrinsic_method(&mut self, klass_name: &str, name_desc: &str, symbol: &str) {
+        let k = self.lookup_klass(&klass_name.to_string());
+        let fq_name = klass_name.to_owned() + "." + name_desc;
+        match k.get_method_by_name_and_desc(&fq_name) {
+            Some(m) => m.set_intrinsic_code(symbol),
+            None => panic!("No method {} to install intrinsic {} in", fq_name, symbol),
+        }
+        self.klass_lookup.get(&(BOOTSTRAP_LOADER, klass_name.to_string())).unwrap().replace(KlassLoadingStatus::Live{ klass: k });
     }
 
 //    fn double_mapper_factory(tfm: fn(f64) -> f64) -> fn(&InterpLocalVars) -> Option<JvmValue> {
@@ -152,95 +1095,138 @@ impl SharedKlassRepo {
 //        }
 //    }
 
+    // Adds every class from a jar, jmod or exploded module directory to the bootstrap
+    // loader. Entries are parsed across the worker pool but added in source order,
+    // and any that can't be read or fail to parse are skipped and handed back rather
+    // than aborting.
+    pub fn add_class_source(&mut self, source: &dyn ClassSource) -> Vec<KlassLoadError> {
+        let entries = source.class_entries();
+        let parsed = parallel_map(worker_count(), &entries, || (), |_, (name, bytes)| {
+            let bytes = bytes.as_ref().map_err(|e| KlassLoadError { entry: name.clone(), message: e.clone() })?;
+            panic::catch_unwind(|| {
+                let mut parser = OtKlassParser::of(bytes.clone(), name.clone());
+                parser.parse();
+                parser.klass()
+            })
+            .map_err(|e| KlassLoadError::of(name, e))
+        });
+
+        let mut errors = Vec::new();
+        for result in parsed {
+            match result {
+                Ok(k) => self.add_klass(&k),
+                Err(e) => errors.push(e),
+            }
+        }
+        errors
+    }
+
+    // For a multi-release jar the entries that are picked are those for the given release
+    pub fn add_jar(&mut self, jar_name: &str, release: u16) -> Vec<KlassLoadError> {
+        self.add_class_source(&JarSource::of(jar_name, release))
+    }
+
     // This reads in classes.jar and adds each class one by one before fixing up
     // the bits of native code that we have working
     //
     // An interpreter callback, i_callback is needed to run the static initializers
-    pub fn bootstrap(&mut self, i_callback: fn(&mut SharedKlassRepo, &OtMethod, &mut InterpLocalVars) -> Option<JvmValue>) -> () {
-        let file = "resources/lib/classes.jar";
-        ZipFiles::new(file)
-        .into_iter()
-        .filter(|f| match f {
-            Ok((name, _)) if name.ends_with(".class") => true,
-            _ => false,
-        })
-        .for_each(|z| {
-            if let Ok((name, bytes)) = z {
-                let mut parser = crate::klass_parser::OtKlassParser::of(bytes, name);
-                parser.parse();
-                self.add_klass(&parser.klass());
-            }
-        });
+    pub fn bootstrap(&mut self, i_callback: Interpreter) {
+        self.interpreter = Some(i_callback);
+        for e in self.add_jar(BOOTSTRAP_JAR, JAVA_RELEASE) {
+            eprintln!("Warning: skipped bootstrap class {}", e);
+        }
 
-//        self.install_native_method(&"java/lang/Object".to_string(), &"getClass:()Ljava/lang/Class;".to_string(), crate::native_methods::java_lang_Object__getClass);
-        self.install_native_method(&"java/lang/Object".to_string(), &"hashCode:()I".to_string(), crate::native_methods::java_lang_Object__hashcode);
-//        self.install_native_method(&"java/lang/Object".to_string(), &"clone:()Ljava/lang/Object;".to_string(), crate::native_methods::java_lang_Object__clone);
-        self.install_native_method(&"java/lang/Object".to_string(), &"notify:()V".to_string(), crate::native_methods::java_lang_Object__notify);
-        self.install_native_method(&"java/lang/Object".to_string(), &"notifyAll:()V".to_string(), crate::native_methods::java_lang_Object__notifyAll);
-        self.install_native_method(&"java/lang/Object".to_string(), &"wait:(J)V".to_string(), crate::native_methods::java_lang_Object__wait);
+//        self.install_native_method(&"java/lang/Object".to_string(), &"getClass:()Ljava/lang/Class;".to_string(), "java_lang_Object__getClass");
+        self.install_native_method(&"java/lang/Object".to_string(), &"hashCode:()I".to_string(), "java_lang_Object__hashcode");
+//        self.install_native_method(&"java/lang/Object".to_string(), &"clone:()Ljava/lang/Object;".to_string(), "java_lang_Object__clone");
+        self.install_native_method(&"java/lang/Object".to_string(), &"notify:()V".to_string(), "java_lang_Object__notify");
+        self.install_native_method(&"java/lang/Object".to_string(), &"notifyAll:()V".to_string(), "java_lang_Object__notifyAll");
+        self.install_native_method(&"java/lang/Object".to_string(), &"wait:(J)V".to_string(), "java_lang_Object__wait");
 
 
 //        public static final native java.lang.Class forName(java.lang.String) throws java.lang.ClassNotFoundException;
 //        public final native java.lang.Object newInstance() throws java.lang.InstantiationException, java.lang.IllegalAccessException;
 
-        self.install_native_method(&"java/lang/Class".to_string(), &"getName:()Ljava/lang/String;".to_string(), crate::native_methods::java_lang_Class__getName);
+        self.install_native_method(&"java/lang/Class".to_string(), &"getName:()Ljava/lang/String;".to_string(), "java_lang_Class__getName");
 //        public final native java.lang.String getName();
 //        public final native java.lang.Class getSuperclass();
 //        public final native java.lang.Class[] getInterfaces();
-//        public final native java.lang.ClassLoader getClassLoader();
+        self.install_native_method(&"java/lang/Class".to_string(), &"getClassLoader:()Ljava/lang/ClassLoader;".to_string(), "java_lang_Class__getClassLoader");
 //        public final native boolean isInterface();
 
-        self.install_native_method(&"java/lang/Compiler".to_string(), &"compileClass:(Ljava/lang/Class;)Z".to_string(), crate::native_methods::java_lang_Compiler__compileClass);
-        self.install_native_method(&"java/lang/Compiler".to_string(), &"compileClasses:(Ljava/lang/String;)Z".to_string(), crate::native_methods::java_lang_Compiler__compileClasses);
+        self.install_native_method(&"java/lang/ClassLoader".to_string(), &"init:()V".to_string(), "java_lang_ClassLoader__init");
+        self.install_native_method(&"java/lang/ClassLoader".to_string(), &"defineClass0:([BII)Ljava/lang/Class;".to_string(), "java_lang_ClassLoader__defineClass0");
+        self.install_native_method(&"java/lang/ClassLoader".to_string(), &"resolveClass0:(Ljava/lang/Class;)V".to_string(), "java_lang_ClassLoader__resolveClass0");
+        self.install_native_method(&"java/lang/ClassLoader".to_string(), &"findSystemClass0:(Ljava/lang/String;)Ljava/lang/Class;".to_string(), "java_lang_ClassLoader__findSystemClass0");
+
+        self.install_native_method(&"java/lang/Compiler".to_string(), &"compileClass:(Ljava/lang/Class;)Z".to_string(), "java_lang_Compiler__compileClass");
+        self.install_native_method(&"java/lang/Compiler".to_string(), &"compileClasses:(Ljava/lang/String;)Z".to_string(), "java_lang_Compiler__compileClasses");
 //        public static final native java.lang.Object command(java.lang.Object);
-        self.install_native_method(&"java/lang/Compiler".to_string(), &"enable:()V".to_string(), crate::native_methods::java_lang_Compiler__enable);
-        self.install_native_method(&"java/lang/Compiler".to_string(), &"disable:()V".to_string(), crate::native_methods::java_lang_Compiler__disable);
+        self.install_native_method(&"java/lang/Compiler".to_string(), &"enable:()V".to_string(), "java_lang_Compiler__enable");
+        self.install_native_method(&"java/lang/Compiler".to_string(), &"disable:()V".to_string(), "java_lang_Compiler__disable");
         
-        self.install_native_method(&"java/lang/Runtime".to_string(), &"freeMemory:()J".to_string(), crate::native_methods::java_lang_Runtime__freeMemory);
-        self.install_native_method(&"java/lang/Runtime".to_string(), &"totalMemory:()J".to_string(), crate::native_methods::java_lang_Runtime__totalMemory);
-        self.install_native_method(&"java/lang/Runtime".to_string(), &"gc:()V".to_string(), crate::native_methods::java_lang_Runtime__gc);
-        self.install_native_method(&"java/lang/Runtime".to_string(), &"runFinalization:()V".to_string(), crate::native_methods::java_lang_Runtime__runFinalization);
-        self.install_native_method(&"java/lang/Runtime".to_string(), &"traceInstructions:(Z)V".to_string(), crate::native_methods::java_lang_Runtime__traceInstructions);
-        self.install_native_method(&"java/lang/Runtime".to_string(), &"traceMethodCalls:(Z)V".to_string(), crate::native_methods::java_lang_Runtime__traceMethodCalls);
+        self.install_native_method(&"java/lang/Runtime".to_string(), &"freeMemory:()J".to_string(), "java_lang_Runtime__freeMemory");
+        self.install_native_method(&"java/lang/Runtime".to_string(), &"totalMemory:()J".to_string(), "java_lang_Runtime__totalMemory");
+        self.install_native_method(&"java/lang/Runtime".to_string(), &"gc:()V".to_string(), "java_lang_Runtime__gc");
+        self.install_native_method(&"java/lang/Runtime".to_string(), &"runFinalization:()V".to_string(), "java_lang_Runtime__runFinalization");
+        self.install_native_method(&"java/lang/Runtime".to_string(), &"traceInstructions:(Z)V".to_string(), "java_lang_Runtime__traceInstructions");
+        self.install_native_method(&"java/lang/Runtime".to_string(), &"traceMethodCalls:(Z)V".to_string(), "java_lang_Runtime__traceMethodCalls");
+
+        // The bundled String.intern() keeps its own Hashtable - in later class
+        // libraries it is native, and shares the pool that literals go into
+        let intern = "java/lang/String.intern:()Ljava/lang/String;".to_string();
+        if self.lookup_klass(&"java/lang/String".to_string()).get_method_by_name_and_desc(&intern).is_some_and(|m| m.is_native()) {
+            self.install_native_method(&"java/lang/String".to_string(), &"intern:()Ljava/lang/String;".to_string(), "java_lang_String__intern");
+        }
+
+        self.install_intrinsic_method("java/lang/String", "hashCode:()I", "java_lang_String__hashCode");
+        self.install_intrinsic_method("java/lang/String", "equals:(Ljava/lang/Object;)Z", "java_lang_String__equals");
 
-        self.install_native_method(&"java/lang/System".to_string(), &"currentTimeMillis:()J".to_string(), crate::native_methods::java_lang_System__currentTimeMillis);
-        self.install_native_method(&"java/lang/System".to_string(), &"arraycopy:(Ljava/lang/Object;ILjava/lang/Object;II)V".to_string(), crate::native_methods::java_lang_System__arraycopy);
+        self.install_native_method(&"java/lang/Throwable".to_string(), &"fillInStackTrace:()Ljava/lang/Throwable;".to_string(), "java_lang_Throwable__fillInStackTrace");
+
+        self.install_native_method(&"java/lang/System".to_string(), &"currentTimeMillis:()J".to_string(), "java_lang_System__currentTimeMillis");
+        self.install_native_method(&"java/lang/System".to_string(), &"arraycopy:(Ljava/lang/Object;ILjava/lang/Object;II)V".to_string(), "java_lang_System__arraycopy");
+        // Only later class libraries than the bundled classes.jar have this one
+        let identity_hash = "java/lang/System.identityHashCode:(Ljava/lang/Object;)I".to_string();
+        if self.lookup_klass(&"java/lang/System".to_string()).get_method_by_name_and_desc(&identity_hash).is_some() {
+            self.install_native_method(&"java/lang/System".to_string(), &"identityHashCode:(Ljava/lang/Object;)I".to_string(), "java_lang_System__identityHashCode");
+        }
 
         // Load j.l.Math native methods
 //        let sin_f = SharedKlassRepo::double_mapper_factory(|i: f64| -> f64 { i.sin() });
 //        self.install_native_method(&"java/lang/Math".to_string(), &"sin:(D)D".to_string(), sin_f);
-        self.install_native_method(&"java/lang/Math".to_string(), &"sin:(D)D".to_string(), crate::native_methods::java_lang_Math__sin);
-        self.install_native_method(&"java/lang/Math".to_string(), &"cos:(D)D".to_string(), crate::native_methods::java_lang_Math__cos);
-        self.install_native_method(&"java/lang/Math".to_string(), &"tan:(D)D".to_string(), crate::native_methods::java_lang_Math__tan);
-        self.install_native_method(&"java/lang/Math".to_string(), &"asin:(D)D".to_string(), crate::native_methods::java_lang_Math__asin);
-        self.install_native_method(&"java/lang/Math".to_string(), &"acos:(D)D".to_string(), crate::native_methods::java_lang_Math__acos);
-        self.install_native_method(&"java/lang/Math".to_string(), &"atan:(D)D".to_string(), crate::native_methods::java_lang_Math__atan);
-        self.install_native_method(&"java/lang/Math".to_string(), &"exp:(D)D".to_string(), crate::native_methods::java_lang_Math__exp);
-        self.install_native_method(&"java/lang/Math".to_string(), &"log:(D)D".to_string(), crate::native_methods::java_lang_Math__log);
-        self.install_native_method(&"java/lang/Math".to_string(), &"sqrt:(D)D".to_string(), crate::native_methods::java_lang_Math__sqrt);
+        self.install_native_method(&"java/lang/Math".to_string(), &"sin:(D)D".to_string(), "java_lang_Math__sin");
+        self.install_native_method(&"java/lang/Math".to_string(), &"cos:(D)D".to_string(), "java_lang_Math__cos");
+        self.install_native_method(&"java/lang/Math".to_string(), &"tan:(D)D".to_string(), "java_lang_Math__tan");
+        self.install_native_method(&"java/lang/Math".to_string(), &"asin:(D)D".to_string(), "java_lang_Math__asin");
+        self.install_native_method(&"java/lang/Math".to_string(), &"acos:(D)D".to_string(), "java_lang_Math__acos");
+        self.install_native_method(&"java/lang/Math".to_string(), &"atan:(D)D".to_string(), "java_lang_Math__atan");
+        self.install_native_method(&"java/lang/Math".to_string(), &"exp:(D)D".to_string(), "java_lang_Math__exp");
+        self.install_native_method(&"java/lang/Math".to_string(), &"log:(D)D".to_string(), "java_lang_Math__log");
+        self.install_native_method(&"java/lang/Math".to_string(), &"sqrt:(D)D".to_string(), "java_lang_Math__sqrt");
 //public static final native double IEEEremainder(double, double);
-        self.install_native_method(&"java/lang/Math".to_string(), &"ceil:(D)D".to_string(), crate::native_methods::java_lang_Math__ceil);
-        self.install_native_method(&"java/lang/Math".to_string(), &"floor:(D)D".to_string(), crate::native_methods::java_lang_Math__floor);
+        self.install_native_method(&"java/lang/Math".to_string(), &"ceil:(D)D".to_string(), "java_lang_Math__ceil");
+        self.install_native_method(&"java/lang/Math".to_string(), &"floor:(D)D".to_string(), "java_lang_Math__floor");
 //public static final native double rint(double);
-        self.install_native_method(&"java/lang/Math".to_string(), &"atan2:(DD)D".to_string(), crate::native_methods::java_lang_Math__atan2);
-        self.install_native_method(&"java/lang/Math".to_string(), &"pow:(DD)D".to_string(), crate::native_methods::java_lang_Math__pow);
+        self.install_native_method(&"java/lang/Math".to_string(), &"atan2:(DD)D".to_string(), "java_lang_Math__atan2");
+        self.install_native_method(&"java/lang/Math".to_string(), &"pow:(DD)D".to_string(), "java_lang_Math__pow");
 
         // TODO Get enough of java.io.PrintStream working to get System.out.println() to work
 
         // // private native void open(String name) throws IOException;
-        // self.install_native_method(&"java/io/FileOutputStream".to_string(), &"open:(Ljava/lang/String;)V".to_string(), crate::native_methods::java_io/_FileOutputStream__open);
+        // self.install_native_method(&"java/io/FileOutputStream".to_string(), &"open:(Ljava/lang/String;)V".to_string(), "java_io/_FileOutputStream__open");
         
         // // public native void write(int b) throws IOException;
-        // self.install_native_method(&"java/io/FileOutputStream".to_string(), &"write:(I)V".to_string(), crate::native_methods::java_io/_FileOutputStream__write);
+        // self.install_native_method(&"java/io/FileOutputStream".to_string(), &"write:(I)V".to_string(), "java_io/_FileOutputStream__write");
 
         // // private native void writeBytes(byte b[], int off, int len) throws IOException;
-        // self.install_native_method(&"java/io/FileOutputStream".to_string(), &"writeBytes:([BII])V".to_string(), crate::native_methods::java_io/_FileOutputStream__writeBytes);
+        // self.install_native_method(&"java/io/FileOutputStream".to_string(), &"writeBytes:([BII])V".to_string(), "java_io/_FileOutputStream__writeBytes");
 
         // // public native void close() throws IOException;
-        // self.install_native_method(&"java/io/FileOutputStream".to_string(), &"close:()V".to_string(), crate::native_methods::java_io/_FileOutputStream__close);
+        // self.install_native_method(&"java/io/FileOutputStream".to_string(), &"close:()V".to_string(), "java_io/_FileOutputStream__close");
 
         // // private static native FileDescriptor initSystemFD(FileDescriptor fdObj, int desc);
-        self.install_native_method(&"java/io/FileDescriptor".to_string(), &"initSystemFD:(Ljava/io/FileDescriptor;I)Ljava/io/FileDescriptor;".to_string(), crate::native_methods::java_io_FileDescriptor__initSystemFD);
+        self.install_native_method(&"java/io/FileDescriptor".to_string(), &"initSystemFD:(Ljava/io/FileDescriptor;I)Ljava/io/FileDescriptor;".to_string(), "java_io_FileDescriptor__initSystemFD");
 
         // let s = format!("{:?}", self.klass_lookup);
         // dbg!(s);
@@ -248,23 +1234,102 @@ impl SharedKlassRepo {
         // All native methods are installed for the bootstrap classes 
         // Now, we need to run the static initializers in the right order
         self.run_clinit_method(&"java/io/FileDescriptor".to_string(), i_callback);
+        self.preallocate_out_of_memory_error();
 
         // // This requires the file descriptor handling to already exist
         // self.run_clinit_method(&"java/lang/System".to_string(), i_callback);
     }
 
-    pub fn lookup_static_field(&self, klass_name: &String, idx: u16) -> OtField {
-        let current_klass = self.lookup_klass(klass_name);
+    // As for bootstrap, but the repo is restored from the snapshot at path if
+    // one was taken from the current classes.jar - otherwise we bootstrap as
+    // normal and leave a snapshot behind for next time
+    pub fn bootstrap_cached(&mut self, i_callback: Interpreter, path: &str) {
+        let hash = snapshot::jar_hash(BOOTSTRAP_JAR);
+        if self.load_snapshot(path, hash) {
+            self.interpreter = Some(i_callback);
+            self.preallocate_out_of_memory_error();
+            return;
+        }
+        self.bootstrap(i_callback);
+        // Failing to write the snapshot only costs us time on the next start
+        let _ = self.save_snapshot(path, hash);
+    }
+
+    // Only the bootstrap loader's klasses are saved - snapshots are taken
+    // straight after bootstrap, before any other loader exists
+    pub fn save_snapshot(&self, path: &str, jar_hash: u64) -> io::Result<()> {
+        let mut klasses = Vec::new();
+        for (loader, klass_name) in self.klass_ids.iter().skip(1) {
+            if *loader != BOOTSTRAP_LOADER {
+                continue;
+            }
+            match &*(self.klass_lookup.get(&(*loader, klass_name.clone())).unwrap().borrow()) {
+                KlassLoadingStatus::Mentioned {} => (),
+                KlassLoadingStatus::Loaded { klass : k } => klasses.push((k.clone(), false)),
+                KlassLoadingStatus::Live { klass : k } => klasses.push((k.clone(), true)),
+            }
+        }
+        let mut mentioned: Vec<String> = self.klass_lookup.iter()
+            .filter(|((loader, _), cell)| *loader == BOOTSTRAP_LOADER && matches!(&*cell.borrow(), KlassLoadingStatus::Mentioned {}))
+            .map(|((_, klass_name), _)| klass_name.clone())
+            .collect();
+        mentioned.sort();
+
+        // Write to a private file first, so that concurrent runs never see half a snapshot
+        let tmp_path = format!("{}.{}.{}.tmp", path, std::process::id(), SNAPSHOT_WRITES.fetch_add(1, Ordering::SeqCst));
+        let mut interned: Vec<(Vec<u16>, usize)> = self.interned.iter().map(|(chars, obj_id)| (chars.clone(), *obj_id)).collect();
+        interned.sort();
+        fs::write(&tmp_path, snapshot::encode(jar_hash, &klasses, &mentioned, &interned, self.heap.as_ref()))?;
+        fs::rename(&tmp_path, path)
+    }
+
+    // Restores a fresh repo from the snapshot at path - false if there is no
+    // usable snapshot of the jar with this hash
+    pub fn load_snapshot(&mut self, path: &str, jar_hash: u64) -> bool {
+        if self.klass_ids.len() > 1 {
+            panic!("Snapshots can only be loaded into an empty repo");
+        }
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(_) => return false,
+        };
+        let (klasses, mentioned, interned) = match snapshot::decode(jar_hash, &bytes, self.heap.as_mut()) {
+            Some(contents) => contents,
+            None => return false,
+        };
+        for (k, live) in klasses {
+            // Klasses come back in id order, so they get the same ids as before
+            k.set_id(self.klass_ids.len());
+            self.klass_ids.push((BOOTSTRAP_LOADER, k.get_name()));
+            let key = (BOOTSTRAP_LOADER, k.get_name());
+            let status = if live {
+                KlassLoadingStatus::Live { klass: k }
+            } else {
+                KlassLoadingStatus::Loaded { klass: k }
+            };
+            self.klass_lookup.insert(key, RefCell::new(status));
+        }
+        for klass_name in mentioned {
+            self.klass_lookup.insert((BOOTSTRAP_LOADER, klass_name), RefCell::new(KlassLoadingStatus::Mentioned {}));
+        }
+        self.interned.extend(interned);
+        true
+    }
+
+    // idx is an index into the constant pool of current_klass
+    pub fn lookup_static_field(&self, current_klass: &OtKlass, idx: u16) -> Result<OtField, VmException> {
+        let loader = current_klass.get_loader();
 
         // Lookup the Fully-Qualified field name from the CP index
         let fq_name_desc = current_klass.cp_as_string(idx);
         let target_klass_name = &SharedKlassRepo::klass_name_from_fq(&fq_name_desc);
-        let target_klass = self.lookup_klass(&target_klass_name);
+        let target_klass = self.lookup_klass_in(loader, &target_klass_name);
+        self.enforce_loader_constraints(loader, &target_klass, &fq_name_desc)?;
 
         let opt_f = target_klass.get_static_field_by_name_and_desc(&fq_name_desc);
 
         match opt_f {
-            Some(f) => f.clone(),
+            Some(f) => Ok(f.clone()),
             None => panic!(
                 "No static field {} found on klass {} ",
                 fq_name_desc.clone(),
@@ -273,18 +1338,34 @@ impl SharedKlassRepo {
         }
     }
 
-    pub fn lookup_instance_field(&self, klass_name: &String, idx: u16) -> OtField {
-        let current_klass = self.lookup_klass(klass_name);
+    // Statics live in the klass held by the repo, so they must be written there
+    // rather than into a klass handed out by lookup_klass
+    pub fn put_static(&self, loader: usize, f: &OtField, v: JvmValue) {
+        let klass_name = f.get_klass_name();
+        let defining_loader = match self.resolve_defining_loader(loader, &klass_name) {
+            Some(l) => l,
+            None => panic!("Klass {} is not loaded yet", klass_name),
+        };
+        match &*(self.klass_lookup.get(&(defining_loader, klass_name)).unwrap().borrow()) {
+            KlassLoadingStatus::Mentioned {} => panic!("Should be unreachable - static field {} on a mentioned klass", f),
+            KlassLoadingStatus::Loaded { klass : k } => k.put_static(f, v),
+            KlassLoadingStatus::Live { klass : k } => k.put_static(f, v),
+        }
+    }
+
+    pub fn lookup_instance_field(&self, current_klass: &OtKlass, idx: u16) -> Result<OtField, VmException> {
+        let loader = current_klass.get_loader();
 
         // Lookup the Fully-Qualified field name from the CP index
         let fq_name_desc = current_klass.cp_as_string(idx);
         let target_klass_name = &SharedKlassRepo::klass_name_from_fq(&fq_name_desc);
-        let target_klass = self.lookup_klass(&target_klass_name);
+        let target_klass = self.lookup_klass_in(loader, &target_klass_name);
+        self.enforce_loader_constraints(loader, &target_klass, &fq_name_desc)?;
 
         let opt_f = target_klass.get_instance_field_by_name_and_desc(&fq_name_desc);
 
         match opt_f {
-            Some(f) => f.clone(),
+            Some(f) => Ok(f.clone()),
             None => panic!(
                 "No instance field {} found on klass {} ",
                 fq_name_desc.clone(),
@@ -298,27 +1379,169 @@ impl SharedKlassRepo {
         0
     }
 
-    pub fn lookup_method_exact(&self, klass_name: &String, fq_name_desc: String) -> OtMethod {
-        match self.klass_lookup.get(klass_name) {
-            Some(cell) => match &*(cell.borrow()) {
-                KlassLoadingStatus::Mentioned {} => panic!("Klass with ID {} is not loaded yet", klass_name),
-                KlassLoadingStatus::Loaded { klass : k } => k.get_method_by_name_and_desc(&fq_name_desc).unwrap().clone(),
-                KlassLoadingStatus::Live { klass : k } => k.get_method_by_name_and_desc(&fq_name_desc).unwrap().clone(),
-            },
-            None => panic!("No klass with ID {} found in repo", klass_name),
+    // klass_name is resolved from loader, i.e. the defining loader of the caller
+    pub fn lookup_method_exact(&self, loader: usize, klass_name: &String, fq_name_desc: String) -> Result<OtMethod, VmException> {
+        let k = self.lookup_klass_in(loader, klass_name);
+        self.enforce_loader_constraints(loader, &k, &fq_name_desc)?;
+        match k.get_method_by_name_and_desc(&fq_name_desc) {
+            Some(m) => Ok(m.clone()),
+            None => panic!("No method {} found on klass {}", fq_name_desc, klass_name),
         }
     }
 
-    // m_idx is IDX in CP of current class
-    pub fn lookup_method_virtual(&self, klass_name: &String, m_idx: u16) -> OtMethod {
-        match self.klass_lookup.get(klass_name) {
-            Some(cell) => match &*(cell.borrow()) {
-                KlassLoadingStatus::Mentioned {} => panic!("Klass with ID {} is not loaded yet", klass_name),
-                KlassLoadingStatus::Loaded { klass : k } => k.get_method_by_offset_virtual(m_idx),
-                KlassLoadingStatus::Live { klass : k } => k.get_method_by_offset_virtual(m_idx),
+    // Interface method resolution, JVMS 5.4.3.4 - name_desc is looked up on the
+    // interface, then on Object, then on the interface's superinterfaces
+    pub fn resolve_interface_method(&self, loader: usize, iface_name: &String, name_desc: &str) -> Result<OtMethod, VmException> {
+        let iface = self.lookup_klass_in(loader, iface_name);
+        if iface.get_flags() & ACC_INTERFACE == 0 {
+            return Err(VmException::of(
+                "java/lang/IncompatibleClassChangeError",
+                format!("Found class {}, but interface was expected", iface_name),
+            ));
+        }
+        self.enforce_loader_constraints(loader, &iface, name_desc)?;
+        if let Some(m) = Self::declared_method(&iface, name_desc) {
+            return Ok(m);
+        }
+        let object = self.lookup_klass(&"java/lang/Object".to_string());
+        if let Some(m) = Self::declared_method(&object, name_desc).filter(|m| m.is_public() && !m.is_static()) {
+            return Ok(m);
+        }
+        let candidates = self.max_specific_methods(&iface, name_desc);
+        match candidates.iter().find(|m| !m.is_abstract()).or_else(|| candidates.first()) {
+            Some(m) => Ok(m.clone()),
+            None => Err(VmException::of("java/lang/NoSuchMethodError", format!("{}.{}", iface_name, name_desc))),
+        }
+    }
+
+    // Interface method selection, JVMS 5.4.6 - the method INVOKEINTERFACE runs
+    // for a receiver of klass, having resolved resolved
+    pub fn select_interface_method(&mut self, klass: &OtKlass, resolved: &OtMethod) -> Result<OtMethod, VmException> {
+        if resolved.is_private() {
+            return Ok(resolved.clone());
+        }
+        let name_desc = resolved.get_desc();
+        if let Some(selected) = self.itables.get(&klass.get_id()).and_then(|itable| itable.get(&name_desc)) {
+            return selected.clone();
+        }
+        let selected = self.select_for(klass, &name_desc);
+        self.itables.entry(klass.get_id()).or_default().insert(name_desc, selected.clone());
+        selected
+    }
+
+    fn select_for(&self, klass: &OtKlass, name_desc: &str) -> Result<OtMethod, VmException> {
+        // The receiver's klass and then its supers, as for a virtual call
+        let mut k = klass.clone();
+        loop {
+            if let Some(m) = Self::declared_method(&k, name_desc).filter(|m| !m.is_static() && !m.is_private()) {
+                return if m.is_abstract() {
+                    Err(VmException::of("java/lang/AbstractMethodError", m.get_fq_name_desc()))
+                } else if !m.is_public() {
+                    Err(VmException::of("java/lang/IllegalAccessError", m.get_fq_name_desc()))
+                } else {
+                    Ok(m)
+                };
+            }
+            if k.get_name() == "java/lang/Object" {
+                break;
             }
-            None => panic!("No klass with ID {} found in repo", klass_name),
+            k = self.lookup_klass_in(k.get_loader(), &k.get_super_name());
         }
+        // Then a default method, if exactly one is maximally specific
+        let defaults: Vec<OtMethod> = self
+            .max_specific_methods(klass, name_desc)
+            .into_iter()
+            .filter(|m| !m.is_abstract())
+            .collect();
+        match defaults.len() {
+            0 => Err(VmException::of(
+                "java/lang/AbstractMethodError",
+                format!("{}.{}", klass.get_name(), name_desc),
+            )),
+            1 => Ok(defaults[0].clone()),
+            _ => Err(VmException::of(
+                "java/lang/IncompatibleClassChangeError",
+                format!(
+                    "Conflicting default methods: {}",
+                    defaults.iter().map(|m| m.get_fq_name_desc()).collect::<Vec<String>>().join(" ")
+                ),
+            )),
+        }
+    }
+
+    // The maximally-specific superinterface methods of klass, JVMS 5.4.3.3 -
+    // the instance methods called name_desc declared by its superinterfaces
+    // that no other such superinterface overrides
+    fn max_specific_methods(&self, klass: &OtKlass, name_desc: &str) -> Vec<OtMethod> {
+        let declaring: Vec<(OtKlass, OtMethod)> = self
+            .superinterfaces(klass)
+            .into_iter()
+            .filter_map(|i| {
+                let m = Self::declared_method(&i, name_desc).filter(|m| !m.is_static() && !m.is_private())?;
+                Some((i, m))
+            })
+            .collect();
+        declaring
+            .iter()
+            .filter(|(i, _)| {
+                !declaring.iter().any(|(j, _)| {
+                    j.get_name() != i.get_name() && self.is_assignable(j.get_loader(), &j.get_name(), &i.get_name())
+                })
+            })
+            .map(|(_, m)| m.clone())
+            .collect()
+    }
+
+    // Every interface klass implements, directly or through its supers, once each
+    fn superinterfaces(&self, klass: &OtKlass) -> Vec<OtKlass> {
+        let mut out: Vec<OtKlass> = Vec::new();
+        let mut pending: Vec<OtKlass> = vec![klass.clone()];
+        while let Some(k) = pending.pop() {
+            for i in k.get_interfaces() {
+                if !out.iter().any(|o| o.get_name() == i) {
+                    let iface = self.lookup_klass_in(k.get_loader(), &i);
+                    out.push(iface.clone());
+                    pending.push(iface);
+                }
+            }
+            if k.get_name() != "java/lang/Object" && k.get_flags() & ACC_INTERFACE == 0 {
+                pending.push(self.lookup_klass_in(k.get_loader(), &k.get_super_name()));
+            }
+        }
+        out
+    }
+
+    fn declared_method(klass: &OtKlass, name_desc: &str) -> Option<OtMethod> {
+        klass.get_method_by_name_and_desc(&format!("{}.{}", klass.get_name(), name_desc)).cloned()
+    }
+
+    pub fn call_site(&self, klass: &OtKlass, cp_idx: u16) -> Option<CallSite> {
+        self.call_sites.get(&(klass.get_id(), klass.get_version(), cp_idx)).cloned()
+    }
+
+    // A redefined klass gets new call sites, as its constant pool has changed
+    pub fn link_call_site(&mut self, klass: &OtKlass, cp_idx: u16, site: CallSite) {
+        self.call_sites.insert((klass.get_id(), klass.get_version(), cp_idx), site);
+    }
+
+    pub fn call_site_count(&self) -> usize {
+        self.call_sites.len()
+    }
+
+    // m_idx is IDX in CP of current class, and klass_name is resolved through
+    // loader, the defining loader of the current class
+    pub fn lookup_method_virtual(&self, loader: usize, klass_name: &String, m_idx: u16) -> OtMethod {
+        self.lookup_klass_in(loader, klass_name).get_method_by_offset_virtual(m_idx)
+    }
+
+    fn enforce_loader_constraints(&self, loader: usize, target_klass: &OtKlass, fq_name_desc: &str) -> Result<(), VmException> {
+        // The descriptor is whatever follows the ':' of the fully-qualified name
+        let desc = match fq_name_desc.split_once(':') {
+            Some((_, desc)) => desc,
+            None => return Ok(()),
+        };
+        self.check_loader_constraints(loader, target_klass.get_loader(), desc)
+            .map_err(|msg| VmException::of("java/lang/LinkageError", msg))
     }
 }
 
@@ -336,6 +1559,27 @@ impl Clone for SharedKlassRepo {
     fn clone(&self) -> SharedKlassRepo {
         SharedKlassRepo {
             klass_lookup: self.klass_lookup.clone(),
+            klass_ids: self.klass_ids.clone(),
+            loaders: self.loaders.clone(),
+            loader_constraints: self.loader_constraints.clone(),
+            mirrors: self.mirrors.clone(),
+            mirror_klasses: self.mirror_klasses.clone(),
+            interned: self.interned.clone(),
+            obsolete_klasses: self.obsolete_klasses.clone(),
+            heap: self.heap.box_clone(),
+            frames: self.frames.clone(),
+            finalizable: self.finalizable.clone(),
+            finalize_queue: self.finalize_queue.clone(),
+            finalizing: self.finalizing,
+            weak_refs: self.weak_refs.clone(),
+            next_weak_ref: self.next_weak_ref,
+            interpreter: self.interpreter,
+            print_gc: self.print_gc,
+            heap_dump_path: self.heap_dump_path.clone(),
+            itables: self.itables.clone(),
+            call_sites: self.call_sites.clone(),
+            out_of_memory_error: self.out_of_memory_error,
+            pending_exception: self.pending_exception.clone(),
         }
     }
 }
@@ -5,33 +5,33 @@
 #![allow(non_camel_case_types)]
 
 use std::fmt;
-use std::sync::Mutex;
 
 #[macro_use]
 extern crate lazy_static;
 
 pub mod constant_pool;
+pub mod copying_heap;
+pub mod heap;
+pub mod histogram;
+pub mod hprof;
 pub mod interp_stack;
 pub mod klass_parser;
 pub mod klass_repo;
+pub mod mark_word;
 pub mod native_methods;
 pub mod object;
 pub mod otfield;
 pub mod otklass;
 pub mod otmethod;
 pub mod simple_heap;
+pub mod snapshot;
 
-use crate::simple_heap::SharedSimpleHeap;
 use object::OtObj;
 use otfield::OtField;
 use otklass::OtKlass;
 use otmethod::OtMethod;
 use klass_repo::SharedKlassRepo;
 
-lazy_static! {
-    pub static ref HEAP: Mutex<SharedSimpleHeap> = Mutex::new(SharedSimpleHeap::of());
-}
-
 //////////// RUNTIME JVM VALUES
 
 #[derive(Clone, Debug, Copy)]
@@ -120,6 +120,31 @@ impl Default for JvmValue {
     fn default() -> JvmValue { JvmValue::Int(0) }
 }
 
+//////////// METHOD RESULTS
+
+// A method either completes normally, returning a value unless it is void, or
+// abruptly, by throwing an exception
+pub type JvmResult = Result<Option<JvmValue>, Thrown>;
+
+// The heap id of a thrown java/lang/Throwable, on its way to a handler
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thrown(pub usize);
+
+// An exception the VM raises for itself, such as an ArithmeticException for a
+// division by zero. It is made into an object of the named klass and thrown
+// by the interpreter, so Java code can catch it
+#[derive(Clone, Debug, PartialEq)]
+pub struct VmException {
+    pub klass_name: &'static str,
+    pub message: String,
+}
+
+impl VmException {
+    pub fn of(klass_name: &'static str, message: String) -> VmException {
+        VmException { klass_name, message }
+    }
+}
+
 //////////// LOCAL VARS
 
 // Keep this here for now, move to separate file as and when it gets bigger
@@ -129,7 +154,7 @@ pub struct InterpLocalVars {
 }
 
 impl InterpLocalVars {
-    pub fn of(var_count: u8) -> InterpLocalVars {
+    pub fn of(var_count: u16) -> InterpLocalVars {
         let mut out = InterpLocalVars { lvt: Vec::new() };
         for i in 0..var_count {
             out.lvt.push(JvmValue::default());
@@ -137,18 +162,24 @@ impl InterpLocalVars {
         out
     }
 
-    pub fn load(&self, idx: u8) -> JvmValue {
-        self.lvt[idx as usize].clone()
+    // Indices are 16 bits wide, as WIDE allows
+    pub fn load(&self, idx: u16) -> JvmValue {
+        self.lvt[idx as usize]
     }
 
-    pub fn store(&mut self, idx: u8, val: JvmValue) -> () {
+    pub fn store(&mut self, idx: u16, val: JvmValue) {
         self.lvt[idx as usize] = val
     }
 
-    pub fn iinc(&mut self, idx: u8, incr: u8) -> () {
+    pub fn iinc(&mut self, idx: u16, incr: i16) {
         let val = self.lvt[idx as usize].as_int()
             .unwrap_or_else(|| panic!("Non-integer value encountered in IINC of local var {}", idx));
-        self.lvt[idx as usize] = JvmValue::Int(val + 1);
+        self.lvt[idx as usize] = JvmValue::Int(val.wrapping_add(incr as i32));
+    }
+
+    // Heap ids of the objects held in these vars, for the collector
+    pub fn obj_refs(&self) -> Vec<usize> {
+        self.lvt.iter().filter_map(|v| v.as_objref()).filter(|id| *id != 0).collect()
     }
 }
 
@@ -0,0 +1,138 @@
+use std::fmt;
+
+// The header word every object carries. From the low bits up:
+//
+//   0..16   lock count - how many times the monitor has been entered and not
+//           yet exited. There is only one interpreter thread, so the owner is
+//           implicit and a count of 0 means unlocked
+//   16      GC mark bit - only ever set during a collection
+//   17..21  GC age - collections survived, saturating at 15
+//   32..63  identity hash - 0 until first asked for
+//
+// The hash is assigned lazily and then lives in the header, so it travels with
+// the object when a compacting collector moves it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MarkWord(u64);
+
+const LOCK_MASK: u64 = 0xffff;
+const MARK_BIT: u64 = 1 << 16;
+const AGE_SHIFT: u32 = 17;
+const AGE_MASK: u64 = 0xf << AGE_SHIFT;
+const HASH_SHIFT: u32 = 32;
+const HASH_MASK: u64 = 0x7fff_ffff << HASH_SHIFT;
+
+pub const MAX_AGE: u8 = 15;
+
+// Marsaglia xor-shift state for new identity hashes - not derived from the
+// object id, so hashes spread over the buckets of a hash table. Each heap has
+// its own, so one VM's hashes never depend on another's
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HashGenerator(u32);
+
+impl Default for HashGenerator {
+    fn default() -> HashGenerator {
+        HashGenerator(0x2545_f491)
+    }
+}
+
+impl HashGenerator {
+    // A fresh hash, which is never 0 as that marks "no hash yet"
+    pub fn next_hash(&mut self) -> i32 {
+        loop {
+            let mut x = self.0;
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            self.0 = x;
+            let hash = (x & 0x7fff_ffff) as i32;
+            if hash != 0 {
+                return hash;
+            }
+        }
+    }
+}
+
+impl MarkWord {
+    pub fn of(bits: u64) -> MarkWord {
+        MarkWord(bits)
+    }
+
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+
+    // Identity hash
+
+    pub fn hash(&self) -> Option<i32> {
+        match (self.0 & HASH_MASK) >> HASH_SHIFT {
+            0 => None,
+            h => Some(h as i32),
+        }
+    }
+
+    // This word with a hash from hashes assigned if it doesn't have one yet
+    pub fn with_hash(self, hashes: &mut HashGenerator) -> MarkWord {
+        match self.hash() {
+            Some(_) => self,
+            None => MarkWord(self.0 | ((hashes.next_hash() as u64) << HASH_SHIFT)),
+        }
+    }
+
+    // Monitor
+
+    pub fn lock_count(&self) -> u16 {
+        (self.0 & LOCK_MASK) as u16
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.lock_count() != 0
+    }
+
+    // None if the monitor has been entered too many times already
+    pub fn locked(self) -> Option<MarkWord> {
+        match self.lock_count() {
+            c if c as u64 == LOCK_MASK => None,
+            _ => Some(MarkWord(self.0 + 1)),
+        }
+    }
+
+    // None if the monitor isn't held
+    pub fn unlocked(self) -> Option<MarkWord> {
+        match self.lock_count() {
+            0 => None,
+            _ => Some(MarkWord(self.0 - 1)),
+        }
+    }
+
+    // GC
+
+    pub fn is_marked(&self) -> bool {
+        self.0 & MARK_BIT != 0
+    }
+
+    pub fn marked(self) -> MarkWord {
+        MarkWord(self.0 | MARK_BIT)
+    }
+
+    pub fn unmarked(self) -> MarkWord {
+        MarkWord(self.0 & !MARK_BIT)
+    }
+
+    pub fn age(&self) -> u8 {
+        ((self.0 & AGE_MASK) >> AGE_SHIFT) as u8
+    }
+
+    // One more collection survived
+    pub fn aged(self) -> MarkWord {
+        match self.age() {
+            MAX_AGE => self,
+            a => MarkWord((self.0 & !AGE_MASK) | ((a as u64 + 1) << AGE_SHIFT)),
+        }
+    }
+}
+
+impl fmt::Display for MarkWord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#018x}", self.0)
+    }
+}
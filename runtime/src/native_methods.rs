@@ -1,31 +1,38 @@
 use std::time::SystemTime;
 
+use crate::klass_repo::SharedKlassRepo;
+use crate::klass_repo::BOOTSTRAP_LOADER;
+use crate::klass_parser::OtKlassParser;
 use crate::InterpLocalVars;
 use crate::JvmValue;
+use crate::VmException;
 
 ////////////////////////////////////////////
 // java.lang.Object
 
 // getClass()
 
-pub fn java_lang_Object__hashcode(args: &InterpLocalVars) -> Option<JvmValue> {
-    // FIXME Proper hashCode algorithm
-    Some(JvmValue::Int(255))
+pub fn java_lang_Object__hashcode(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    let obj = match args.load(0) {
+        JvmValue::ObjRef(v) => v,
+        x => panic!("Non-object value {} of type {} encountered in Object.hashCode()", x, x.name())
+    };
+    Some(JvmValue::Int(repo.heap_mut().identity_hash(obj)))
 }
 
 // clone()
 
-pub fn java_lang_Object__notify(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Object__notify(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     // NO-OP for now
     None
 }
 
-pub fn java_lang_Object__notifyAll(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Object__notifyAll(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     // NO-OP for now
     None
 }
 
-pub fn java_lang_Object__wait(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Object__wait(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     // NO-OP for now
     None
 }
@@ -34,34 +41,109 @@ pub fn java_lang_Object__wait(args: &InterpLocalVars) -> Option<JvmValue> {
 // java.lang.Class
 
 
-pub fn java_lang_Class__getName(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Class__getName(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let obj = match args.load(0) {
         JvmValue::ObjRef(v) => v,
         x => panic!("Non-object value {} of type {} encountered in Class.getName()", x, x.name())
     };
-    // Lookup object in heap...
+    let klass_name = repo.lookup_klass_by_id(repo.klass_id_for_mirror(obj)).get_name().replace('/', ".");
+    if let Err(ex) = repo.safepoint(repo.string_size(klass_name.encode_utf16().count())) {
+        repo.throw_from_native(ex);
+        return None;
+    }
+    Some(JvmValue::ObjRef(repo.new_string_from_rust(&klass_name)))
+}
+
+pub fn java_lang_Class__getClassLoader(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    let obj = match args.load(0) {
+        JvmValue::ObjRef(v) => v,
+        x => panic!("Non-object value {} of type {} encountered in Class.getClassLoader()", x, x.name())
+    };
+    let klass = repo.lookup_klass_by_id(repo.klass_id_for_mirror(obj));
 
-    // FIXME Currently returns null
-    Some(JvmValue::ObjRef(0))
+    // The bootstrap loader is represented by null
+    Some(JvmValue::ObjRef(klass.get_loader()))
+}
+
+////////////////////////////////////////////
+// java.lang.ClassLoader
+
+pub fn java_lang_ClassLoader__init(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    let loader = match args.load(0) {
+        JvmValue::ObjRef(v) => v,
+        x => panic!("Non-object value {} of type {} encountered in ClassLoader.init()", x, x.name())
+    };
+    repo.register_loader(loader);
+    None
+}
+
+pub fn java_lang_ClassLoader__defineClass0(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    let loader = match args.load(0) {
+        JvmValue::ObjRef(v) => v,
+        x => panic!("Non-object value {} of type {} encountered in ClassLoader.defineClass0()", x, x.name())
+    };
+    let arr = match args.load(1) {
+        JvmValue::ObjRef(v) => v,
+        x => panic!("Non-object value {} of type {} encountered in ClassLoader.defineClass0()", x, x.name())
+    };
+    let offset = args.load(2).as_int().expect("Non-int offset encountered in ClassLoader.defineClass0()");
+    let len = args.load(3).as_int().expect("Non-int length encountered in ClassLoader.defineClass0()");
+
+    let bytes = repo.heap().get_byte_arr_region(arr, offset, len);
+    match repo.define_klass_from_bytes(loader, bytes) {
+        Ok(klass_id) => Some(JvmValue::ObjRef(repo.get_mirror(klass_id))),
+        Err(ex) => {
+            repo.throw_from_native(ex);
+            None
+        }
+    }
+}
+
+pub fn java_lang_ClassLoader__resolveClass0(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    let mirror = match args.load(1) {
+        JvmValue::ObjRef(v) => v,
+        x => panic!("Non-object value {} of type {} encountered in ClassLoader.resolveClass0()", x, x.name())
+    };
+    let klass_id = repo.klass_id_for_mirror(mirror);
+    if let Err(ex) = repo.resolve_klass(klass_id) {
+        repo.throw_from_native(ex);
+    }
+    None
+}
+
+pub fn java_lang_ClassLoader__findSystemClass0(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    let name_obj = match args.load(1) {
+        JvmValue::ObjRef(v) => v,
+        x => panic!("Non-object value {} of type {} encountered in ClassLoader.findSystemClass0()", x, x.name())
+    };
+    // System classes are the ones the bootstrap loader knows about
+    let klass_name = repo.string_to_rust(name_obj).replace('.', "/");
+    match repo.find_klass_in(BOOTSTRAP_LOADER, &klass_name) {
+        Some(klass) => Some(JvmValue::ObjRef(repo.get_mirror(klass.get_id()))),
+        None => {
+            repo.throw_from_native(VmException::of("java/lang/ClassNotFoundException", klass_name));
+            None
+        }
+    }
 }
 
 ////////////////////////////////////////////
 // java.lang.Compiler
 
-pub fn java_lang_Compiler__compileClass(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Compiler__compileClass(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Boolean(true))
 }
 
-pub fn java_lang_Compiler__compileClasses(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Compiler__compileClasses(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Boolean(true))
 }
 
-pub fn java_lang_Compiler__enable(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Compiler__enable(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     // DUMMY
     None
 }
 
-pub fn java_lang_Compiler__disable(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Compiler__disable(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     // DUMMY
     None
 }
@@ -71,48 +153,95 @@ pub fn java_lang_Compiler__disable(args: &InterpLocalVars) -> Option<JvmValue> {
 // java.lang.Runtime
 
 
-pub fn java_lang_Runtime__freeMemory(args: &InterpLocalVars) -> Option<JvmValue> {
-    // DUMMY
-    Some(JvmValue::Long(64 * 1024 * 1024))
+pub fn java_lang_Runtime__freeMemory(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    Some(JvmValue::Long(repo.heap().stats().free_bytes() as i64))
 }
 
-pub fn java_lang_Runtime__totalMemory(args: &InterpLocalVars) -> Option<JvmValue> {
-    // DUMMY
-    Some(JvmValue::Long(64 * 1024 * 1024))
+pub fn java_lang_Runtime__totalMemory(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    Some(JvmValue::Long(repo.heap().stats().committed_bytes as i64))
 }
 
-pub fn java_lang_Runtime__gc(args: &InterpLocalVars) -> Option<JvmValue> {
-    // DUMMY
+pub fn java_lang_Runtime__gc(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    repo.collect_garbage();
     None
 }
 
-pub fn java_lang_Runtime__runFinalization(args: &InterpLocalVars) -> Option<JvmValue> {
-    // DUMMY
+pub fn java_lang_Runtime__runFinalization(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    repo.run_finalization();
     None
 }
 
-pub fn java_lang_Runtime__traceInstructions(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Runtime__traceInstructions(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     // DUMMY
     None
 }
 
-pub fn java_lang_Runtime__traceMethodCalls(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Runtime__traceMethodCalls(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     // DUMMY
     None
 }
 
+////////////////////////////////////////////
+// java.lang.String
+
+pub fn java_lang_String__intern(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    let obj = match args.load(0) {
+        JvmValue::ObjRef(v) => v,
+        x => panic!("Non-object value {} of type {} encountered in String.intern()", x, x.name())
+    };
+    Some(JvmValue::ObjRef(repo.intern(obj)))
+}
+
+// The bundled String predates the hashCode() the JLS specifies, which javac
+// relies on when it compiles a switch on a String
+pub fn java_lang_String__hashCode(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    let obj = match args.load(0) {
+        JvmValue::ObjRef(v) => v,
+        x => panic!("Non-object value {} of type {} encountered in String.hashCode()", x, x.name())
+    };
+    let hash = repo.string_chars(obj).iter().fold(0i32, |h, c| h.wrapping_mul(31).wrapping_add(*c as i32));
+    Some(JvmValue::Int(hash))
+}
+
+// Booleans are ints once they are on the stack
+pub fn java_lang_String__equals(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    let (obj, other) = match (args.load(0), args.load(1)) {
+        (JvmValue::ObjRef(v), JvmValue::ObjRef(o)) => (v, o),
+        (x, y) => panic!("Non-object values {} and {} encountered in String.equals()", x, y)
+    };
+    let equal = obj == other
+        || (other != 0 && repo.klass_name_of(other) == "java/lang/String" && repo.string_chars(obj) == repo.string_chars(other));
+    Some(JvmValue::Int(equal as i32))
+}
+
+////////////////////////////////////////////
+// java.lang.Throwable
+
+// There are no stack traces yet, so there is nothing to fill in
+pub fn java_lang_Throwable__fillInStackTrace(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    Some(args.load(0))
+}
+
 ////////////////////////////////////////////
 // java.lang.System
 
 
-pub fn java_lang_System__currentTimeMillis(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_System__currentTimeMillis(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let millis = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
         .expect("SystemTime before UNIX EPOCH!")
         .as_millis();
     Some(JvmValue::Long(millis as i64))
 }
 
-pub fn java_lang_System__arraycopy(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_System__identityHashCode(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
+    let obj = match args.load(0) {
+        JvmValue::ObjRef(v) => v,
+        x => panic!("Non-object value {} of type {} encountered in System.identityHashCode()", x, x.name())
+    };
+    Some(JvmValue::Int(repo.heap_mut().identity_hash(obj)))
+}
+
+pub fn java_lang_System__arraycopy(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     // NO-OP for now
     None
 }
@@ -121,7 +250,7 @@ pub fn java_lang_System__arraycopy(args: &InterpLocalVars) -> Option<JvmValue> {
 // java.lang.Math simple maths methods
 
 
-pub fn java_lang_Math__sin(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__sin(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let d = match args.load(0) {
         JvmValue::Double(v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.sin", x, x.name())
@@ -129,7 +258,7 @@ pub fn java_lang_Math__sin(args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Double(d.sin()))
 }
 
-pub fn java_lang_Math__cos(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__cos(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let d = match args.load(0) {
         JvmValue::Double(v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.cos", x, x.name())
@@ -137,7 +266,7 @@ pub fn java_lang_Math__cos(args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Double(d.cos()))
 }
 
-pub fn java_lang_Math__tan(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__tan(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let d = match args.load(0) {
         JvmValue::Double(v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.tan", x, x.name())
@@ -145,7 +274,7 @@ pub fn java_lang_Math__tan(args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Double(d.tan()))
 }
 
-pub fn java_lang_Math__asin(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__asin(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let d = match args.load(0) {
         JvmValue::Double(v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.asin", x, x.name())
@@ -153,7 +282,7 @@ pub fn java_lang_Math__asin(args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Double(d.asin()))
 }
 
-pub fn java_lang_Math__acos(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__acos(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let d = match args.load(0) {
         JvmValue::Double (v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.acos", x, x.name())
@@ -162,7 +291,7 @@ pub fn java_lang_Math__acos(args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Double(d.acos()))
 }
 
-pub fn java_lang_Math__atan(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__atan(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let d = match args.load(0) {
         JvmValue::Double (v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.atan", x, x.name())
@@ -171,7 +300,7 @@ pub fn java_lang_Math__atan(args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Double(d.atan()))
 }
 
-pub fn java_lang_Math__exp(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__exp(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let d = match args.load(0) {
         JvmValue::Double (v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.exp", x, x.name())
@@ -180,7 +309,7 @@ pub fn java_lang_Math__exp(args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Double(d.exp()))
 }
 
-pub fn java_lang_Math__log(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__log(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let d = match args.load(0) {
         JvmValue::Double (v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.log", x, x.name())
@@ -189,7 +318,7 @@ pub fn java_lang_Math__log(args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Double(d.ln()))
 }
 
-pub fn java_lang_Math__sqrt(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__sqrt(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let d = match args.load(0) {
         JvmValue::Double (v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.sqrt", x, x.name())
@@ -198,7 +327,7 @@ pub fn java_lang_Math__sqrt(args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Double(d.sqrt()))
 }
 
-pub fn java_lang_Math__ceil(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__ceil(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let d = match args.load(0) {
         JvmValue::Double (v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.ceil", x, x.name())
@@ -207,7 +336,7 @@ pub fn java_lang_Math__ceil(args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Double(d.ceil()))
 }
 
-pub fn java_lang_Math__floor(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__floor(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let d = match args.load(0) {
         JvmValue::Double (v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.floor", x, x.name())
@@ -220,13 +349,14 @@ pub fn java_lang_Math__floor(args: &InterpLocalVars) -> Option<JvmValue> {
 
 //public static final native double rint(double);
 
-pub fn java_lang_Math__atan2(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__atan2(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let base = match args.load(0) {
         JvmValue::Double (v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.atan2", x, x.name())
     };
 
-    let other = match args.load(0) {
+    // The first double takes slots 0 and 1
+    let other = match args.load(2) {
         JvmValue::Double (v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.atan2", x, x.name())
     };
@@ -235,13 +365,13 @@ pub fn java_lang_Math__atan2(args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Double(base.atan2(other)))
 }
 
-pub fn java_lang_Math__pow(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Math__pow(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let base = match args.load(0) {
         JvmValue::Double (v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.pow", x, x.name())
     };
 
-    let raise = match args.load(0) {
+    let raise = match args.load(2) {
         JvmValue::Double (v) => v,
         x => panic!("Non-double value {} of type {} encountered in Math.pow", x, x.name())
     };
@@ -261,11 +391,11 @@ pub fn java_lang_Math__pow(args: &InterpLocalVars) -> Option<JvmValue> {
 ////////////////////////////////////////////
 
 // FIXME System -> Runtime -> Shutdown
-pub fn java_lang_Shutdown__exit(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_lang_Shutdown__exit(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     Some(JvmValue::Int(255))
 }
 
-pub fn java_io_FileDescriptor__initSystemFD(args: &InterpLocalVars) -> Option<JvmValue> {
+pub fn java_io_FileDescriptor__initSystemFD(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
     let obj = args.load(0);
     let fd = args.load(1);
 
@@ -274,10 +404,71 @@ pub fn java_io_FileDescriptor__initSystemFD(args: &InterpLocalVars) -> Option<Jv
     Some(obj)
 }
 
-// pub fn java_lang_System__nanoTime(args: &InterpLocalVars) -> Option<JvmValue> {
+// pub fn java_lang_System__nanoTime(repo: &mut SharedKlassRepo, args: &InterpLocalVars) -> Option<JvmValue> {
 //     let millis = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
 //         Ok(n) => n.as_millis(),
 //         Err(_) => panic!("SystemTime before UNIX EPOCH!"),
 //     };
 //     Some(JvmValue::Long { val: millis as i64})
 // }
+
+////////////////////////////////////////////
+// Symbol table
+
+// Natives are installed by symbol name, which is how a method's native code is
+// found again when the repo is restored from a snapshot
+macro_rules! native_symbols {
+    ($($f:ident),* $(,)?) => {
+        pub fn lookup_native(symbol: &str) -> Option<(&'static str, fn(&mut SharedKlassRepo, &InterpLocalVars) -> Option<JvmValue>)> {
+            match symbol {
+                $(stringify!($f) => Some((stringify!($f), $f)),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+native_symbols!(
+    java_lang_Object__hashcode,
+    java_lang_Object__notify,
+    java_lang_Object__notifyAll,
+    java_lang_Object__wait,
+    java_lang_Class__getName,
+    java_lang_Class__getClassLoader,
+    java_lang_ClassLoader__init,
+    java_lang_ClassLoader__defineClass0,
+    java_lang_ClassLoader__resolveClass0,
+    java_lang_ClassLoader__findSystemClass0,
+    java_lang_Compiler__compileClass,
+    java_lang_Compiler__compileClasses,
+    java_lang_Compiler__enable,
+    java_lang_Compiler__disable,
+    java_lang_Runtime__freeMemory,
+    java_lang_Runtime__totalMemory,
+    java_lang_Runtime__gc,
+    java_lang_Runtime__runFinalization,
+    java_lang_Runtime__traceInstructions,
+    java_lang_Runtime__traceMethodCalls,
+    java_lang_String__intern,
+    java_lang_String__hashCode,
+    java_lang_String__equals,
+    java_lang_Throwable__fillInStackTrace,
+    java_lang_System__currentTimeMillis,
+    java_lang_System__identityHashCode,
+    java_lang_System__arraycopy,
+    java_lang_Math__sin,
+    java_lang_Math__cos,
+    java_lang_Math__tan,
+    java_lang_Math__asin,
+    java_lang_Math__acos,
+    java_lang_Math__atan,
+    java_lang_Math__exp,
+    java_lang_Math__log,
+    java_lang_Math__sqrt,
+    java_lang_Math__ceil,
+    java_lang_Math__floor,
+    java_lang_Math__atan2,
+    java_lang_Math__pow,
+    java_lang_Shutdown__exit,
+    java_io_FileDescriptor__initSystemFD,
+);
@@ -1,57 +1,291 @@
 use std::fmt;
 use std::cell::Cell;
 
+use crate::mark_word::MarkWord;
 use crate::JvmValue;
 use crate::OtField;
 
+// Mark word and klass pointer
+const OBJ_HEADER_SIZE: usize = 16;
+
 // If we need this, we'd better impl it manually
 // #[derive(Debug)]
+#[derive(Clone)]
 pub enum OtObj {
     VmObj {
         id: usize,
-        mark: u64,
+        mark: MarkWord,
         klassid: usize,
         fields: Vec<Cell<JvmValue>>,
     },
     VmArrInt {
         id: usize,
-        mark: u64,
+        mark: MarkWord,
         klassid: usize,
         length: i32,
         elements: Vec<i32>,
     },
     VmArrLong {
         id: usize,
-        mark: u64,
+        mark: MarkWord,
         klassid: usize,
         length: i32,
         elements: Vec<i64>,
     },
+    VmArrByte {
+        id: usize,
+        mark: MarkWord,
+        klassid: usize,
+        length: i32,
+        elements: Vec<i8>,
+    },
+    VmArrChar {
+        id: usize,
+        mark: MarkWord,
+        klassid: usize,
+        length: i32,
+        elements: Vec<u16>,
+    },
+    VmArrBoolean {
+        id: usize,
+        mark: MarkWord,
+        klassid: usize,
+        length: i32,
+        elements: Vec<bool>,
+    },
+    VmArrShort {
+        id: usize,
+        mark: MarkWord,
+        klassid: usize,
+        length: i32,
+        elements: Vec<i16>,
+    },
+    VmArrFloat {
+        id: usize,
+        mark: MarkWord,
+        klassid: usize,
+        length: i32,
+        elements: Vec<f32>,
+    },
+    VmArrDouble {
+        id: usize,
+        mark: MarkWord,
+        klassid: usize,
+        length: i32,
+        elements: Vec<f64>,
+    },
+    // Elements are object ids, 0 for null
+    VmArrObj {
+        id: usize,
+        mark: MarkWord,
+        klassid: usize,
+        length: i32,
+        elements: Vec<usize>,
+    },
 }
 
 impl OtObj {
     pub fn obj_of(klass_id: usize, obj_id: usize, initial: Vec<JvmValue>) -> OtObj {
         OtObj::VmObj {
             id: obj_id,
-            mark: 0u64,
+            mark: MarkWord::default(),
             klassid: klass_id,
             fields: initial.into_iter().map(|s| Cell::new(s)).collect(),
         }
     }
 
-    pub fn int_arr_of(size: i32, obj_id: usize) -> OtObj {
+    pub fn int_arr_of(klass_id: usize, size: i32, obj_id: usize) -> OtObj {
         let sz = size as usize;
         let mut elts = Vec::with_capacity(sz);
         elts.resize(sz, 0);
         OtObj::VmArrInt {
             id: obj_id,
-            mark: 0u64,
-            klassid: 2, // FIXME Need Object in the mix soon...
+            mark: MarkWord::default(),
+            klassid: klass_id,
             length: size,
             elements: elts,
         }
     }
 
+    pub fn byte_arr_of(klass_id: usize, elements: Vec<i8>, obj_id: usize) -> OtObj {
+        OtObj::VmArrByte {
+            id: obj_id,
+            mark: MarkWord::default(),
+            klassid: klass_id,
+            length: elements.len() as i32,
+            elements,
+        }
+    }
+
+    pub fn char_arr_of(klass_id: usize, elements: Vec<u16>, obj_id: usize) -> OtObj {
+        OtObj::VmArrChar {
+            id: obj_id,
+            mark: MarkWord::default(),
+            klassid: klass_id,
+            length: elements.len() as i32,
+            elements,
+        }
+    }
+
+    // A primitive array of the given element type ('Z', 'B', 'C', 'S', 'I', 'J',
+    // 'F' or 'D'), with values converted to that type as by the xASTORE opcodes.
+    // klassid is the array klass, e.g. [I
+    pub fn prim_arr_of(klassid: usize, elem_type: char, values: Vec<JvmValue>, obj_id: usize) -> OtObj {
+        let length = values.len() as i32;
+        match elem_type {
+            'Z' => OtObj::VmArrBoolean {
+                id: obj_id,
+                mark: MarkWord::default(),
+                klassid,
+                length,
+                elements: values.into_iter().map(|v| int_value(v) & 1 != 0).collect(),
+            },
+            'B' => OtObj::byte_arr_of(klassid, values.into_iter().map(|v| int_value(v) as i8).collect(), obj_id),
+            'C' => OtObj::char_arr_of(klassid, values.into_iter().map(|v| int_value(v) as u16).collect(), obj_id),
+            'S' => OtObj::VmArrShort {
+                id: obj_id,
+                mark: MarkWord::default(),
+                klassid,
+                length,
+                elements: values.into_iter().map(|v| int_value(v) as i16).collect(),
+            },
+            'I' => OtObj::VmArrInt {
+                id: obj_id,
+                mark: MarkWord::default(),
+                klassid,
+                length,
+                elements: values.into_iter().map(int_value).collect(),
+            },
+            'J' => OtObj::VmArrLong {
+                id: obj_id,
+                mark: MarkWord::default(),
+                klassid,
+                length,
+                elements: values.into_iter().map(|v| v.as_long().expect("Non-long value stored in long[]")).collect(),
+            },
+            'F' => OtObj::VmArrFloat {
+                id: obj_id,
+                mark: MarkWord::default(),
+                klassid,
+                length,
+                elements: values.into_iter().map(|v| v.as_float().expect("Non-float value stored in float[]")).collect(),
+            },
+            'D' => OtObj::VmArrDouble {
+                id: obj_id,
+                mark: MarkWord::default(),
+                klassid,
+                length,
+                elements: values.into_iter().map(|v| v.as_double().expect("Non-double value stored in double[]")).collect(),
+            },
+            _ => panic!("Illegal primitive array type {}", elem_type),
+        }
+    }
+
+    // An array of references, klass_id being the id of its array klass
+    pub fn obj_arr_of(klass_id: usize, elements: Vec<usize>, obj_id: usize) -> OtObj {
+        OtObj::VmArrObj {
+            id: obj_id,
+            mark: MarkWord::default(),
+            klassid: klass_id,
+            length: elements.len() as i32,
+            elements,
+        }
+    }
+
+    // A new primitive array, with every element zero
+    pub fn new_prim_arr(klass_id: usize, elem_type: char, size: i32, obj_id: usize) -> OtObj {
+        if size < 0 {
+            panic!("java/lang/NegativeArraySizeException: {}", size);
+        }
+        OtObj::prim_arr_of(klass_id, elem_type, vec![JvmValue::default_value(elem_type); size as usize], obj_id)
+    }
+
+    // The descriptor letter of the element type of a primitive array, or 'A'
+    // (as for JvmValue::name) for an array of references
+    pub fn elem_type(&self) -> char {
+        match self {
+            OtObj::VmObj { .. } => panic!("Not an array"),
+            OtObj::VmArrBoolean { .. } => 'Z',
+            OtObj::VmArrByte { .. } => 'B',
+            OtObj::VmArrChar { .. } => 'C',
+            OtObj::VmArrShort { .. } => 'S',
+            OtObj::VmArrInt { .. } => 'I',
+            OtObj::VmArrLong { .. } => 'J',
+            OtObj::VmArrFloat { .. } => 'F',
+            OtObj::VmArrDouble { .. } => 'D',
+            OtObj::VmArrObj { .. } => 'A',
+        }
+    }
+
+    // Bytes this object takes up on its own, not counting anything it refers
+    // to - as for jmap -histo
+    pub fn shallow_size(&self) -> usize {
+        match self {
+            OtObj::VmObj { fields, .. } => OtObj::obj_size(fields.len()),
+            arr => OtObj::array_size(arr.elem_type(), arr.length()),
+        }
+    }
+
+    // A header is a mark word and a klass pointer, and sizes are rounded up to
+    // 8 bytes. Fields don't narrow what is stored in them, so each takes a
+    // full 8 byte slot
+    pub fn obj_size(field_count: usize) -> usize {
+        align(OBJ_HEADER_SIZE + 8 * field_count)
+    }
+
+    // Arrays add an int length to the header. elem_type is as for elem_type()
+    pub fn array_size(elem_type: char, length: i32) -> usize {
+        let elem_size = match elem_type {
+            'Z' | 'B' => 1,
+            'C' | 'S' => 2,
+            'I' | 'F' => 4,
+            _ => 8,
+        };
+        align(OBJ_HEADER_SIZE + 4 + elem_size * length as usize)
+    }
+
+    fn check_index(&self, pos: i32) -> usize {
+        if pos < 0 || pos >= self.length() {
+            panic!("java/lang/ArrayIndexOutOfBoundsException: Index {} out of bounds for length {}", pos, self.length());
+        }
+        pos as usize
+    }
+
+    // An element as the xALOAD opcodes push it - boolean, byte, char and short
+    // elements are widened to int
+    pub fn array_load(&self, pos: i32) -> JvmValue {
+        let p = self.check_index(pos);
+        match self {
+            OtObj::VmObj { .. } => panic!("Not an array"),
+            OtObj::VmArrBoolean { elements, .. } => JvmValue::Int(elements[p] as i32),
+            OtObj::VmArrByte { elements, .. } => JvmValue::Int(elements[p] as i32),
+            OtObj::VmArrChar { elements, .. } => JvmValue::Int(elements[p] as i32),
+            OtObj::VmArrShort { elements, .. } => JvmValue::Int(elements[p] as i32),
+            OtObj::VmArrInt { elements, .. } => JvmValue::Int(elements[p]),
+            OtObj::VmArrLong { elements, .. } => JvmValue::Long(elements[p]),
+            OtObj::VmArrFloat { elements, .. } => JvmValue::Float(elements[p]),
+            OtObj::VmArrDouble { elements, .. } => JvmValue::Double(elements[p]),
+            OtObj::VmArrObj { elements, .. } => JvmValue::ObjRef(elements[p]),
+        }
+    }
+
+    // Replaces one element in place, converted as for prim_arr_of
+    pub fn array_store(&mut self, pos: i32, val: JvmValue) {
+        let p = self.check_index(pos);
+        match self {
+            OtObj::VmObj { .. } => panic!("Not an array"),
+            OtObj::VmArrBoolean { elements, .. } => elements[p] = int_value(val) & 1 != 0,
+            OtObj::VmArrByte { elements, .. } => elements[p] = int_value(val) as i8,
+            OtObj::VmArrChar { elements, .. } => elements[p] = int_value(val) as u16,
+            OtObj::VmArrShort { elements, .. } => elements[p] = int_value(val) as i16,
+            OtObj::VmArrInt { elements, .. } => elements[p] = int_value(val),
+            OtObj::VmArrLong { elements, .. } => elements[p] = val.as_long().expect("Non-long value stored in long[]"),
+            OtObj::VmArrFloat { elements, .. } => elements[p] = val.as_float().expect("Non-float value stored in float[]"),
+            OtObj::VmArrDouble { elements, .. } => elements[p] = val.as_double().expect("Non-double value stored in double[]"),
+            OtObj::VmArrObj { elements, .. } => elements[p] = val.as_objref().expect("Non-reference value stored in reference array"),
+        }
+    }
+
     pub fn put_field(&self, offset : usize, val: JvmValue) -> () {
         let (kid, fields) = match self {
             OtObj::VmObj {
@@ -103,14 +337,27 @@ impl OtObj {
     pub fn get_null() -> OtObj {
         OtObj::VmObj {
             id: 0,
-            mark: 0u64,
+            mark: MarkWord::default(),
             klassid: 0, // klassid of 0 implies null
             fields: Vec::new(),
         }
     }
 
+    // Ids of the objects this one points at - primitive arrays hold no references
+    pub fn references(&self) -> Vec<usize> {
+        match self {
+            OtObj::VmObj { fields, .. } => fields
+                .iter()
+                .filter_map(|f| f.get().as_objref())
+                .filter(|id| *id != 0)
+                .collect(),
+            OtObj::VmArrObj { elements, .. } => elements.iter().copied().filter(|id| *id != 0).collect(),
+            _ => Vec::new(),
+        }
+    }
+
     pub fn is_null(&self) -> bool {
-        if self.get_mark() == 0u64 && self.get_klassid() == 0 {
+        if self.get_mark() == MarkWord::default() && self.get_klassid() == 0 {
             true
         } else {
             false
@@ -139,10 +386,59 @@ impl OtObj {
                 length: _,
                 elements: _,
             } => i,
+            OtObj::VmArrByte {
+                id: i,
+                mark: _,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => i,
+            OtObj::VmArrChar {
+                id: i,
+                mark: _,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => i,
+            OtObj::VmArrBoolean {
+                id: i,
+                mark: _,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => i,
+            OtObj::VmArrShort {
+                id: i,
+                mark: _,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => i,
+            OtObj::VmArrFloat {
+                id: i,
+                mark: _,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => i,
+            OtObj::VmArrDouble {
+                id: i,
+                mark: _,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => i,
+            OtObj::VmArrObj {
+                id: i,
+                mark: _,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => i,
         }
     }
 
-    pub fn get_mark(&self) -> u64 {
+    pub fn get_mark(&self) -> MarkWord {
         match *self {
             OtObj::VmObj {
                 id: _,
@@ -164,6 +460,70 @@ impl OtObj {
                 length: _,
                 elements: _,
             } => m,
+            OtObj::VmArrByte {
+                id: _,
+                mark: m,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => m,
+            OtObj::VmArrChar {
+                id: _,
+                mark: m,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => m,
+            OtObj::VmArrBoolean {
+                id: _,
+                mark: m,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => m,
+            OtObj::VmArrShort {
+                id: _,
+                mark: m,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => m,
+            OtObj::VmArrFloat {
+                id: _,
+                mark: m,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => m,
+            OtObj::VmArrDouble {
+                id: _,
+                mark: m,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => m,
+            OtObj::VmArrObj {
+                id: _,
+                mark: m,
+                klassid: _,
+                length: _,
+                elements: _,
+            } => m,
+        }
+    }
+
+    pub fn set_mark(&mut self, new_mark: MarkWord) {
+        match self {
+            OtObj::VmObj { mark, .. }
+            | OtObj::VmArrInt { mark, .. }
+            | OtObj::VmArrLong { mark, .. }
+            | OtObj::VmArrByte { mark, .. }
+            | OtObj::VmArrChar { mark, .. }
+            | OtObj::VmArrBoolean { mark, .. }
+            | OtObj::VmArrShort { mark, .. }
+            | OtObj::VmArrFloat { mark, .. }
+            | OtObj::VmArrDouble { mark, .. }
+            | OtObj::VmArrObj { mark, .. } => *mark = new_mark,
         }
     }
 
@@ -189,6 +549,55 @@ impl OtObj {
                 length: _,
                 elements: _,
             } => k,
+            OtObj::VmArrByte {
+                id: _,
+                mark: _,
+                klassid: k,
+                length: _,
+                elements: _,
+            } => k,
+            OtObj::VmArrChar {
+                id: _,
+                mark: _,
+                klassid: k,
+                length: _,
+                elements: _,
+            } => k,
+            OtObj::VmArrBoolean {
+                id: _,
+                mark: _,
+                klassid: k,
+                length: _,
+                elements: _,
+            } => k,
+            OtObj::VmArrShort {
+                id: _,
+                mark: _,
+                klassid: k,
+                length: _,
+                elements: _,
+            } => k,
+            OtObj::VmArrFloat {
+                id: _,
+                mark: _,
+                klassid: k,
+                length: _,
+                elements: _,
+            } => k,
+            OtObj::VmArrDouble {
+                id: _,
+                mark: _,
+                klassid: k,
+                length: _,
+                elements: _,
+            } => k,
+            OtObj::VmArrObj {
+                id: _,
+                mark: _,
+                klassid: k,
+                length: _,
+                elements: _,
+            } => k,
         }
     }
 
@@ -214,10 +623,76 @@ impl OtObj {
                 length: l,
                 elements: _,
             } => l,
+            OtObj::VmArrByte {
+                id: _,
+                mark: _,
+                klassid: _,
+                length: l,
+                elements: _,
+            } => l,
+            OtObj::VmArrChar {
+                id: _,
+                mark: _,
+                klassid: _,
+                length: l,
+                elements: _,
+            } => l,
+            OtObj::VmArrBoolean {
+                id: _,
+                mark: _,
+                klassid: _,
+                length: l,
+                elements: _,
+            } => l,
+            OtObj::VmArrShort {
+                id: _,
+                mark: _,
+                klassid: _,
+                length: l,
+                elements: _,
+            } => l,
+            OtObj::VmArrFloat {
+                id: _,
+                mark: _,
+                klassid: _,
+                length: l,
+                elements: _,
+            } => l,
+            OtObj::VmArrDouble {
+                id: _,
+                mark: _,
+                klassid: _,
+                length: l,
+                elements: _,
+            } => l,
+            OtObj::VmArrObj {
+                id: _,
+                mark: _,
+                klassid: _,
+                length: l,
+                elements: _,
+            } => l,
         }
     }
 }
 
+fn align(size: usize) -> usize {
+    (size + 7) & !7
+}
+
+// Values narrower than int can turn up on the eval stack (e.g. after I2B) but
+// are all stored as ints
+fn int_value(v: JvmValue) -> i32 {
+    match v {
+        JvmValue::Boolean(b) => b as i32,
+        JvmValue::Byte(b) => b as i32,
+        JvmValue::Short(s) => s as i32,
+        JvmValue::Char(c) => c as i32,
+        JvmValue::Int(i) => i,
+        _ => panic!("Non-int value {} stored in an int-like array", v),
+    }
+}
+
 impl fmt::Display for OtObj {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
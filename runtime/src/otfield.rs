@@ -52,6 +52,22 @@ impl OtField {
         self.name.clone()
     }
 
+    pub fn get_desc(&self) -> String {
+        self.desc.clone()
+    }
+
+    pub fn get_flags(&self) -> u16 {
+        self.flags
+    }
+
+    pub fn get_name_idx(&self) -> u16 {
+        self.name_idx
+    }
+
+    pub fn get_desc_idx(&self) -> u16 {
+        self.desc_idx
+    }
+
     pub fn is_static(&self) -> bool {
         self.flags & ACC_STATIC == ACC_STATIC
     }
@@ -5,6 +5,7 @@ use std::fmt;
 use crate::constant_pool::*;
 use crate::otfield::OtField;
 use crate::otmethod::OtMethod;
+use crate::klass_repo::SharedKlassRepo;
 use crate::InterpLocalVars;
 use crate::JvmValue;
 
@@ -13,9 +14,15 @@ use crate::JvmValue;
 #[derive(Debug, Clone)]
 pub struct OtKlass {
     id: Cell<usize>,
+    loader: usize,
+    // Bumped each time the klass is redefined
+    version: u32,
     name: String,
     super_name: String,
+    interfaces: Vec<String>,
     flags: u16,
+    // From the BootstrapMethods attribute, for INVOKEDYNAMIC
+    bootstrap_methods: Vec<BootstrapMethod>,
     cp_entries: Vec<CpEntry>,
     methods: Vec<OtMethod>,
     i_fields: Vec<OtField>,
@@ -64,9 +71,13 @@ impl OtKlass {
         // dbg!(f_lookup.clone());
         OtKlass {
             id: Cell::new(0), // This indicates that the class has not yet been loaded into a repo
+            loader: 0,
+            version: 0,
             name: klass_name,
             super_name: super_klass,
+            interfaces: Vec::new(),
             flags,
+            bootstrap_methods: Vec::new(),
             cp_entries: cp_entries.to_vec(),
             methods: methods.to_vec(),
             i_fields: i_fields.to_vec(),
@@ -155,6 +166,72 @@ impl OtKlass {
         self.id.get()
     }
 
+    // Loader identity is part of class identity - 0 is the bootstrap loader,
+    // anything else is the heap id of a Java-defined ClassLoader
+    pub fn get_loader(&self) -> usize {
+        self.loader
+    }
+
+    pub fn set_loader(&mut self, loader: usize) {
+        self.loader = loader;
+        for m in self.methods.iter_mut() {
+            m.set_loader(loader);
+        }
+    }
+
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    // Checks that new_klass could replace this klass without changing its schema,
+    // i.e. the superclass, flags, fields and set of methods are all unchanged
+    pub fn check_same_schema(&self, new_klass: &OtKlass) -> Result<(), String> {
+        let field_schema = |k: &OtKlass| -> Vec<(String, u16)> {
+            k.s_fields.iter().chain(k.i_fields.iter())
+                .map(|f| (f.get_fq_name_desc(), f.get_flags()))
+                .collect()
+        };
+        let method_schema = |k: &OtKlass| -> Vec<(String, u16)> {
+            let mut out: Vec<(String, u16)> = k.methods.iter()
+                .map(|m| (m.get_fq_name_desc(), m.get_flags()))
+                .collect();
+            out.sort();
+            out
+        };
+        if self.name != new_klass.name {
+            Err(format!("class name changed from {} to {}", self.name, new_klass.name))
+        } else if self.super_name != new_klass.super_name || self.flags != new_klass.flags {
+            Err(format!("attempted to change the superclass or modifiers of {}", self.name))
+        } else if self.interfaces != new_klass.interfaces {
+            Err(format!("attempted to change the interfaces of {}", self.name))
+        } else if field_schema(self) != field_schema(new_klass) {
+            Err(format!("attempted to change the fields of {}", self.name))
+        } else if method_schema(self) != method_schema(new_klass) {
+            Err(format!("attempted to add, delete or change the modifiers of a method of {}", self.name))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Takes the constant pool and method bodies of new_klass, which must have the
+    // same schema. Statics, id and loader stay as they are, and natives keep
+    // their native code.
+    pub fn redefine_from(&mut self, new_klass: &OtKlass) {
+        let mut methods = new_klass.methods.clone();
+        for m in methods.iter_mut() {
+            m.set_loader(self.loader);
+            m.set_klass_version(self.version + 1);
+            if let Some(symbol) = self.get_method_by_name_and_desc(&m.get_fq_name_desc()).and_then(|old| old.get_native_symbol()) {
+                m.reinstall_native_code(symbol);
+            }
+        }
+        self.version += 1;
+        self.cp_entries = new_klass.cp_entries.clone();
+        self.bootstrap_methods = new_klass.bootstrap_methods.clone();
+        self.methods = methods;
+        self.m_name_desc_lookup = new_klass.m_name_desc_lookup.clone();
+    }
+
     pub fn get_name(&self) -> String {
         self.name.to_owned()
     }
@@ -163,17 +240,46 @@ impl OtKlass {
         self.super_name.to_owned()
     }
 
+    pub fn get_flags(&self) -> u16 {
+        self.flags
+    }
+
+    pub fn get_cp_entries(&self) -> Vec<CpEntry> {
+        self.cp_entries.clone()
+    }
+
+    // The names of the interfaces the klass directly implements
+    pub fn get_interfaces(&self) -> Vec<String> {
+        self.interfaces.clone()
+    }
+
+    pub fn set_interfaces(&mut self, interfaces: Vec<String>) {
+        self.interfaces = interfaces;
+    }
+
+    pub fn get_bootstrap_methods(&self) -> Vec<BootstrapMethod> {
+        self.bootstrap_methods.clone()
+    }
+
+    pub fn set_bootstrap_methods(&mut self, bootstrap_methods: Vec<BootstrapMethod>) {
+        self.bootstrap_methods = bootstrap_methods;
+    }
+
     pub fn get_methods(&self) -> Vec<OtMethod> {
         self.methods.clone()
     }
 
-    pub fn set_native_method(
-        &self,
-        name_desc: String,
-        n_code: fn(&InterpLocalVars) -> Option<JvmValue>,
-    ) {
+    pub fn get_static_fields(&self) -> Vec<OtField> {
+        self.s_fields.clone()
+    }
+
+    pub fn get_instance_fields(&self) -> Vec<OtField> {
+        self.i_fields.clone()
+    }
+
+    pub fn set_native_method(&self, name_desc: String, symbol: &str) {
         match self.get_method_by_name_and_desc(&name_desc) {
-            Some(m2) => m2.set_native_code(n_code),
+            Some(m2) => m2.set_native_code(symbol),
             None => {
                 panic!("Should be unreachable - trying to store native code in a non-existant method")
             }
@@ -238,6 +344,10 @@ impl OtKlass {
         self.s_field_vals.get(idx).unwrap().set(v);
     }
 
+    pub fn get_static_values(&self) -> Vec<JvmValue> {
+        self.s_field_vals.iter().map(|v| v.get()).collect()
+    }
+
 
     pub fn get_method_by_offset_virtual(&self, m_idx: u16) -> OtMethod {
         // If present, return value at specific offset
@@ -303,6 +413,7 @@ impl OtKlass {
         let cp_entry = self.lookup_cp(cp_idx);
         let name_and_type = match cp_entry {
             CpEntry::MethodRef(mr) => self.lookup_cp(mr.nt_idx),
+            CpEntry::InterfaceMethodRef(imr) => self.lookup_cp(imr.nt_idx),
             _ => panic!(
                 "Attempt to count args of non-method in {} at index {} where {:?}",
                 self.name, cp_idx, self.cp_entries.get(cp_idx as usize)
@@ -330,7 +441,10 @@ impl OtKlass {
             CpEntry::Class(c) => self.cp_as_string(c.0),
             CpEntry::FieldRef(fr) => self.cp_as_string(fr.clz_idx) + "." + &self.cp_as_string(fr.nt_idx),
             CpEntry::MethodRef(mr) => self.cp_as_string(mr.clz_idx) + "." + &self.cp_as_string(mr.nt_idx),
+            CpEntry::InterfaceMethodRef(imr) => self.cp_as_string(imr.clz_idx) + "." + &self.cp_as_string(imr.nt_idx),
             CpEntry::NameAndType(nt) => self.cp_as_string(nt.name_idx) + ":" + &self.cp_as_string(nt.type_idx),
+            CpEntry::MethodHandle(mh) => self.cp_as_string(mh.ref_idx),
+            CpEntry::MethodType(MethodTypeRef(desc_idx)) => self.cp_as_string(desc_idx),
             _ => panic!(
                 "Unimplemented stringify of CP entry found in {} at index {}",
                 self.name, i
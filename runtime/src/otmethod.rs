@@ -2,20 +2,48 @@ use std::cell::Cell;
 use std::fmt;
 
 use crate::constant_pool::CpAttr;
-use crate::constant_pool::ACC_NATIVE;
+use crate::constant_pool::{ACC_ABSTRACT_M, ACC_NATIVE, ACC_PRIVATE, ACC_PUBLIC, ACC_STATIC};
+use crate::klass_repo::SharedKlassRepo;
+use crate::native_methods::lookup_native;
+use crate::otklass::OtKlass;
 use crate::InterpLocalVars;
 use crate::JvmValue;
 
+// An entry in a method's exception table. The pcs are offsets into the code -
+// the handler covers [start_pc, end_pc) - and catch_type is the constant pool
+// index of the klass it catches, or 0 to catch everything, as for finally
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExceptionHandler {
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    pub catch_type: u16,
+}
+
+impl ExceptionHandler {
+    pub fn covers(&self, pc: usize) -> bool {
+        self.start_pc as usize <= pc && pc < self.end_pc as usize
+    }
+}
+
 #[derive(Clone)]
 pub struct OtMethod {
     klass_name: String,
+    loader: usize,
+    // The version of the klass this method body belongs to
+    klass_version: u32,
     flags: u16,
     name: String,
     name_desc: String,
     name_idx: u16,
     desc_idx: u16,
     code: Vec<u8>,
-    native_code: Cell<Option<fn(&InterpLocalVars) -> Option<JvmValue>>>,
+    // From the Code attribute, so 0 for native and abstract methods
+    max_locals: u16,
+    // In the order javac wrote them, which is the order they are tried in
+    exception_table: Vec<ExceptionHandler>,
+    native_code: Cell<Option<fn(&mut SharedKlassRepo, &InterpLocalVars) -> Option<JvmValue>>>,
+    native_symbol: Cell<Option<&'static str>>,
     attrs: Vec<CpAttr>,
 }
 
@@ -31,12 +59,17 @@ impl OtMethod {
         let name_and_desc = name.clone() + ":" + &desc.clone();
         OtMethod {
             klass_name: klass_name.to_string(),
+            loader: 0,
+            klass_version: 0,
             flags,
             name: name.clone(),
             name_desc: name_and_desc,
             attrs: Vec::new(),
             code: Vec::new(),
+            max_locals: 0,
+            exception_table: Vec::new(),
             native_code: Cell::new(None),
+            native_symbol: Cell::new(None),
             // FIXME
             name_idx: desc_idx,
             desc_idx,
@@ -53,10 +86,55 @@ impl OtMethod {
         self.code.clone()
     }
 
+    pub fn set_max_locals(&mut self, max_locals: u16) {
+        self.max_locals = max_locals;
+    }
+
+    pub fn get_max_locals(&self) -> u16 {
+        self.max_locals
+    }
+
+    pub fn set_exception_table(&mut self, handlers: Vec<ExceptionHandler>) {
+        self.exception_table = handlers;
+    }
+
+    pub fn get_exception_table(&self) -> Vec<ExceptionHandler> {
+        self.exception_table.clone()
+    }
+
     pub fn get_klass_name(&self) -> String {
         self.klass_name.clone()
     }
 
+    // The defining loader of the klass that declares this method
+    pub fn get_loader(&self) -> usize {
+        self.loader
+    }
+
+    pub fn set_loader(&mut self, loader: usize) {
+        self.loader = loader;
+    }
+
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn get_name_idx(&self) -> u16 {
+        self.name_idx
+    }
+
+    pub fn get_desc_idx(&self) -> u16 {
+        self.desc_idx
+    }
+
+    pub fn get_klass_version(&self) -> u32 {
+        self.klass_version
+    }
+
+    pub fn set_klass_version(&mut self, version: u32) {
+        self.klass_version = version;
+    }
+
     pub fn get_desc(&self) -> String {
         self.name_desc.clone()
     }
@@ -73,20 +151,102 @@ impl OtMethod {
         self.flags & ACC_NATIVE == ACC_NATIVE
     }
 
-    pub fn set_native_code(&self, n_code: fn(&InterpLocalVars) -> Option<JvmValue>) {
+    pub fn is_static(&self) -> bool {
+        self.flags & ACC_STATIC == ACC_STATIC
+    }
+
+    pub fn is_abstract(&self) -> bool {
+        self.flags & ACC_ABSTRACT_M == ACC_ABSTRACT_M
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.flags & ACC_PUBLIC == ACC_PUBLIC
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.flags & ACC_PRIVATE == ACC_PRIVATE
+    }
+
+    // The local var slot each parameter in the descriptor is passed in. The
+    // receiver of an instance method takes slot 0, and longs and doubles take
+    // two slots each
+    pub fn get_param_slots(&self) -> Vec<u16> {
+        let mut slot = if self.is_static() { 0 } else { 1 };
+        let mut out = Vec::new();
+        for param in self.get_params() {
+            out.push(slot);
+            slot += slot_width(&param);
+        }
+        out
+    }
+
+    // Slots taken up by the receiver, if any, and the parameters
+    pub fn get_arg_slots(&self) -> u16 {
+        let receiver = if self.is_static() { 0 } else { 1 };
+        receiver + self.get_params().iter().map(slot_width).sum::<u16>()
+    }
+
+    // Default values of the parameter types in the descriptor
+    fn get_params(&self) -> Vec<JvmValue> {
+        OtKlass::parse_sig_for_args(self.name_desc[self.name.len() + 1..].to_string())
+    }
+
+    // Native code is named by its symbol in native_methods, so that it can be
+    // found again when a method is restored from a snapshot
+    pub fn set_native_code(&self, symbol: &str) {
         if !self.is_native() {
             panic!("Should be unreachable - trying to store native code in a regular method")
         }
+        self.install_native_code(symbol);
+    }
+
+    // Native code that runs in place of a regular method's bytecode, for when
+    // the bundled class library does not do what later ones (or javac) expect
+    pub fn set_intrinsic_code(&self, symbol: &str) {
+        self.install_native_code(symbol);
+    }
+
+    pub fn is_intrinsic(&self) -> bool {
+        !self.is_native() && self.native_code.get().is_some()
+    }
+
+    // Whichever of the two the symbol was installed as before
+    pub fn reinstall_native_code(&self, symbol: &str) {
+        if self.is_native() {
+            self.set_native_code(symbol);
+        } else {
+            self.set_intrinsic_code(symbol);
+        }
+    }
+
+    fn install_native_code(&self, symbol: &str) {
+        let (sym, n_code) = match lookup_native(symbol) {
+            Some(native) => native,
+            None => panic!("No native code with symbol {} for {}", symbol, self),
+        };
+        self.native_symbol.set(Some(sym));
         self.native_code.set(Some(n_code));
     }
 
-    pub fn get_native_code(&self) -> Option<fn(&InterpLocalVars) -> Option<JvmValue>> {
+    pub fn get_native_code(&self) -> Option<fn(&mut SharedKlassRepo, &InterpLocalVars) -> Option<JvmValue>> {
         self.native_code.get()
     }
 
-    // HACK Replace with proper local var size by parsing class attributes properly
-    pub fn get_local_var_size(&self) -> u8 {
-        255
+    pub fn get_native_symbol(&self) -> Option<&'static str> {
+        self.native_symbol.get()
+    }
+
+    // The size of a frame for this method. Natives have no max_locals, so get
+    // just their arguments
+    pub fn get_local_var_size(&self) -> u16 {
+        self.max_locals.max(self.get_arg_slots())
+    }
+}
+
+fn slot_width(param: &JvmValue) -> u16 {
+    match param {
+        JvmValue::Long(_) | JvmValue::Double(_) => 2,
+        _ => 1,
     }
 }
 
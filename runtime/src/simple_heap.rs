@@ -1,95 +1,158 @@
 #![deny(unreachable_patterns)]
 
-use crate::JvmValue;
-use crate::OtField;
-use crate::OtKlass;
+use crate::heap::{Heap, HeapStats};
+use crate::mark_word::HashGenerator;
 use crate::OtObj;
 
-use std::sync::atomic::{AtomicUsize, Ordering};
+// A collection is due once this many objects have been allocated since the
+// last one, or as many as survived it, whichever is more
+const MIN_GC_THRESHOLD: usize = 4096;
 
+// Objects stay in their slot for life - collection is mark-sweep, and swept
+// slots go on a free list for reuse
+#[derive(Clone)]
 pub struct SharedSimpleHeap {
-    obj_count: AtomicUsize,
-    // Free list
-    // Alloc table
-    alloc: Vec<OtObj>,
+    // Alloc table - indexed by object id, a None slot is free or reserved
+    alloc: Vec<Option<OtObj>>,
+    // Free list of slots reclaimed by the collector
+    free: Vec<usize>,
+    live: usize,
+    live_after_gc: usize,
+    allocated_since_gc: usize,
+    stats: HeapStats,
+    hashes: HashGenerator,
 }
 
 impl SharedSimpleHeap {
     pub fn of() -> SharedSimpleHeap {
         let mut out = SharedSimpleHeap {
-            obj_count: AtomicUsize::new(1),
             alloc: Vec::new(),
+            free: Vec::new(),
+            live: 0,
+            live_after_gc: 0,
+            allocated_since_gc: 0,
+            stats: HeapStats::of(),
+            hashes: HashGenerator::default(),
         };
         let null_obj = OtObj::get_null();
-        out.alloc.push(null_obj);
+        out.alloc.push(Some(null_obj));
         out
     }
+}
 
-    pub fn allocate_obj(&mut self, klass: &OtKlass) -> usize {
-        let klass_id = klass.get_id();
-        let obj_id: usize = self.obj_count.fetch_add(1, Ordering::SeqCst);
-        let out = OtObj::obj_of(klass_id, obj_id, klass.make_default_values());
-        self.alloc.push(out);
-        obj_id
+impl Heap for SharedSimpleHeap {
+    fn name(&self) -> &'static str {
+        "mark-sweep"
     }
 
-    pub fn allocate_int_arr(&mut self, size: i32) -> usize {
-        let obj_id = self.obj_count.fetch_add(1, Ordering::SeqCst);
-        let out = OtObj::int_arr_of(size, obj_id);
-        self.alloc.push(out);
-        obj_id
+    fn box_clone(&self) -> Box<dyn Heap> {
+        Box::new(self.clone())
     }
 
-    pub fn get_obj(&self, id: usize) -> &OtObj {
-        match self.alloc.get(id) {
-            Some(val) => val,
-            None => panic!("Error: object {} not found", id),
+    fn reserve(&mut self) -> usize {
+        match self.free.pop() {
+            Some(obj_id) => obj_id,
+            None => {
+                self.alloc.push(None);
+                self.alloc.len() - 1
+            }
+        }
+    }
+
+    fn fill(&mut self, id: usize, obj: OtObj) {
+        if self.alloc[id].is_some() {
+            panic!("Error: slot {} is already in use", id);
+        }
+        self.stats.allocated(obj.shallow_size());
+        self.alloc[id] = Some(obj);
+        self.live += 1;
+        self.allocated_since_gc += 1;
+    }
+
+    fn live_count(&self) -> usize {
+        self.live
+    }
+
+    fn live_ids(&self) -> Vec<usize> {
+        (1..self.alloc.len()).filter(|id| self.alloc[*id].is_some()).collect()
+    }
+
+    fn is_live(&self, id: usize) -> bool {
+        matches!(self.alloc.get(id), Some(Some(_)))
+    }
+
+    fn stats(&self) -> &HeapStats {
+        &self.stats
+    }
+
+    fn hashes(&mut self) -> &mut HashGenerator {
+        &mut self.hashes
+    }
+
+    fn set_limits(&mut self, initial_bytes: u64, max_bytes: u64) {
+        self.stats.set_limits(initial_bytes, max_bytes);
+    }
+
+    fn should_collect(&self) -> bool {
+        self.allocated_since_gc >= MIN_GC_THRESHOLD.max(self.live_after_gc)
+    }
+
+    // Mark everything reachable from roots, then sweep every other slot onto
+    // the free list. Survivors have their mark bit cleared and grow older
+    fn collect(&mut self, roots: &[usize]) -> usize {
+        let mut pending = roots.to_vec();
+        while let Some(id) = pending.pop() {
+            let obj = self.get_obj_mut(id);
+            let mark = obj.get_mark();
+            if id == 0 || mark.is_marked() {
+                continue;
+            }
+            obj.set_mark(mark.marked());
+            pending.extend(obj.references());
+        }
+
+        let mut freed = 0;
+        let mut live_bytes = 0;
+        for (id, slot) in self.alloc.iter_mut().enumerate().skip(1) {
+            match slot {
+                Some(obj) if obj.get_mark().is_marked() => {
+                    obj.set_mark(obj.get_mark().unmarked().aged());
+                    live_bytes += obj.shallow_size() as u64;
+                }
+                Some(_) => {
+                    *slot = None;
+                    self.free.push(id);
+                    freed += 1;
+                }
+                None => {}
+            }
         }
+        self.live -= freed;
+        self.live_after_gc = self.live;
+        self.allocated_since_gc = 0;
+        self.stats.collected(live_bytes);
+        freed
     }
 
-    // FIXME Handle storage properly
-    pub fn put_field(&self, id: usize, f: OtField, v: JvmValue) -> () {
-        // Get object from heap
+    fn get_obj(&self, id: usize) -> &OtObj {
         match self.alloc.get(id) {
-            Some(val) => val.put_field(f.get_offset() as usize, v),
-            None => panic!("Error: object {} not found", id),
-        };
+            Some(Some(val)) => val,
+            _ => panic!("Error: object {} not found", id),
+        }
     }
 
-    pub fn get_field(&self, id: usize, offset: u16) -> JvmValue {
-        // Get object from heap
-        let obj = match self.alloc.get(id) {
-            Some(val) => val,
-            None => panic!("Error: object {} not found", id),
-        };
-        obj.get_field_value(offset as usize)
+    fn get_obj_mut(&mut self, id: usize) -> &mut OtObj {
+        match self.alloc.get_mut(id) {
+            Some(Some(val)) => val,
+            _ => panic!("Error: object {} not found", id),
+        }
     }
 
-    pub fn iastore(&mut self, id: usize, pos: i32, v: i32) -> () {
-        let p = pos as usize;
-        let obj = match self.alloc.get(id) {
-            Some(val) => val,
-            None => panic!("Error: object {} not found", id),
-        };
-        let t = match obj {
-            OtObj::VmArrInt {
-                id: i,
-                mark: m,
-                klassid: kid,
-                length: _,
-                elements: elts,
-            } => (i, m, kid, elts),
-            _ => panic!("Non-int[] seen in heap during IASTORE at {}", id),
-        };
-        let mut elts = t.3.clone();
-        elts[pos as usize] = v;
-        let obj = OtObj::VmArrInt {
-            id: *t.0,
-            mark: *t.1,
-            klassid: *t.2,
-            length: elts.len() as i32,
-            elements: elts,
-        };
-        self.alloc[id] = obj;
+    fn replace_obj(&mut self, id: usize, obj: OtObj) {
+        if !self.is_live(id) {
+            panic!("Error: object {} not found", id);
+        }
+        self.stats.resized(self.get_obj(id).shallow_size(), obj.shallow_size());
+        self.alloc[id] = Some(obj);
     }
 }
@@ -0,0 +1,567 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::constant_pool::*;
+use crate::object::OtObj;
+use crate::otfield::OtField;
+use crate::otklass::OtKlass;
+use crate::otmethod::{ExceptionHandler, OtMethod};
+use crate::JvmValue;
+use crate::heap::Heap;
+use crate::native_methods::lookup_native;
+
+use ocelotter_util::file_to_bytes;
+
+//////////// STARTUP SNAPSHOTS
+
+// A snapshot holds the bootstrap klasses in klass id order, the names that
+// they mention, and the heap objects reachable from their statics. Bump the
+// version whenever the layout of anything in here changes.
+const SNAPSHOT_MAGIC: &[u8; 4] = b"OTSS";
+const SNAPSHOT_VERSION: u16 = 9;
+
+// The sources of everything that decides what goes into a snapshot - how
+// classes are parsed, how klasses and objects are laid out, what bootstrap
+// does and which natives exist. A change to any of them makes every earlier
+// snapshot stale, even if nobody remembered to bump the version
+const BUILD_SOURCES: [&str; 9] = [
+    include_str!("snapshot.rs"),
+    include_str!("klass_repo.rs"),
+    include_str!("klass_parser.rs"),
+    include_str!("constant_pool.rs"),
+    include_str!("otklass.rs"),
+    include_str!("otmethod.rs"),
+    include_str!("otfield.rs"),
+    include_str!("object.rs"),
+    include_str!("native_methods.rs"),
+];
+
+// Tags for the kinds of heap object that can be reached from a static - each is
+// followed by its klass id, and a primitive array then by the descriptor letter
+// of its element type
+const OBJ_TAG: u8 = 1;
+const PRIM_ARR_TAG: u8 = 2;
+const OBJ_ARR_TAG: u8 = 3;
+
+// The bootstrap klasses as (klass, is live) in klass id order, the names
+// that are only mentioned, and the interned strings as (chars, heap id)
+pub type SnapshotContents = (Vec<(OtKlass, bool)>, Vec<String>, Vec<(Vec<u16>, usize)>);
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+fn fnv1a(h: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(h, |h, b| (h ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+// FNV-1a over the bytes of the jar that the snapshot was taken from, so a
+// stale snapshot is never used
+pub fn jar_hash(jar_name: &str) -> u64 {
+    let bytes = file_to_bytes(Path::new(jar_name))
+        .unwrap_or_else(|_| panic!("Problem reading {}", jar_name));
+    fnv1a(FNV_OFFSET_BASIS, &bytes)
+}
+
+// FNV-1a over the crate version and BUILD_SOURCES - snapshots are only ever
+// read back by the build that wrote them
+pub fn build_hash() -> u64 {
+    BUILD_SOURCES
+        .iter()
+        .fold(fnv1a(FNV_OFFSET_BASIS, env!("CARGO_PKG_VERSION").as_bytes()), |h, src| fnv1a(h, src.as_bytes()))
+}
+
+// klasses are (klass, is live) in klass id order
+pub fn encode(jar_hash: u64, klasses: &[(OtKlass, bool)], mentioned: &[String], interned: &[(Vec<u16>, usize)], heap: &dyn Heap) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(SNAPSHOT_MAGIC);
+    // Writes into a Vec<u8> can't fail
+    out.write_u16::<BigEndian>(SNAPSHOT_VERSION).unwrap();
+    out.write_u64::<BigEndian>(build_hash()).unwrap();
+    out.write_u64::<BigEndian>(jar_hash).unwrap();
+
+    out.write_u32::<BigEndian>(klasses.len() as u32).unwrap();
+    for (k, live) in klasses {
+        out.write_u8(*live as u8).unwrap();
+        write_klass(&mut out, k);
+    }
+
+    out.write_u32::<BigEndian>(mentioned.len() as u32).unwrap();
+    for name in mentioned {
+        write_str(&mut out, name);
+    }
+
+    out.write_u32::<BigEndian>(interned.len() as u32).unwrap();
+    for (chars, obj_id) in interned {
+        out.write_u32::<BigEndian>(chars.len() as u32).unwrap();
+        for c in chars {
+            out.write_u16::<BigEndian>(*c).unwrap();
+        }
+        out.write_u64::<BigEndian>(*obj_id as u64).unwrap();
+    }
+
+    let roots = klasses
+        .iter()
+        .flat_map(|(k, _)| k.get_static_fields().into_iter().map(move |f| k.get_static(&f)))
+        .chain(interned.iter().map(|(_, obj_id)| JvmValue::ObjRef(*obj_id)))
+        .collect();
+    write_objects(&mut out, roots, heap);
+    out
+}
+
+// Returns None if the bytes are not a snapshot of the jar with this hash, taken
+// by this build of the VM. The objects reachable from statics and interned
+// strings are recreated in heap, and the statics and interned strings that
+// come back point at the new copies.
+pub fn decode(jar_hash: u64, bytes: &[u8], heap: &mut dyn Heap) -> Option<SnapshotContents> {
+    let mut buf = Cursor::new(bytes);
+    let mut magic = [0u8; 4];
+    buf.read_exact(&mut magic).ok()?;
+    if &magic != SNAPSHOT_MAGIC
+        || buf.read_u16::<BigEndian>().ok()? != SNAPSHOT_VERSION
+        || buf.read_u64::<BigEndian>().ok()? != build_hash()
+        || buf.read_u64::<BigEndian>().ok()? != jar_hash
+    {
+        return None;
+    }
+    read_snapshot(&mut buf, heap).ok()
+}
+
+fn read_snapshot(buf: &mut Cursor<&[u8]>, heap: &mut dyn Heap) -> io::Result<SnapshotContents> {
+    let mut klasses = Vec::new();
+    let mut statics = Vec::new();
+    for _ in 0..buf.read_u32::<BigEndian>()? {
+        let live = buf.read_u8()? == 1;
+        let (k, vals) = read_klass(buf)?;
+        klasses.push((k, live));
+        statics.push(vals);
+    }
+
+    let mut mentioned = Vec::new();
+    for _ in 0..buf.read_u32::<BigEndian>()? {
+        mentioned.push(read_str(buf)?);
+    }
+
+    let mut interned = Vec::new();
+    for _ in 0..buf.read_u32::<BigEndian>()? {
+        let mut chars = Vec::new();
+        for _ in 0..buf.read_u32::<BigEndian>()? {
+            chars.push(buf.read_u16::<BigEndian>()?);
+        }
+        interned.push((chars, buf.read_u64::<BigEndian>()? as usize));
+    }
+
+    let remap = read_objects(buf, heap)?;
+    for ((k, _), vals) in klasses.iter().zip(statics) {
+        for (f, v) in k.get_static_fields().iter().zip(vals) {
+            k.put_static(f, remap_value(&remap, v));
+        }
+    }
+    let interned = interned
+        .into_iter()
+        .map(|(chars, obj_id)| match remap.get(&obj_id) {
+            Some(new_id) => Ok((chars, *new_id)),
+            None => Err(corrupt(format!("interned string {} was not saved", obj_id))),
+        })
+        .collect::<io::Result<_>>()?;
+    Ok((klasses, mentioned, interned))
+}
+
+//////////// KLASSES
+
+fn write_klass(out: &mut Vec<u8>, k: &OtKlass) {
+    write_str(out, &k.get_name());
+    write_str(out, &k.get_super_name());
+    let interfaces = k.get_interfaces();
+    out.write_u16::<BigEndian>(interfaces.len() as u16).unwrap();
+    for i in interfaces.iter() {
+        write_str(out, i);
+    }
+    out.write_u16::<BigEndian>(k.get_flags()).unwrap();
+
+    let cp_entries = k.get_cp_entries();
+    out.write_u16::<BigEndian>(cp_entries.len() as u16).unwrap();
+    for cp in cp_entries.iter() {
+        write_cp_entry(out, cp);
+    }
+
+    let s_fields = k.get_static_fields();
+    let i_fields = k.get_instance_fields();
+    out.write_u16::<BigEndian>((s_fields.len() + i_fields.len()) as u16).unwrap();
+    for f in s_fields.iter().chain(i_fields.iter()) {
+        write_field(out, f);
+    }
+    for f in s_fields.iter() {
+        write_value(out, k.get_static(f));
+    }
+
+    let methods = k.get_methods();
+    out.write_u16::<BigEndian>(methods.len() as u16).unwrap();
+    for m in methods.iter() {
+        write_method(out, m);
+    }
+
+    let bootstrap_methods = k.get_bootstrap_methods();
+    out.write_u16::<BigEndian>(bootstrap_methods.len() as u16).unwrap();
+    for bsm in bootstrap_methods.iter() {
+        out.write_u16::<BigEndian>(bsm.method_idx).unwrap();
+        out.write_u16::<BigEndian>(bsm.arg_idxs.len() as u16).unwrap();
+        for idx in bsm.arg_idxs.iter() {
+            out.write_u16::<BigEndian>(*idx).unwrap();
+        }
+    }
+}
+
+fn read_klass(buf: &mut Cursor<&[u8]>) -> io::Result<(OtKlass, Vec<JvmValue>)> {
+    let name = read_str(buf)?;
+    let super_name = read_str(buf)?;
+    let mut interfaces = Vec::new();
+    for _ in 0..buf.read_u16::<BigEndian>()? {
+        interfaces.push(read_str(buf)?);
+    }
+    let flags = buf.read_u16::<BigEndian>()?;
+
+    let mut cp_entries = Vec::new();
+    for _ in 0..buf.read_u16::<BigEndian>()? {
+        cp_entries.push(read_cp_entry(buf)?);
+    }
+
+    let mut fields = Vec::new();
+    for _ in 0..buf.read_u16::<BigEndian>()? {
+        fields.push(read_field(buf, &name)?);
+    }
+    let mut static_vals = Vec::new();
+    for _ in fields.iter().filter(|f| f.is_static()) {
+        static_vals.push(read_value(buf)?);
+    }
+
+    let mut methods = Vec::new();
+    for _ in 0..buf.read_u16::<BigEndian>()? {
+        methods.push(read_method(buf, &name)?);
+    }
+
+    let mut bootstrap_methods = Vec::new();
+    for _ in 0..buf.read_u16::<BigEndian>()? {
+        let method_idx = buf.read_u16::<BigEndian>()?;
+        let mut arg_idxs = Vec::new();
+        for _ in 0..buf.read_u16::<BigEndian>()? {
+            arg_idxs.push(buf.read_u16::<BigEndian>()?);
+        }
+        bootstrap_methods.push(BootstrapMethod { method_idx, arg_idxs });
+    }
+
+    let mut k = OtKlass::of(name, super_name, flags, &cp_entries, &methods, &fields);
+    k.set_interfaces(interfaces);
+    k.set_bootstrap_methods(bootstrap_methods);
+    Ok((k, static_vals))
+}
+
+fn write_cp_entry(out: &mut Vec<u8>, cp: &CpEntry) {
+    let write_pair = |out: &mut Vec<u8>, tag: u8, a: u16, b: u16| {
+        out.write_u8(tag).unwrap();
+        out.write_u16::<BigEndian>(a).unwrap();
+        out.write_u16::<BigEndian>(b).unwrap();
+    };
+    match cp {
+        CpEntry::Utf8(s) => {
+            out.write_u8(CP_UTF8).unwrap();
+            write_str(out, s);
+        }
+        CpEntry::Integer(i) => {
+            out.write_u8(CP_INTEGER).unwrap();
+            out.write_i32::<BigEndian>(*i).unwrap();
+        }
+        CpEntry::Float(f) => {
+            out.write_u8(CP_FLOAT).unwrap();
+            out.write_f32::<BigEndian>(*f).unwrap();
+        }
+        CpEntry::Long(l) => {
+            out.write_u8(CP_LONG).unwrap();
+            out.write_i64::<BigEndian>(*l).unwrap();
+        }
+        CpEntry::Double(d) => {
+            out.write_u8(CP_DOUBLE).unwrap();
+            out.write_f64::<BigEndian>(*d).unwrap();
+        }
+        CpEntry::Class(ClassRef(idx)) => {
+            out.write_u8(CP_CLASS).unwrap();
+            out.write_u16::<BigEndian>(*idx).unwrap();
+        }
+        CpEntry::String(StringRef(idx)) => {
+            out.write_u8(CP_STRING).unwrap();
+            out.write_u16::<BigEndian>(*idx).unwrap();
+        }
+        CpEntry::FieldRef(fr) => write_pair(out, CP_FIELDREF, fr.clz_idx, fr.nt_idx),
+        CpEntry::MethodRef(mr) => write_pair(out, CP_METHODREF, mr.clz_idx, mr.nt_idx),
+        CpEntry::InterfaceMethodRef(imr) => {
+            write_pair(out, CP_INTERFACE_METHODREF, imr.clz_idx, imr.nt_idx)
+        }
+        CpEntry::NameAndType(nt) => write_pair(out, CP_NAMEANDTYPE, nt.name_idx, nt.type_idx),
+        CpEntry::MethodHandle(mh) => write_pair(out, CP_METHODHANDLE, mh.kind as u16, mh.ref_idx),
+        CpEntry::MethodType(MethodTypeRef(idx)) => {
+            out.write_u8(CP_METHODTYPE).unwrap();
+            out.write_u16::<BigEndian>(*idx).unwrap();
+        }
+        CpEntry::InvokeDynamic(indy) => write_pair(out, CP_INVOKEDYNAMIC, indy.bsm_idx, indy.nt_idx),
+    }
+}
+
+fn read_cp_entry(buf: &mut Cursor<&[u8]>) -> io::Result<CpEntry> {
+    let tag = buf.read_u8()?;
+    Ok(match tag {
+        CP_UTF8 => CpEntry::Utf8(read_str(buf)?),
+        CP_INTEGER => CpEntry::Integer(buf.read_i32::<BigEndian>()?),
+        CP_FLOAT => CpEntry::Float(buf.read_f32::<BigEndian>()?),
+        CP_LONG => CpEntry::Long(buf.read_i64::<BigEndian>()?),
+        CP_DOUBLE => CpEntry::Double(buf.read_f64::<BigEndian>()?),
+        CP_CLASS => CpEntry::Class(ClassRef(buf.read_u16::<BigEndian>()?)),
+        CP_STRING => CpEntry::String(StringRef(buf.read_u16::<BigEndian>()?)),
+        CP_METHODTYPE => CpEntry::MethodType(MethodTypeRef(buf.read_u16::<BigEndian>()?)),
+        _ => {
+            let a = buf.read_u16::<BigEndian>()?;
+            let b = buf.read_u16::<BigEndian>()?;
+            match tag {
+                CP_FIELDREF => CpEntry::FieldRef(FieldRef::new(a, b)),
+                CP_METHODREF => CpEntry::MethodRef(MethodRef::new(a, b)),
+                CP_INTERFACE_METHODREF => CpEntry::InterfaceMethodRef(InterfaceMethodRef::new(a, b)),
+                CP_NAMEANDTYPE => CpEntry::NameAndType(NameAndType::new(a, b)),
+                CP_METHODHANDLE => CpEntry::MethodHandle(MethodHandleRef::new(a as u8, b)),
+                CP_INVOKEDYNAMIC => CpEntry::InvokeDynamic(InvokeDynamicRef::new(a, b)),
+                _ => return Err(corrupt(format!("unknown constant pool tag {}", tag))),
+            }
+        }
+    })
+}
+
+fn write_field(out: &mut Vec<u8>, f: &OtField) {
+    out.write_u16::<BigEndian>(f.get_offset()).unwrap();
+    out.write_u16::<BigEndian>(f.get_flags()).unwrap();
+    out.write_u16::<BigEndian>(f.get_name_idx()).unwrap();
+    out.write_u16::<BigEndian>(f.get_desc_idx()).unwrap();
+    write_str(out, &f.get_name());
+    write_str(out, &f.get_desc());
+}
+
+fn read_field(buf: &mut Cursor<&[u8]>, klass_name: &str) -> io::Result<OtField> {
+    let offset = buf.read_u16::<BigEndian>()?;
+    let flags = buf.read_u16::<BigEndian>()?;
+    let name_idx = buf.read_u16::<BigEndian>()?;
+    let desc_idx = buf.read_u16::<BigEndian>()?;
+    let name = read_str(buf)?;
+    let desc = read_str(buf)?;
+    Ok(OtField::of(offset, klass_name.to_string(), name, desc, flags, name_idx, desc_idx))
+}
+
+fn write_method(out: &mut Vec<u8>, m: &OtMethod) {
+    let name = m.get_name();
+    // The descriptor is whatever follows the name in name:desc
+    let desc = m.get_desc()[name.len() + 1..].to_string();
+    out.write_u16::<BigEndian>(m.get_flags()).unwrap();
+    out.write_u16::<BigEndian>(m.get_name_idx()).unwrap();
+    out.write_u16::<BigEndian>(m.get_desc_idx()).unwrap();
+    write_str(out, &name);
+    write_str(out, &desc);
+    write_bytes(out, &m.get_code());
+    out.write_u16::<BigEndian>(m.get_max_locals()).unwrap();
+    let handlers = m.get_exception_table();
+    out.write_u16::<BigEndian>(handlers.len() as u16).unwrap();
+    for h in handlers {
+        out.write_u16::<BigEndian>(h.start_pc).unwrap();
+        out.write_u16::<BigEndian>(h.end_pc).unwrap();
+        out.write_u16::<BigEndian>(h.handler_pc).unwrap();
+        out.write_u16::<BigEndian>(h.catch_type).unwrap();
+    }
+    write_str(out, m.get_native_symbol().unwrap_or(""));
+}
+
+fn read_method(buf: &mut Cursor<&[u8]>, klass_name: &str) -> io::Result<OtMethod> {
+    let flags = buf.read_u16::<BigEndian>()?;
+    let name_idx = buf.read_u16::<BigEndian>()?;
+    let desc_idx = buf.read_u16::<BigEndian>()?;
+    let name = read_str(buf)?;
+    let desc = read_str(buf)?;
+    let mut m = OtMethod::of(klass_name.to_string(), name, desc, flags, name_idx, desc_idx);
+    m.set_code(read_bytes(buf)?);
+    m.set_max_locals(buf.read_u16::<BigEndian>()?);
+    let handler_count = buf.read_u16::<BigEndian>()?;
+    let mut handlers = Vec::with_capacity(handler_count as usize);
+    for _ in 0..handler_count {
+        handlers.push(ExceptionHandler {
+            start_pc: buf.read_u16::<BigEndian>()?,
+            end_pc: buf.read_u16::<BigEndian>()?,
+            handler_pc: buf.read_u16::<BigEndian>()?,
+            catch_type: buf.read_u16::<BigEndian>()?,
+        });
+    }
+    m.set_exception_table(handlers);
+    // A native this build doesn't have means the snapshot came from another one
+    let symbol = read_str(buf)?;
+    if !symbol.is_empty() {
+        if lookup_native(&symbol).is_none() {
+            return Err(corrupt(format!("unknown native {}", symbol)));
+        }
+        m.reinstall_native_code(&symbol);
+    }
+    Ok(m)
+}
+
+//////////// VALUES AND OBJECTS
+
+fn write_value(out: &mut Vec<u8>, v: JvmValue) {
+    out.write_u8(v.name() as u8).unwrap();
+    match v {
+        JvmValue::Boolean(b) => out.write_u8(b as u8).unwrap(),
+        JvmValue::Byte(b) => out.write_i8(b).unwrap(),
+        JvmValue::Short(s) => out.write_i16::<BigEndian>(s).unwrap(),
+        JvmValue::Int(i) => out.write_i32::<BigEndian>(i).unwrap(),
+        JvmValue::Long(l) => out.write_i64::<BigEndian>(l).unwrap(),
+        JvmValue::Float(f) => out.write_f32::<BigEndian>(f).unwrap(),
+        JvmValue::Double(d) => out.write_f64::<BigEndian>(d).unwrap(),
+        JvmValue::Char(c) => out.write_u32::<BigEndian>(c as u32).unwrap(),
+        JvmValue::ObjRef(id) => out.write_u64::<BigEndian>(id as u64).unwrap(),
+    }
+}
+
+fn read_value(buf: &mut Cursor<&[u8]>) -> io::Result<JvmValue> {
+    Ok(match buf.read_u8()? as char {
+        'Z' => JvmValue::Boolean(buf.read_u8()? != 0),
+        'B' => JvmValue::Byte(buf.read_i8()?),
+        'S' => JvmValue::Short(buf.read_i16::<BigEndian>()?),
+        'I' => JvmValue::Int(buf.read_i32::<BigEndian>()?),
+        'J' => JvmValue::Long(buf.read_i64::<BigEndian>()?),
+        'F' => JvmValue::Float(buf.read_f32::<BigEndian>()?),
+        'D' => JvmValue::Double(buf.read_f64::<BigEndian>()?),
+        'C' => JvmValue::Char(std::char::from_u32(buf.read_u32::<BigEndian>()?).unwrap_or('\0')),
+        'A' => JvmValue::ObjRef(buf.read_u64::<BigEndian>()? as usize),
+        x => return Err(corrupt(format!("unknown value type {}", x))),
+    })
+}
+
+fn remap_value(remap: &HashMap<usize, usize>, v: JvmValue) -> JvmValue {
+    match v {
+        JvmValue::ObjRef(id) if id != 0 => JvmValue::ObjRef(remap[&id]),
+        _ => v,
+    }
+}
+
+// Everything reachable from the roots, each object written with its old id so
+// that references to it can be pointed at its new copy
+fn write_objects(out: &mut Vec<u8>, roots: Vec<JvmValue>, heap: &dyn Heap) {
+    let mut pending: Vec<usize> = roots.iter().filter_map(|v| v.as_objref()).collect();
+    // Objects are written in the order they are first reached
+    let mut seen = HashSet::new();
+    let mut order = Vec::new();
+    while let Some(id) = pending.pop() {
+        if id == 0 || !seen.insert(id) {
+            continue;
+        }
+        order.push(id);
+        pending.extend(heap.get_obj(id).references());
+    }
+
+    out.write_u32::<BigEndian>(order.len() as u32).unwrap();
+    for id in order {
+        out.write_u64::<BigEndian>(id as u64).unwrap();
+        match heap.get_obj(id) {
+            OtObj::VmObj { klassid, fields, .. } => {
+                out.write_u8(OBJ_TAG).unwrap();
+                out.write_u64::<BigEndian>(*klassid as u64).unwrap();
+                out.write_u32::<BigEndian>(fields.len() as u32).unwrap();
+                for f in fields {
+                    write_value(out, f.get());
+                }
+            }
+            OtObj::VmArrObj { klassid, elements, .. } => {
+                out.write_u8(OBJ_ARR_TAG).unwrap();
+                out.write_u64::<BigEndian>(*klassid as u64).unwrap();
+                out.write_u32::<BigEndian>(elements.len() as u32).unwrap();
+                for e in elements {
+                    write_value(out, JvmValue::ObjRef(*e));
+                }
+            }
+            arr => {
+                out.write_u8(PRIM_ARR_TAG).unwrap();
+                out.write_u64::<BigEndian>(arr.get_klassid() as u64).unwrap();
+                out.write_u8(arr.elem_type() as u8).unwrap();
+                out.write_u32::<BigEndian>(arr.length() as u32).unwrap();
+                for i in 0..arr.length() {
+                    write_value(out, arr.array_load(i));
+                }
+            }
+        }
+    }
+}
+
+// Allocates the objects afresh and returns the map from old ids to new ones
+fn read_objects(buf: &mut Cursor<&[u8]>, heap: &mut dyn Heap) -> io::Result<HashMap<usize, usize>> {
+    let count = buf.read_u32::<BigEndian>()? as usize;
+
+    // Every object gets its slot before any of them is built, so that references
+    // between them can be remapped
+    let mut objs = Vec::with_capacity(count);
+    let mut remap = HashMap::new();
+    for _ in 0..count {
+        let old_id = buf.read_u64::<BigEndian>()? as usize;
+        let obj_id = heap.reserve();
+        remap.insert(old_id, obj_id);
+        let tag = buf.read_u8()?;
+        let klass_id = buf.read_u64::<BigEndian>()? as usize;
+        // The element type of a primitive array, '\0' for anything else
+        let elem_type = match tag {
+            OBJ_TAG | OBJ_ARR_TAG => '\0',
+            PRIM_ARR_TAG => match buf.read_u8()? as char {
+                t @ ('Z' | 'B' | 'C' | 'S' | 'I' | 'J' | 'F' | 'D') => t,
+                t => return Err(corrupt(format!("unknown array type {}", t))),
+            },
+            _ => return Err(corrupt(format!("unknown object tag {}", tag))),
+        };
+        let len = buf.read_u32::<BigEndian>()? as usize;
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            values.push(read_value(buf)?);
+        }
+        objs.push((obj_id, tag, klass_id, elem_type, values));
+    }
+
+    for (obj_id, tag, klass_id, elem_type, values) in objs {
+        let obj = match tag {
+            OBJ_TAG => OtObj::obj_of(klass_id, obj_id, values.into_iter().map(|v| remap_value(&remap, v)).collect()),
+            OBJ_ARR_TAG => OtObj::obj_arr_of(
+                klass_id,
+                values.into_iter().map(|v| remap_value(&remap, v).as_objref().unwrap_or(0)).collect(),
+                obj_id,
+            ),
+            _ => OtObj::prim_arr_of(klass_id, elem_type, values, obj_id),
+        };
+        heap.fill(obj_id, obj);
+    }
+    Ok(remap)
+}
+
+//////////// PRIMITIVES
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_bytes(out, s.as_bytes());
+}
+
+fn read_str(buf: &mut Cursor<&[u8]>) -> io::Result<String> {
+    String::from_utf8(read_bytes(buf)?).map_err(|e| corrupt(e.to_string()))
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.write_u32::<BigEndian>(bytes.len() as u32).unwrap();
+    out.extend_from_slice(bytes);
+}
+
+fn read_bytes(buf: &mut Cursor<&[u8]>) -> io::Result<Vec<u8>> {
+    let len = buf.read_u32::<BigEndian>()? as usize;
+    let mut out = vec![0u8; len];
+    buf.read_exact(&mut out)?;
+    Ok(out)
+}
+
+fn corrupt(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
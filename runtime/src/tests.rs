@@ -2,6 +2,12 @@ use super::*;
 
 use std::path::Path;
 use ocelotter_util::file_to_bytes;
+use crate::copying_heap::CopyingHeap;
+use crate::heap::{Heap, HeapStats, INITIAL_COMMITTED_BYTES};
+use crate::simple_heap::SharedSimpleHeap;
+use crate::klass_repo::BOOTSTRAP_LOADER;
+use crate::klass_parser::decode_modified_utf8;
+use crate::mark_word::{HashGenerator, MarkWord, MAX_AGE};
 
 #[test]
 fn test_klass_name_from_fq() {
@@ -80,3 +86,457 @@ fn check_system_current_timemillis() {
 // assert_eq!("SampleInvoke", k.get_name());
 // assert_eq!("java/lang/Object", k.get_super_name());
 // assert_eq!(4, k.get_methods().len());
+
+#[test]
+fn test_klass_names_from_desc() {
+    let desc = "(ILjava/lang/String;[[LFoo;J)Ljava/lang/String;".to_string();
+    assert_eq!(
+        vec!["java/lang/String".to_string(), "Foo".to_string()],
+        SharedKlassRepo::klass_names_from_desc(&desc)
+    );
+    assert!(SharedKlassRepo::klass_names_from_desc("([IDZ)V").is_empty());
+}
+
+fn parse_test_klass(cname: &str) -> OtKlass {
+    let path = format!("../resources/test/{}.class", cname);
+    let bytes = match file_to_bytes(Path::new(&path)) {
+        Ok(buf) => buf,
+        _ => panic!("Error reading {}", cname),
+    };
+    let mut parser = klass_parser::OtKlassParser::of(bytes, cname.to_string());
+    parser.parse();
+    parser.klass()
+}
+
+#[test]
+fn check_loader_constraints() {
+    let mut repo = SharedKlassRepo::of();
+    repo.register_loader(1);
+    repo.register_loader(2);
+    let desc = "(LPluginHelper;)V".to_string();
+
+    repo.define_klass(1, &parse_test_klass("loader/v1/PluginHelper")).unwrap();
+    repo.define_klass(2, &parse_test_klass("loader/v1/PluginHelper")).unwrap();
+    assert!(repo.check_loader_constraints(1, 1, &desc).is_ok());
+    assert!(repo.check_loader_constraints(1, 2, &desc).is_err());
+
+    // Both loaders delegate to the same bootstrap klass
+    repo.add_klass(&parse_test_klass("Foo"));
+    assert!(repo.check_loader_constraints(1, 2, "(LFoo;)V").is_ok());
+}
+
+#[test]
+fn virtual_lookup_searches_the_callers_loader() {
+    let mut repo = SharedKlassRepo::of();
+    repo.register_loader(1);
+    repo.define_klass(1, &parse_test_klass("loader/v1/PluginHelper")).unwrap();
+    // There is no bootstrap PluginHelper to fall back on
+    repo.lookup_method_virtual(1, &"PluginHelper".to_string(), 1);
+}
+
+struct TestSource(Vec<ocelotter_util::ClassEntry>);
+
+impl ocelotter_util::ClassSource for TestSource {
+    fn class_entries(&self) -> Vec<ocelotter_util::ClassEntry> {
+        self.0.clone()
+    }
+}
+
+#[test]
+fn add_class_source_collects_bad_entries() {
+    let read = |cname: &str| file_to_bytes(Path::new(&format!("../resources/test/{}.class", cname))).map_err(|e| e.to_string());
+    let source = TestSource(vec![
+        ("Foo.class".to_string(), read("Foo")),
+        ("Broken.class".to_string(), Ok(vec![0xca, 0xfe, 0xd0, 0x0d])),
+        ("Foo2.class".to_string(), read("Foo2")),
+    ]);
+
+    let mut repo = SharedKlassRepo::of();
+    let errors = repo.add_class_source(&source);
+    assert_eq!(1, errors.len());
+    assert_eq!("Broken.class", errors[0].entry);
+    assert!(errors[0].message.contains("magic number"));
+
+    // The good entries still go in, in source order
+    assert_eq!(1, repo.lookup_klass(&"Foo".to_string()).get_id());
+    assert_eq!(2, repo.lookup_klass(&"Foo2".to_string()).get_id());
+}
+
+#[test]
+fn add_jar_reports_unreadable_entries() {
+    let mut repo = SharedKlassRepo::of();
+    // The deflated bytes of Foo.class are damaged, but Foo2.class is intact
+    let errors = repo.add_jar("../resources/test/jar/corrupt.jar", 8);
+    assert_eq!(1, errors.len());
+    assert_eq!("Foo.class", errors[0].entry);
+    assert!(repo.find_klass_in(BOOTSTRAP_LOADER, "Foo").is_none());
+    assert_eq!(1, repo.lookup_klass(&"Foo2".to_string()).get_id());
+}
+
+fn node_klass(repo: &mut SharedKlassRepo) -> OtKlass {
+    repo.add_klass(&parse_test_klass("gc/Node"));
+    repo.lookup_klass(&"Node".to_string())
+}
+
+// Heaps only need the name and id of a primitive array klass
+fn prim_arr_klass(elem_type: char) -> OtKlass {
+    OtKlass::of(format!("[{}", elem_type), "java/lang/Object".to_string(), 0, &Vec::new(), &Vec::new(), &Vec::new())
+}
+
+fn link(heap: &dyn Heap, node: &OtKlass, from: usize, to: usize) {
+    let next = node.get_instance_fields()[0].clone();
+    heap.get_obj(from).put_field(node.get_instance_field_offset(&next), JvmValue::ObjRef(to));
+}
+
+fn next_of(heap: &dyn Heap, node: &OtKlass, id: usize) -> usize {
+    let next = node.get_instance_fields()[0].clone();
+    heap.get_obj(id).get_field_value(node.get_instance_field_offset(&next)).as_objref().unwrap()
+}
+
+fn check_reclaims_unreachable_objects(heap: &mut dyn Heap) {
+    let mut repo = SharedKlassRepo::of();
+    let node = node_klass(&mut repo);
+    let a = heap.allocate_obj(&node);
+    let b = heap.allocate_obj(&node);
+    let c = heap.allocate_obj(&node);
+    let arr = heap.allocate_int_arr(&prim_arr_klass('I'), 4).unwrap();
+    link(heap, &node, a, b);
+    link(heap, &node, c, a);
+
+    // c points at a, but nothing points at c
+    assert_eq!(1, heap.collect(&[a, arr]));
+    assert!(heap.is_live(a) && heap.is_live(b) && heap.is_live(arr));
+    assert!(!heap.is_live(c));
+    assert_eq!(3, heap.live_count());
+    assert_eq!(b, next_of(heap, &node, a));
+
+    // Freed ids are reused
+    assert_eq!(c, heap.allocate_obj(&node));
+}
+
+// A list of the 100 most recent nodes stays live while 200k are allocated
+fn check_bounded_under_churn(heap: &mut dyn Heap) {
+    let mut repo = SharedKlassRepo::of();
+    let node = node_klass(&mut repo);
+
+    let mut live: Vec<usize> = Vec::new();
+    for _ in 0..200_000 {
+        if heap.should_collect() {
+            heap.collect(&live[..1]);
+        }
+        let n = heap.allocate_obj(&node);
+        if let Some(prev) = live.first() {
+            link(heap, &node, n, *prev);
+        }
+        live.insert(0, n);
+        live.truncate(100);
+        if let Some(last) = live.last() {
+            link(heap, &node, *last, 0);
+        }
+    }
+
+    assert!(heap.live_count() < 10_000, "heap grew to {} objects", heap.live_count());
+    heap.collect(&live[..1]);
+    assert_eq!(100, heap.live_count());
+    // The list is intact, however often it has been moved
+    let mut id = live[0];
+    for expected in &live {
+        assert_eq!(*expected, id);
+        id = next_of(heap, &node, id);
+    }
+    assert_eq!(0, id);
+}
+
+#[test]
+fn mark_sweep_reclaims_unreachable_objects() {
+    check_reclaims_unreachable_objects(&mut SharedSimpleHeap::of());
+}
+
+#[test]
+fn mark_sweep_stays_bounded_under_churn() {
+    check_bounded_under_churn(&mut SharedSimpleHeap::of());
+}
+
+#[test]
+fn copying_reclaims_unreachable_objects() {
+    check_reclaims_unreachable_objects(&mut CopyingHeap::of());
+}
+
+#[test]
+fn copying_stays_bounded_under_churn() {
+    check_bounded_under_churn(&mut CopyingHeap::of());
+}
+
+#[test]
+fn collect_garbage_uses_statics_loaders_and_frames_as_roots() {
+    let mut repo = SharedKlassRepo::of();
+    let node = node_klass(&mut repo);
+    let (kept, next, in_frame, garbage) = {
+        let heap = repo.heap_mut();
+        let ids = (heap.allocate_obj(&node), heap.allocate_obj(&node), heap.allocate_obj(&node), heap.allocate_obj(&node));
+        link(heap, &node, ids.0, ids.1);
+        ids
+    };
+    repo.put_static(BOOTSTRAP_LOADER, &node.get_static_fields()[0], JvmValue::ObjRef(kept));
+    repo.push_frame(vec![in_frame]);
+
+    repo.collect_garbage();
+    let heap = repo.heap();
+    assert!(heap.is_live(kept) && heap.is_live(next) && heap.is_live(in_frame));
+    assert!(!heap.is_live(garbage));
+
+    // Once its frame is gone, so is the object it held
+    repo.pop_frame();
+    repo.collect_garbage();
+    assert!(!repo.heap().is_live(in_frame));
+}
+
+#[test]
+fn mark_word_fields_are_independent() {
+    let mark = MarkWord::default();
+    assert_eq!(None, mark.hash());
+    assert!(!mark.is_locked());
+
+    let mut hashes = HashGenerator::default();
+    let mark = mark.with_hash(&mut hashes).locked().unwrap().locked().unwrap().marked();
+    let hash = mark.hash().unwrap();
+    assert!(hash > 0);
+    assert_eq!(2, mark.lock_count());
+    assert!(mark.is_marked());
+
+    let mut aged = mark.unmarked().unlocked().unwrap();
+    for _ in 0..20 {
+        aged = aged.aged();
+    }
+    assert_eq!(MAX_AGE, aged.age());
+    assert_eq!(Some(hash), aged.with_hash(&mut hashes).hash());
+    assert_eq!(1, aged.lock_count());
+    assert!(!aged.is_marked());
+    assert_eq!(None, MarkWord::default().unlocked());
+}
+
+fn check_identity_hash_survives_collection(heap: &mut dyn Heap) {
+    let mut repo = SharedKlassRepo::of();
+    let node = node_klass(&mut repo);
+    let garbage = heap.allocate_obj(&node);
+    let a = heap.allocate_obj(&node);
+    let b = heap.allocate_obj(&node);
+    link(heap, &node, a, b);
+
+    let hash_a = heap.identity_hash(a);
+    let hash_b = heap.identity_hash(b);
+    assert_ne!(hash_a, hash_b);
+    assert_eq!(hash_a, heap.identity_hash(a));
+    assert_eq!(0, heap.identity_hash(0));
+    assert!(heap.monitor_enter(a));
+
+    // garbage goes, so a copying collector moves a and b down
+    assert_eq!(1, heap.collect(&[a]));
+    assert!(!heap.is_live(garbage));
+    assert_eq!(hash_a, heap.identity_hash(a));
+    assert_eq!(hash_b, heap.identity_hash(b));
+
+    let mark = heap.get_obj(a).get_mark();
+    assert_eq!(1, mark.age());
+    assert!(!mark.is_marked());
+    assert!(mark.is_locked());
+    assert!(heap.monitor_exit(a));
+    assert!(!heap.monitor_exit(a));
+}
+
+#[test]
+fn mark_sweep_keeps_identity_hashes() {
+    check_identity_hash_survives_collection(&mut SharedSimpleHeap::of());
+}
+
+#[test]
+fn copying_keeps_identity_hashes() {
+    check_identity_hash_survives_collection(&mut CopyingHeap::of());
+}
+
+// Hashing in one heap doesn't move on the hashes of another
+#[test]
+fn identity_hashes_are_per_heap() {
+    let mut repo = SharedKlassRepo::of();
+    let node = node_klass(&mut repo);
+    let mut first = SharedSimpleHeap::of();
+    let mut second = SharedSimpleHeap::of();
+    let a = first.allocate_obj(&node);
+    let b = first.allocate_obj(&node);
+    let c = second.allocate_obj(&node);
+    let hash_a = first.identity_hash(a);
+    first.identity_hash(b);
+    assert_eq!(hash_a, second.identity_hash(c));
+}
+
+fn check_heap_stats_follow_allocation_and_collection(heap: &mut dyn Heap) {
+    let mut repo = SharedKlassRepo::of();
+    let node = node_klass(&mut repo);
+    assert_eq!(HeapStats::of(), *heap.stats());
+
+    let a = heap.allocate_obj(&node);
+    let garbage = heap.allocate_int_arr(&prim_arr_klass('I'), 3).unwrap();
+    let node_size = heap.get_obj(a).shallow_size() as u64;
+    // Header, length and three ints
+    assert_eq!(32, heap.get_obj(garbage).shallow_size());
+    assert_eq!(0, node_size % 8);
+
+    let stats = heap.stats().clone();
+    assert_eq!(node_size + 32, stats.allocated_bytes);
+    assert_eq!(node_size + 32, stats.used_bytes);
+    assert_eq!(stats.committed_bytes - stats.used_bytes, stats.free_bytes());
+
+    assert_eq!(1, heap.collect(&[a]));
+    let stats = heap.stats().clone();
+    assert_eq!(node_size + 32, stats.allocated_bytes);
+    assert_eq!(node_size, stats.used_bytes);
+
+    // Growing past what is committed doubles it, and it never shrinks again
+    let big = heap.allocate_prim_arr(&prim_arr_klass('J'), INITIAL_COMMITTED_BYTES as i32 / 8).unwrap();
+    assert_eq!(2 * INITIAL_COMMITTED_BYTES, heap.stats().committed_bytes);
+    heap.collect(&[a]);
+    assert!(!heap.is_live(big));
+    assert_eq!(node_size, heap.stats().used_bytes);
+    assert_eq!(2 * INITIAL_COMMITTED_BYTES, heap.stats().committed_bytes);
+}
+
+#[test]
+fn mark_sweep_keeps_heap_stats() {
+    check_heap_stats_follow_allocation_and_collection(&mut SharedSimpleHeap::of());
+}
+
+#[test]
+fn copying_keeps_heap_stats() {
+    check_heap_stats_follow_allocation_and_collection(&mut CopyingHeap::of());
+}
+
+// A million stores was quadratic when each one copied the array
+fn check_array_stores_are_in_place(heap: &mut dyn Heap) {
+    let size = 1_000_000;
+    let arr = heap.allocate_prim_arr(&prim_arr_klass('I'), size).unwrap();
+    let stats = heap.stats().clone();
+    for i in 0..size {
+        heap.array_store(arr, i, JvmValue::Int(i));
+    }
+    let sum: i64 = (0..size).map(|i| heap.get_obj(arr).array_load(i).as_int().unwrap() as i64).sum();
+    assert_eq!(499_999_500_000, sum);
+    assert_eq!(stats, *heap.stats());
+}
+
+#[test]
+fn mark_sweep_stores_arrays_in_place() {
+    check_array_stores_are_in_place(&mut SharedSimpleHeap::of());
+}
+
+#[test]
+fn copying_stores_arrays_in_place() {
+    check_array_stores_are_in_place(&mut CopyingHeap::of());
+}
+
+// Nothing is reserved for an array that can't exist
+fn check_negative_array_sizes_are_raised(heap: &mut dyn Heap) {
+    let mut repo = SharedKlassRepo::of();
+    let node = node_klass(&mut repo);
+    let negative = |size: i32| Err(VmException::of("java/lang/NegativeArraySizeException", size.to_string()));
+    assert_eq!(negative(-1), heap.allocate_int_arr(&prim_arr_klass('I'), -1));
+    assert_eq!(negative(-2), heap.allocate_prim_arr(&prim_arr_klass('J'), -2));
+    assert_eq!(negative(-3), heap.allocate_obj_arr(&node, -3));
+    assert_eq!(0, heap.live_count());
+}
+
+#[test]
+fn mark_sweep_raises_negative_array_sizes() {
+    check_negative_array_sizes_are_raised(&mut SharedSimpleHeap::of());
+}
+
+#[test]
+fn copying_raises_negative_array_sizes() {
+    check_negative_array_sizes_are_raised(&mut CopyingHeap::of());
+}
+
+#[test]
+fn heap_stats_respect_limits() {
+    let mut stats = HeapStats::of();
+    stats.set_limits(1000, 3000);
+    assert_eq!(1000, stats.committed_bytes);
+    assert!(stats.has_room_for(3000));
+    assert!(!stats.has_room_for(3001));
+
+    stats.allocated(1200);
+    assert_eq!(2000, stats.committed_bytes);
+    // Growth stops at the maximum
+    stats.allocated(1200);
+    assert_eq!(3000, stats.committed_bytes);
+    assert_eq!(600, stats.free_bytes());
+    assert!(!stats.has_room_for(601));
+
+    stats.collected(0);
+    assert_eq!(3000, stats.committed_bytes);
+    assert_eq!(2400, stats.allocated_bytes);
+}
+
+#[test]
+#[should_panic(expected = "Initial heap size set to a larger value than the maximum heap size")]
+fn heap_stats_reject_initial_over_max() {
+    HeapStats::of().set_limits(2, 1);
+}
+
+#[test]
+fn decodes_modified_utf8() {
+    // NUL takes two bytes, and U+1F600 is written as its two surrogates
+    let bytes = [0x61, 0xc0, 0x80, 0xc3, 0xa9, 0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80];
+    assert_eq!(Ok(vec![0x61, 0, 0xe9, 0xd83d, 0xde00]), decode_modified_utf8(&bytes));
+    assert!(decode_modified_utf8(&[0x61, 0x00]).is_err());
+    assert!(decode_modified_utf8(&[0xf0, 0x9f, 0x98, 0x80]).is_err());
+    assert!(decode_modified_utf8(&[0xc3]).is_err());
+}
+
+#[test]
+fn field_offsets_are_slots() {
+    let k = parse_test_klass("gc/Node");
+    let next = k.get_instance_field_by_name_and_desc(&"Node.next:LNode;".to_string()).unwrap();
+    let value = k.get_instance_field_by_name_and_desc(&"Node.value:I".to_string()).unwrap();
+    // The static field declared first doesn't take up a slot
+    assert_eq!(0, next.get_offset());
+    assert_eq!(1, value.get_offset());
+    assert_eq!(k.get_instance_field_offset(value), value.get_offset() as usize);
+}
+
+#[test]
+fn weak_refs_are_cleared_by_collection() {
+    let mut repo = SharedKlassRepo::of();
+    let node = node_klass(&mut repo);
+    let held = repo.heap_mut().allocate_obj(&node);
+    let dropped = repo.heap_mut().allocate_obj(&node);
+    let weak_held = repo.new_weak_ref(held);
+    let weak_dropped = repo.new_weak_ref(dropped);
+    assert_eq!(Some(dropped), repo.weak_ref_get(weak_dropped));
+
+    // Weak references alone don't keep anything alive
+    repo.push_frame(vec![held]);
+    assert_eq!(1, repo.collect_garbage());
+    assert_eq!(Some(held), repo.weak_ref_get(weak_held));
+    assert_eq!(None, repo.weak_ref_get(weak_dropped));
+
+    repo.drop_weak_ref(weak_held);
+    assert_eq!(None, repo.weak_ref_get(weak_held));
+    repo.pop_frame();
+}
+
+#[test]
+fn parses_bootstrap_methods() {
+    let k = parse_test_klass("indy/Lambdas");
+    let bsms = k.get_bootstrap_methods();
+    assert_eq!(15, bsms.len());
+
+    // The metafactory gets the sam type, the implementation and the instantiated type
+    assert!(k.cp_as_string(bsms[0].method_idx).starts_with("java/lang/invoke/LambdaMetafactory.metafactory:"));
+    assert_eq!(3, bsms[0].arg_idxs.len());
+    assert_eq!("(II)I", k.cp_as_string(bsms[0].arg_idxs[0]));
+    assert_eq!("Lambdas.lambda$nonCapturing$0:(II)I", k.cp_as_string(bsms[0].arg_idxs[1]));
+
+    // The recipe, then the constant it refers to
+    let last = &bsms[14];
+    assert!(k.cp_as_string(last.method_idx).starts_with("java/lang/invoke/StringConcatFactory.makeConcatWithConstants:"));
+    assert_eq!(2, last.arg_idxs.len());
+}
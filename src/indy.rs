@@ -0,0 +1,510 @@
+// INVOKEDYNAMIC. There is no java.lang.invoke to run bootstrap methods with,
+// so the VM knows the ones javac emits and links their call sites itself
+use std::fmt::{Display, LowerExp};
+
+use ocelotter_runtime::constant_pool::*;
+use ocelotter_runtime::interp_stack::InterpEvalStack;
+use ocelotter_runtime::klass_repo::{CallSite, SharedKlassRepo};
+use ocelotter_runtime::object::OtObj;
+use ocelotter_runtime::otfield::OtField;
+use ocelotter_runtime::otklass::OtKlass;
+use ocelotter_runtime::otmethod::OtMethod;
+use ocelotter_runtime::*;
+
+use crate::{exec_method, opcode};
+
+// altMetafactory flags that add to what the lambda klass implements
+const FLAG_MARKERS: i32 = 1 << 1;
+const FLAG_BRIDGES: i32 = 1 << 2;
+
+// Links the call site on first use, then runs it. Callers must have published
+// their roots, as the call site's args are still on the stack
+pub fn invoke_dynamic(
+    repo: &mut SharedKlassRepo,
+    current_klass: &OtKlass,
+    cp_lookup: u16,
+    eval: &mut InterpEvalStack,
+) -> Result<(), Thrown> {
+    let (bsm_idx, nt_idx) = match current_klass.lookup_cp(cp_lookup) {
+        CpEntry::InvokeDynamic(indy) => (indy.bsm_idx, indy.nt_idx),
+        _ => panic!(
+            "Non-invokedynamic found in {} at CP index {}",
+            current_klass.get_name(),
+            cp_lookup
+        ),
+    };
+    let name_desc = current_klass.cp_as_string(nt_idx);
+    let (name, desc) = name_desc.split_once(':').unwrap();
+
+    let site = match repo.call_site(current_klass, cp_lookup) {
+        Some(site) => site,
+        None => {
+            let site = link(repo, current_klass, bsm_idx, name, desc);
+            repo.link_call_site(current_klass, cp_lookup, site.clone());
+            site
+        }
+    };
+    match site {
+        CallSite::Lambda { klass_id } => new_lambda(repo, klass_id, eval),
+        CallSite::Concat { recipe, constants } => concat(repo, desc, &recipe, &constants, eval),
+    }
+}
+
+// Does what the bootstrap method would, for the ones the VM knows
+fn link(repo: &mut SharedKlassRepo, klass: &OtKlass, bsm_idx: u16, name: &str, desc: &str) -> CallSite {
+    let bsm = klass.get_bootstrap_methods().get(bsm_idx as usize).cloned().unwrap_or_else(|| {
+        panic!("java/lang/BootstrapMethodError: No bootstrap method {} in {}", bsm_idx, klass.get_name())
+    });
+    let bsm_name_desc = klass.cp_as_string(bsm.method_idx);
+    let (bsm_name, _) = bsm_name_desc.split_once(':').unwrap();
+    match bsm_name {
+        "java/lang/invoke/LambdaMetafactory.metafactory" | "java/lang/invoke/LambdaMetafactory.altMetafactory" => {
+            CallSite::Lambda { klass_id: spin_lambda_klass(repo, klass, &bsm, name, desc) }
+        }
+        "java/lang/invoke/StringConcatFactory.makeConcatWithConstants" => {
+            let recipe = static_arg_chars(klass, bsm.arg_idxs[0]);
+            let constants = bsm.arg_idxs[1..].iter().map(|idx| static_arg_chars(klass, *idx)).collect();
+            CallSite::Concat { recipe, constants }
+        }
+        // Every arg, in order, with nothing in between
+        "java/lang/invoke/StringConcatFactory.makeConcat" => CallSite::Concat {
+            recipe: vec![1; desc_types(desc).0.len()],
+            constants: Vec::new(),
+        },
+        _ => panic!(
+            "java/lang/BootstrapMethodError: Unsupported bootstrap method {} for {}:{} in {}",
+            bsm_name_desc,
+            name,
+            desc,
+            klass.get_name()
+        ),
+    }
+}
+
+fn static_arg_chars(klass: &OtKlass, idx: u16) -> Vec<u16> {
+    let s = match klass.lookup_cp(idx) {
+        CpEntry::String(s) => klass.cp_as_string(s.0),
+        CpEntry::Integer(i) => i.to_string(),
+        CpEntry::Long(l) => l.to_string(),
+        CpEntry::Float(f) => java_fp(f, f as f64),
+        CpEntry::Double(d) => java_fp(d, d),
+        entry => panic!(
+            "java/lang/BootstrapMethodError: Unsupported constant {} in {} at CP index {}",
+            entry.name(),
+            klass.get_name(),
+            idx
+        ),
+    };
+    s.encode_utf16().collect()
+}
+
+fn static_arg_int(klass: &OtKlass, idx: u16) -> i32 {
+    match klass.lookup_cp(idx) {
+        CpEntry::Integer(i) => i,
+        entry => panic!(
+            "java/lang/BootstrapMethodError: Expected Integer, found {} in {} at CP index {}",
+            entry.name(),
+            klass.get_name(),
+            idx
+        ),
+    }
+}
+
+// The lambda klass implements the interface the call site returns, with a
+// field for each arg the call site captures. Its sam method loads the fields
+// and its own args and hands them all to the implementation method, e.g.
+//   ALOAD_0; GETFIELD arg$1; ILOAD_1; INVOKESTATIC Caller.lambda$0:(II)I; IRETURN
+// Static args are samMethodType, implMethod, instantiatedMethodType and then,
+// for altMetafactory, flags, markers and bridges
+fn spin_lambda_klass(repo: &mut SharedKlassRepo, caller: &OtKlass, bsm: &BootstrapMethod, sam_name: &str, desc: &str) -> usize {
+    let args = &bsm.arg_idxs;
+    let sam_desc = caller.cp_as_string(args[0]);
+    let (captured, iface) = desc_types(desc);
+    let iface = klass_name_of_type(&iface);
+
+    let mut interfaces = vec![iface];
+    let mut sam_descs = vec![sam_desc.clone()];
+    if args.len() > 3 {
+        let flags = static_arg_int(caller, args[3]);
+        let mut next = 4;
+        if flags & FLAG_MARKERS != 0 {
+            let count = static_arg_int(caller, args[next]) as usize;
+            interfaces.extend(args[next + 1..next + 1 + count].iter().map(|idx| caller.cp_as_string(*idx)));
+            next += 1 + count;
+        }
+        if flags & FLAG_BRIDGES != 0 {
+            let count = static_arg_int(caller, args[next]) as usize;
+            sam_descs.extend(args[next + 1..next + 1 + count].iter().map(|idx| caller.cp_as_string(*idx)));
+        }
+    }
+
+    let (kind, impl_ref_idx) = match caller.lookup_cp(args[1]) {
+        CpEntry::MethodHandle(mh) => (mh.kind, mh.ref_idx),
+        entry => panic!(
+            "java/lang/BootstrapMethodError: Expected MethodHandle, found {} in {} at CP index {}",
+            entry.name(),
+            caller.get_name(),
+            args[1]
+        ),
+    };
+    let (impl_clz_idx, impl_nt_idx, impl_is_iface) = match caller.lookup_cp(impl_ref_idx) {
+        CpEntry::MethodRef(mr) => (mr.clz_idx, mr.nt_idx, false),
+        CpEntry::InterfaceMethodRef(imr) => (imr.clz_idx, imr.nt_idx, true),
+        entry => panic!(
+            "java/lang/invoke/LambdaConversionException: Unsupported implementation {} in {} at CP index {}",
+            entry.name(),
+            caller.get_name(),
+            impl_ref_idx
+        ),
+    };
+    let impl_klass = caller.cp_as_string(impl_clz_idx);
+    let impl_name_desc = caller.cp_as_string(impl_nt_idx);
+    let (impl_name, impl_desc) = impl_name_desc.split_once(':').unwrap();
+
+    // What the implementation takes and gives, with any receiver as its first arg
+    let (mut impl_params, mut impl_ret) = desc_types(impl_desc);
+    match kind {
+        REF_INVOKESTATIC => {}
+        REF_INVOKEVIRTUAL | REF_INVOKEINTERFACE | REF_INVOKESPECIAL => {
+            impl_params.insert(0, format!("L{};", impl_klass))
+        }
+        REF_NEWINVOKESPECIAL => impl_ret = format!("L{};", impl_klass),
+        _ => panic!(
+            "java/lang/invoke/LambdaConversionException: Unsupported method handle kind {} for {}",
+            kind, impl_name_desc
+        ),
+    }
+
+    let lambda_name = format!("{}$$Lambda${}", caller.get_name(), repo.call_site_count());
+    let mut cp = CpBuilder::of();
+    let this_idx = cp.class(&lambda_name);
+
+    let mut fields = Vec::new();
+    let mut field_refs = Vec::new();
+    for (i, f_desc) in captured.iter().enumerate() {
+        let f_name = format!("arg${}", i + 1);
+        let name_idx = cp.utf8(&f_name);
+        let desc_idx = cp.utf8(f_desc);
+        fields.push(OtField::of(
+            i as u16,
+            lambda_name.clone(),
+            f_name.clone(),
+            f_desc.clone(),
+            ACC_PRIVATE | ACC_FINAL,
+            name_idx,
+            desc_idx,
+        ));
+        field_refs.push(cp.field_ref(this_idx, &f_name, f_desc));
+    }
+
+    let impl_clz_idx = cp.class(&impl_klass);
+    let impl_ref = cp.method_ref(impl_clz_idx, impl_name, impl_desc, impl_is_iface);
+
+    let mut methods = Vec::new();
+    for m_desc in sam_descs.iter() {
+        let (sam_params, sam_ret) = desc_types(m_desc);
+        let mut incoming = captured.clone();
+        incoming.extend(sam_params.iter().cloned());
+        check_convertible(&incoming, &impl_params, &sam_ret, &impl_ret, &impl_name_desc);
+
+        let mut code = Vec::new();
+        if kind == REF_NEWINVOKESPECIAL {
+            code.push(opcode::NEW);
+            code.extend_from_slice(&impl_clz_idx.to_be_bytes());
+            code.push(opcode::DUP);
+        }
+        for field_ref in field_refs.iter() {
+            code.push(opcode::ALOAD_0);
+            code.push(opcode::GETFIELD);
+            code.extend_from_slice(&field_ref.to_be_bytes());
+        }
+        let mut slot: u16 = 1;
+        for param in sam_params.iter() {
+            if slot > u8::MAX as u16 {
+                code.push(opcode::WIDE);
+                code.push(load_op(param));
+                code.extend_from_slice(&slot.to_be_bytes());
+            } else {
+                code.push(load_op(param));
+                code.push(slot as u8);
+            }
+            slot += type_width(param);
+        }
+        match kind {
+            REF_INVOKESTATIC => code.push(opcode::INVOKESTATIC),
+            REF_INVOKEVIRTUAL => code.push(opcode::INVOKEVIRTUAL),
+            REF_INVOKEINTERFACE => code.push(opcode::INVOKEINTERFACE),
+            _ => code.push(opcode::INVOKESPECIAL),
+        }
+        code.extend_from_slice(&impl_ref.to_be_bytes());
+        if kind == REF_INVOKEINTERFACE {
+            // The count is in slots and includes the receiver
+            let count: u16 = impl_params.iter().map(|p| type_width(p)).sum();
+            code.push(count as u8);
+            code.push(0);
+        }
+        // A void sam drops whatever the implementation returns - wide values
+        // take a single entry on the eval stack
+        if sam_ret == "V" && impl_ret != "V" {
+            code.push(opcode::POP);
+        }
+        code.push(return_op(&sam_ret));
+
+        let name_idx = cp.utf8(sam_name);
+        let desc_idx = cp.utf8(m_desc);
+        let mut m = OtMethod::of(lambda_name.clone(), sam_name.to_string(), m_desc.clone(), ACC_PUBLIC, name_idx, desc_idx);
+        m.set_code(code);
+        m.set_max_locals(slot);
+        methods.push(m);
+    }
+
+    let mut k = OtKlass::of(
+        lambda_name.clone(),
+        "java/lang/Object".to_string(),
+        ACC_FINAL | ACC_SUPER | ACC_SYNTHETIC,
+        &cp.entries,
+        &methods,
+        &fields,
+    );
+    k.set_interfaces(interfaces);
+    if let Err(ex) = repo.define_klass(caller.get_loader(), &k) {
+        panic!("java/lang/BootstrapMethodError: {}: {}", ex.klass_name, ex.message);
+    }
+    match repo.find_klass_in(caller.get_loader(), &lambda_name) {
+        Some(k) => k.get_id(),
+        None => panic!("Lambda klass {} not found after definition", lambda_name),
+    }
+}
+
+// Boxing, unboxing and widening are left to the compiler - each arg must
+// already be what the implementation takes
+fn check_convertible(incoming: &[String], impl_params: &[String], sam_ret: &str, impl_ret: &str, impl_name_desc: &str) {
+    let same_kind = |from: &str, to: &str| {
+        if is_reference(from) || is_reference(to) {
+            is_reference(from) && is_reference(to)
+        } else {
+            from == to
+        }
+    };
+    let args_ok = incoming.len() == impl_params.len() && incoming.iter().zip(impl_params).all(|(f, t)| same_kind(f, t));
+    let ret_ok = sam_ret == "V" || same_kind(impl_ret, sam_ret);
+    if !args_ok || !ret_ok {
+        panic!(
+            "java/lang/invoke/LambdaConversionException: Cannot convert ({}){} to {}",
+            incoming.join(""),
+            sam_ret,
+            impl_name_desc
+        );
+    }
+}
+
+// Each call makes a new lambda, holding the args on top of the stack
+fn new_lambda(repo: &mut SharedKlassRepo, klass_id: usize, eval: &mut InterpEvalStack) -> Result<(), Thrown> {
+    let klass = repo.lookup_klass_by_id(klass_id);
+    let fields = klass.get_instance_fields();
+    repo.safepoint(OtObj::obj_size(fields.len()) as u64).map_err(|ex| repo.new_exception(&ex))?;
+    let obj_id = repo.heap_mut().allocate_obj(&klass);
+    for f in fields.into_iter().rev() {
+        repo.heap().put_field(obj_id, f, eval.pop());
+    }
+    eval.push(JvmValue::ObjRef(obj_id));
+    Ok(())
+}
+
+// The args are turned into strings while they are still on the stack, as an
+// object's toString() can allocate
+fn concat(
+    repo: &mut SharedKlassRepo,
+    desc: &str,
+    recipe: &[u16],
+    constants: &[Vec<u16>],
+    eval: &mut InterpEvalStack,
+) -> Result<(), Thrown> {
+    let (params, _) = desc_types(desc);
+    let mut parts = Vec::new();
+    for (i, param) in params.iter().enumerate() {
+        parts.push(stringify(repo, param, eval.peek(params.len() - 1 - i))?);
+    }
+    for _ in params.iter() {
+        eval.pop();
+    }
+
+    let mut parts = parts.into_iter();
+    let mut constants = constants.iter();
+    let mut chars = Vec::new();
+    for c in recipe {
+        match c {
+            1 => chars.extend(parts.next().expect("Concat recipe has more args than the call site")),
+            2 => chars.extend(constants.next().expect("Concat recipe has more constants than the call site")),
+            c => chars.push(*c),
+        }
+    }
+    repo.safepoint(repo.string_size(chars.len())).map_err(|ex| repo.new_exception(&ex))?;
+    let obj_id = repo.new_string(&chars);
+    eval.push(JvmValue::ObjRef(obj_id));
+    Ok(())
+}
+
+// What String.valueOf() gives for a value of type desc
+fn stringify(repo: &mut SharedKlassRepo, desc: &str, v: JvmValue) -> Result<Vec<u16>, Thrown> {
+    let s = match v {
+        JvmValue::Boolean(b) => b.to_string(),
+        JvmValue::Byte(b) => b.to_string(),
+        JvmValue::Short(s) => s.to_string(),
+        JvmValue::Char(c) => c.to_string(),
+        // Booleans and chars are ints once loaded
+        JvmValue::Int(i) if desc == "Z" => (i != 0).to_string(),
+        JvmValue::Int(i) if desc == "C" => return Ok(vec![i as u16]),
+        JvmValue::Int(i) => i.to_string(),
+        JvmValue::Long(l) => l.to_string(),
+        JvmValue::Float(f) => java_fp(f, f as f64),
+        JvmValue::Double(d) => java_fp(d, d),
+        JvmValue::ObjRef(0) => "null".to_string(),
+        JvmValue::ObjRef(obj_id) if desc == "Ljava/lang/String;" => return Ok(repo.string_chars(obj_id)),
+        JvmValue::ObjRef(obj_id) => return to_string(repo, obj_id),
+    };
+    Ok(s.encode_utf16().collect())
+}
+
+// Calls the toString() the object's klass has or inherits
+fn to_string(repo: &mut SharedKlassRepo, obj_id: usize) -> Result<Vec<u16>, Thrown> {
+    let mut klass = repo.lookup_klass_by_id(repo.heap().get_obj(obj_id).get_klassid());
+    let meth = loop {
+        let fq_name_desc = format!("{}.toString:()Ljava/lang/String;", klass.get_name());
+        if let Some(m) = klass.get_method_by_name_and_desc(&fq_name_desc) {
+            break m.clone();
+        }
+        klass = repo.lookup_klass_in(klass.get_loader(), &klass.get_super_name());
+    };
+    let mut vars = InterpLocalVars::of(meth.get_local_var_size());
+    vars.store(0, JvmValue::ObjRef(obj_id));
+    match exec_method(repo, &meth, &mut vars)? {
+        Some(JvmValue::ObjRef(0)) => Ok("null".encode_utf16().collect()),
+        Some(JvmValue::ObjRef(s_id)) => Ok(repo.string_chars(s_id)),
+        other => panic!("{} returned {:?}", meth, other),
+    }
+}
+
+// What Double.toString() and Float.toString() give. Rust's Display and
+// LowerExp give the same shortest digits, so only the layout differs
+fn java_fp<T: Display + LowerExp>(x: T, val: f64) -> String {
+    if val.is_nan() {
+        "NaN".to_string()
+    } else if val.is_infinite() {
+        if val > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if val == 0.0 || (1e-3..1e7).contains(&val.abs()) {
+        let s = format!("{}", x);
+        if s.contains('.') { s } else { s + ".0" }
+    } else {
+        let s = format!("{:e}", x);
+        let (mantissa, exponent) = s.split_once('e').unwrap();
+        if mantissa.contains('.') {
+            format!("{}E{}", mantissa, exponent)
+        } else {
+            format!("{}.0E{}", mantissa, exponent)
+        }
+    }
+}
+
+// The param types of a method descriptor, and its return type
+fn desc_types(desc: &str) -> (Vec<String>, String) {
+    let (params, ret) = desc[1..].split_once(')').unwrap();
+    let mut out = Vec::new();
+    let mut rest = params;
+    while !rest.is_empty() {
+        let dims = rest.len() - rest.trim_start_matches('[').len();
+        let end = match rest.as_bytes()[dims] {
+            b'L' => dims + rest[dims..].find(';').unwrap() + 1,
+            _ => dims + 1,
+        };
+        out.push(rest[..end].to_string());
+        rest = &rest[end..];
+    }
+    (out, ret.to_string())
+}
+
+fn klass_name_of_type(desc: &str) -> String {
+    match desc.strip_prefix('L').and_then(|d| d.strip_suffix(';')) {
+        Some(name) => name.to_string(),
+        None => desc.to_string(),
+    }
+}
+
+fn is_reference(desc: &str) -> bool {
+    desc.starts_with('L') || desc.starts_with('[')
+}
+
+fn type_width(desc: &str) -> u16 {
+    match desc {
+        "J" | "D" => 2,
+        _ => 1,
+    }
+}
+
+fn load_op(desc: &str) -> u8 {
+    match desc {
+        "J" => opcode::LLOAD,
+        "F" => opcode::FLOAD,
+        "D" => opcode::DLOAD,
+        d if is_reference(d) => opcode::ALOAD,
+        _ => opcode::ILOAD,
+    }
+}
+
+fn return_op(desc: &str) -> u8 {
+    match desc {
+        "V" => opcode::RETURN,
+        "J" => opcode::LRETURN,
+        "F" => opcode::FRETURN,
+        "D" => opcode::DRETURN,
+        d if is_reference(d) => opcode::ARETURN,
+        _ => opcode::IRETURN,
+    }
+}
+
+// The constant pool of a lambda klass. Entry 0 is unused, as in a parsed klass
+struct CpBuilder {
+    entries: Vec<CpEntry>,
+}
+
+impl CpBuilder {
+    fn of() -> CpBuilder {
+        CpBuilder {
+            entries: vec![CpEntry::Integer(0)],
+        }
+    }
+
+    fn add(&mut self, entry: CpEntry) -> u16 {
+        self.entries.push(entry);
+        (self.entries.len() - 1) as u16
+    }
+
+    fn utf8(&mut self, s: &str) -> u16 {
+        self.add(CpEntry::Utf8(s.to_string()))
+    }
+
+    fn class(&mut self, name: &str) -> u16 {
+        let name_idx = self.utf8(name);
+        self.add(CpEntry::Class(ClassRef(name_idx)))
+    }
+
+    fn name_and_type(&mut self, name: &str, desc: &str) -> u16 {
+        let name_idx = self.utf8(name);
+        let desc_idx = self.utf8(desc);
+        self.add(CpEntry::NameAndType(NameAndType::new(name_idx, desc_idx)))
+    }
+
+    fn field_ref(&mut self, clz_idx: u16, name: &str, desc: &str) -> u16 {
+        let nt_idx = self.name_and_type(name, desc);
+        self.add(CpEntry::FieldRef(FieldRef::new(clz_idx, nt_idx)))
+    }
+
+    fn method_ref(&mut self, clz_idx: u16, name: &str, desc: &str, is_iface: bool) -> u16 {
+        let nt_idx = self.name_and_type(name, desc);
+        if is_iface {
+            self.add(CpEntry::InterfaceMethodRef(InterfaceMethodRef::new(clz_idx, nt_idx)))
+        } else {
+            self.add(CpEntry::MethodRef(MethodRef::new(clz_idx, nt_idx)))
+        }
+    }
+}
@@ -3,55 +3,132 @@
 #![allow(unused_variables)]
 
 use ocelotter_runtime::constant_pool::*;
+use ocelotter_runtime::heap::Heap;
 use ocelotter_runtime::interp_stack::InterpEvalStack;
 use ocelotter_runtime::klass_repo::SharedKlassRepo;
+use ocelotter_runtime::object::OtObj;
 use ocelotter_runtime::otklass::OtKlass;
-use ocelotter_runtime::otmethod::OtMethod;
+use ocelotter_runtime::otmethod::{ExceptionHandler, OtMethod};
 use ocelotter_runtime::*;
 
+mod indy;
 mod opcode;
 
 pub fn exec_method(
     repo: &mut SharedKlassRepo,
     meth: &OtMethod,
     lvt: &mut InterpLocalVars,
-) -> Option<JvmValue> {
-    if meth.is_native() {
+) -> JvmResult {
+    if meth.is_native() || meth.is_intrinsic() {
         // Explicit type hint here to document the type of n_f
-        let n_f: fn(&InterpLocalVars) -> Option<JvmValue> = meth
+        let n_f: fn(&mut SharedKlassRepo, &InterpLocalVars) -> Option<JvmValue> = meth
             .get_native_code()
             .unwrap_or_else(|| panic!("Native code not found {}", meth.get_fq_name_desc()));
 
         // FIXME Parameter passing
-        n_f(lvt)
+        // The args are the native's handles on the heap
+        repo.push_frame(lvt.obj_refs());
+        let ret = n_f(repo, lvt);
+        repo.pop_frame();
+        match repo.take_pending_exception() {
+            Some(ex) => Err(repo.new_exception(&ex)),
+            None => Ok(ret),
+        }
     } else {
-        exec_bytecode_method(repo, meth.get_klass_name(), &meth.get_code(), lvt)
+        let frame_klass = repo.lookup_klass_for_method(meth);
+        exec_frame(
+            repo,
+            Some(frame_klass),
+            meth.get_loader(),
+            meth.get_klass_name(),
+            &meth.get_code(),
+            &meth.get_exception_table(),
+            lvt,
+        )
     }
 }
 
+// klass_name is resolved through loader, the defining loader of the method's klass.
+// Bare bytecode has no exception table, so anything it throws comes straight out
 pub fn exec_bytecode_method(
     repo: &mut SharedKlassRepo,
+    loader: usize,
+    klass_name: String,
+    instr: &[u8],
+    lvt: &mut InterpLocalVars,
+) -> JvmResult {
+    let frame_klass = repo.find_klass_in(loader, &klass_name);
+    exec_frame(repo, frame_klass, loader, klass_name, instr, &[], lvt)
+}
+
+fn exec_frame(
+    repo: &mut SharedKlassRepo,
+    frame_klass: Option<OtKlass>,
+    loader: usize,
     klass_name: String,
     instr: &[u8],
+    handlers: &[ExceptionHandler],
     lvt: &mut InterpLocalVars,
-) -> Option<JvmValue> {
+) -> JvmResult {
+    // A frame that completes abruptly is popped all the same - that is what
+    // unwinds it
+    repo.push_frame(lvt.obj_refs());
+    let ret = run_frame(repo, frame_klass, loader, klass_name, instr, handlers, lvt);
+    repo.pop_frame();
+    ret
+}
+
+// The references a frame holds are its GC roots - they are published to the
+// repo before anything that can allocate, i.e. invokes and NEW*
+fn publish_roots(repo: &mut SharedKlassRepo, eval: &InterpEvalStack, lvt: &InterpLocalVars) {
+    let mut roots = eval.obj_refs();
+    roots.extend(lvt.obj_refs());
+    repo.update_frame(roots);
+}
+
+// The constant pool used by a frame is that of frame_klass, the version of the
+// klass that instr was loaded with, so a frame is unaffected by redefinitions
+// that happen while it runs
+fn run_frame(
+    repo: &mut SharedKlassRepo,
+    frame_klass: Option<OtKlass>,
+    loader: usize,
+    klass_name: String,
+    instr: &[u8],
+    handlers: &[ExceptionHandler],
+    lvt: &mut InterpLocalVars,
+) -> JvmResult {
+    let current_klass = || -> OtKlass {
+        frame_klass
+            .clone()
+            .unwrap_or_else(|| panic!("No klass called {} found in repo", klass_name))
+    };
     let mut current = 0;
     let mut eval = InterpEvalStack::of();
 
     loop {
         // let my_klass_name = klass_name.clone();
+        let pc = current;
         let ins: u8 = *instr
             .get(current)
             .unwrap_or_else(|| panic!("Byte {} has no value", current));
 
         current += 1;
+        // Set when the instruction throws, whether by ATHROW or from a callee
+        let mut thrown: Option<Thrown> = None;
+        // Set when one of the VM's own checks fails, e.g. on a null reference
+        let mut raised: Option<VmException> = None;
 
         // dbg!(ins);
         match ins {
+            opcode::AALOAD => raised = array_load(repo.heap(), &mut eval, "A", current - 1).err(),
+
+            opcode::AASTORE => raised = aastore(repo, &mut eval, current - 1).err(),
+
             opcode::ACONST_NULL => eval.aconst_null(),
 
             opcode::ALOAD => {
-                eval.push(lvt.load(instr[current]));
+                eval.push(lvt.load(instr[current] as u16));
                 current += 1;
             }
             opcode::ALOAD_0 => eval.push(lvt.load(0)),
@@ -62,9 +139,34 @@ pub fn exec_bytecode_method(
 
             opcode::ALOAD_3 => eval.push(lvt.load(3)),
 
-            opcode::ARETURN => break Some(eval.pop()),
+            opcode::ANEWARRAY => {
+                let cp_lookup = ((instr[current] as u16) << 8) + instr[current + 1] as u16;
+                current += 2;
+                let component = cp_klass_name(&current_klass(), cp_lookup);
+                let count = eval.pop().as_int().unwrap_or_else(|| panic!("Not an int on the stack at {}", current - 3));
+                let allocated = check_array_size(repo, count).and_then(|()| {
+                    let array_klass = repo.array_klass(loader, &SharedKlassRepo::array_klass_name(&component))?;
+                    publish_roots(repo, &eval, lvt);
+                    repo.safepoint(OtObj::array_size('A', count) as u64)?;
+                    repo.heap_mut().allocate_obj_arr(&array_klass, count)
+                });
+                match allocated {
+                    Ok(arr_id) => eval.push(JvmValue::ObjRef(arr_id)),
+                    Err(ex) => raised = Some(ex),
+                }
+            }
+
+            opcode::ARETURN => break Ok(Some(eval.pop())),
+            opcode::ARRAYLENGTH => match pop_array_ref(&mut eval, current - 1) {
+                Ok(arrayid) => {
+                    let len = repo.heap().get_obj(arrayid).length();
+                    eval.push(JvmValue::Int(len));
+                }
+                Err(ex) => raised = Some(ex),
+            },
+
             opcode::ASTORE => {
-                lvt.store(instr[current], eval.pop());
+                lvt.store(instr[current] as u16, eval.pop());
                 current += 1;
             }
             opcode::ASTORE_0 => lvt.store(0, eval.pop()),
@@ -75,11 +177,25 @@ pub fn exec_bytecode_method(
 
             opcode::ASTORE_3 => lvt.store(3, eval.pop()),
 
+            opcode::ATHROW => match eval.pop() {
+                JvmValue::ObjRef(0) => raised = Some(null_pointer(format!("throw of null at {}", current - 1))),
+                JvmValue::ObjRef(v) => thrown = Some(Thrown(v)),
+                _ => panic!("Non-objref seen on stack during ATHROW at {}", current - 1),
+            },
+
+            opcode::BALOAD => raised = array_load(repo.heap(), &mut eval, "BZ", current - 1).err(),
+
+            opcode::BASTORE => raised = array_store(repo.heap_mut(), &mut eval, "BZ", current - 1).err(),
+
             opcode::BIPUSH => {
                 eval.iconst(instr[current] as i32);
                 current += 1;
             }
 
+            opcode::CALOAD => raised = array_load(repo.heap(), &mut eval, "C", current - 1).err(),
+
+            opcode::CASTORE => raised = array_store(repo.heap_mut(), &mut eval, "C", current - 1).err(),
+
             opcode::D2F => {
                 match eval.pop() {
                     JvmValue::Double(v) => eval.push(JvmValue::Float(v as f32)),
@@ -103,6 +219,10 @@ pub fn exec_bytecode_method(
 
             opcode::DADD => eval.dadd(),
 
+            opcode::DALOAD => raised = array_load(repo.heap(), &mut eval, "D", current - 1).err(),
+
+            opcode::DASTORE => raised = array_store(repo.heap_mut(), &mut eval, "D", current - 1).err(),
+
             opcode::DCMPG => eval.dcmpg(),
 
             opcode::DCMPL => eval.dcmpl(),
@@ -114,7 +234,7 @@ pub fn exec_bytecode_method(
             opcode::DDIV => eval.ddiv(),
 
             opcode::DLOAD => {
-                eval.push(lvt.load(instr[current]));
+                eval.push(lvt.load(instr[current] as u16));
                 current += 1;
             }
 
@@ -132,10 +252,10 @@ pub fn exec_bytecode_method(
 
             opcode::DREM => eval.drem(),
 
-            opcode::DRETURN => break Some(eval.pop()),
+            opcode::DRETURN => break Ok(Some(eval.pop())),
 
             opcode::DSTORE => {
-                lvt.store(instr[current], eval.pop());
+                lvt.store(instr[current] as u16, eval.pop());
                 current += 1;
             }
 
@@ -162,6 +282,10 @@ pub fn exec_bytecode_method(
 
             opcode::FADD => eval.fadd(),
 
+            opcode::FALOAD => raised = array_load(repo.heap(), &mut eval, "F", current - 1).err(),
+
+            opcode::FASTORE => raised = array_store(repo.heap_mut(), &mut eval, "F", current - 1).err(),
+
             opcode::FCMPG => eval.fcmpg(),
 
             opcode::FCMPL => eval.fcmpl(),
@@ -175,7 +299,7 @@ pub fn exec_bytecode_method(
             opcode::FDIV => eval.fdiv(),
 
             opcode::FLOAD => {
-                eval.push(lvt.load(instr[current]));
+                eval.push(lvt.load(instr[current] as u16));
                 current += 1;
             }
 
@@ -193,10 +317,10 @@ pub fn exec_bytecode_method(
 
             opcode::FREM => eval.frem(),
 
-            opcode::FRETURN => break Some(eval.pop()),
+            opcode::FRETURN => break Ok(Some(eval.pop())),
 
             opcode::FSTORE => {
-                lvt.store(instr[current], eval.pop());
+                lvt.store(instr[current] as u16, eval.pop());
                 current += 1;
             }
 
@@ -219,31 +343,42 @@ pub fn exec_bytecode_method(
                     JvmValue::ObjRef(v) => v,
                     _ => panic!("Not an object ref at {}", (current - 1)),
                 };
-                let heap = HEAP.lock().unwrap();
-                let obj = heap.get_obj(obj_id);
-                let getf = repo.lookup_instance_field(&klass_name, cp_lookup);
-
-                let ret = obj.get_field_value(getf.get_offset() as usize);
-                eval.push(ret);
+                match repo.lookup_instance_field(&current_klass(), cp_lookup) {
+                    Ok(getf) if obj_id == 0 => {
+                        raised = Some(null_pointer(format!("getfield of {} on null at {}", getf, current - 3)));
+                    }
+                    Ok(getf) => {
+                        let ret = repo.heap().get_obj(obj_id).get_field_value(getf.get_offset() as usize);
+                        eval.push(ret);
+                    }
+                    Err(ex) => raised = Some(ex),
+                }
             }
             opcode::GETSTATIC => {
                 let cp_lookup = ((instr[current] as u16) << 8) + instr[current + 1] as u16;
                 current += 2;
 
-                let getf = repo.lookup_static_field(&klass_name, cp_lookup).clone();
-                let klass = repo.lookup_klass(&getf.get_klass_name()).clone();
+                match repo.lookup_static_field(&current_klass(), cp_lookup) {
+                    Ok(getf) => {
+                        let klass = repo.lookup_klass_in(loader, &getf.get_klass_name()).clone();
 
-                let ret = klass.get_static(&getf);
-                eval.push(ret);
+                        let ret = klass.get_static(&getf);
+                        eval.push(ret);
+                    }
+                    Err(ex) => raised = Some(ex),
+                }
             }
+            // Offsets are signed and relative to the GOTO itself
             opcode::GOTO => {
-                current += ((instr[current] as usize) << 8) + instr[current + 1] as usize
+                let offset = (((instr[current] as u16) << 8) + instr[current + 1] as u16) as i16;
+                current = (current as isize - 1 + offset as isize) as usize;
             }
             opcode::GOTO_W => {
-                current += ((instr[current] as usize) << 24)
-                    + ((instr[current + 1] as usize) << 16)
-                    + ((instr[current + 2] as usize) << 8)
-                    + instr[current + 3] as usize
+                let offset = ((instr[current] as i32) << 24)
+                    + ((instr[current + 1] as i32) << 16)
+                    + ((instr[current + 2] as i32) << 8)
+                    + instr[current + 3] as i32;
+                current = (current as isize - 1 + offset as isize) as usize;
             }
 
             opcode::I2B => eval.i2b(),
@@ -260,50 +395,11 @@ pub fn exec_bytecode_method(
 
             opcode::IADD => eval.iadd(),
 
-            opcode::IALOAD => {
-                let pos_to_load = match eval.pop() {
-                    JvmValue::Int(v) => v,
-                    _ => panic!("Non-int seen on stack during IASTORE at {}", current - 1),
-                };
-                let arrayid = match eval.pop() {
-                    JvmValue::ObjRef(v) => v,
-                    _ => panic!("Non-objref seen on stack during IASTORE at {}", current - 1),
-                };
-                dbg!(arrayid);
-
-                let unwrapped_val = match HEAP.lock().unwrap().get_obj(arrayid) {
-                    ocelotter_runtime::object::OtObj::VmArrInt {
-                        id: _,
-                        mark: _,
-                        klassid: _,
-                        length: _,
-                        elements: elts,
-                    } => elts[pos_to_load as usize],
-                    _ => panic!("Non-int[] seen on stack during IASTORE at {}", current - 1),
-                };
-                eval.push(JvmValue::Int(unwrapped_val));
-            }
+            opcode::IALOAD => raised = array_load(repo.heap(), &mut eval, "I", current - 1).err(),
 
             opcode::IAND => eval.iand(),
 
-            opcode::IASTORE => {
-                let val_to_store = match eval.pop() {
-                    JvmValue::Int(v) => v,
-                    _ => panic!("Non-int seen on stack during IASTORE at {}", current - 1),
-                };
-                let pos_to_store = match eval.pop() {
-                    JvmValue::Int(v) => v,
-                    _ => panic!("Non-int seen on stack during IASTORE at {}", current - 1),
-                };
-                let obj_id = match eval.pop() {
-                    JvmValue::ObjRef(v) => v,
-                    _ => panic!("Non-objref seen on stack during IASTORE at {}", current - 1),
-                };
-
-                HEAP.lock()
-                    .unwrap()
-                    .iastore(obj_id, pos_to_store, val_to_store);
-            }
+            opcode::IASTORE => raised = array_store(repo.heap_mut(), &mut eval, "I", current - 1).err(),
 
             opcode::ICONST_0 => eval.iconst(0),
 
@@ -319,7 +415,7 @@ pub fn exec_bytecode_method(
 
             opcode::ICONST_M1 => eval.iconst(-1),
 
-            opcode::IDIV => eval.idiv(),
+            opcode::IDIV => raised = eval.idiv().err(),
 
             opcode::IF_ICMPEQ => {
                 let jump_to = ((instr[current] as usize) << 8) + instr[current + 1] as usize;
@@ -498,12 +594,12 @@ pub fn exec_bytecode_method(
                 };
             }
             opcode::IINC => {
-                lvt.iinc(instr[current], instr[current + 1]);
+                lvt.iinc(instr[current] as u16, instr[current + 1] as i8 as i16);
                 current += 2;
             }
 
             opcode::ILOAD => {
-                eval.push(lvt.load(instr[current]));
+                eval.push(lvt.load(instr[current] as u16));
                 current += 1
             }
 
@@ -519,40 +615,57 @@ pub fn exec_bytecode_method(
 
             opcode::INEG => eval.ineg(),
 
+            opcode::INVOKEDYNAMIC => {
+                let cp_lookup = ((instr[current] as u16) << 8) + instr[current + 1] as u16;
+                // Followed by two zero bytes
+                current += 4;
+                let current_klass = current_klass();
+                publish_roots(repo, &eval, lvt);
+                thrown = indy::invoke_dynamic(repo, &current_klass, cp_lookup, &mut eval).err();
+            }
+            opcode::INVOKEINTERFACE => {
+                let cp_lookup = ((instr[current] as u16) << 8) + instr[current + 1] as u16;
+                // The count and zero bytes add nothing to the descriptor
+                current += 4;
+                let current_klass = current_klass();
+                publish_roots(repo, &eval, lvt);
+                thrown = invoke_interface(repo, current_klass, cp_lookup, &mut eval).err();
+            }
             opcode::INVOKESPECIAL => {
                 let cp_lookup = ((instr[current] as u16) << 8) + instr[current + 1] as u16;
                 current += 2;
-                let current_klass = repo.lookup_klass(&klass_name).clone();
-                dispatch_invoke(repo, current_klass, cp_lookup, &mut eval, 1);
+                let current_klass = current_klass();
+                publish_roots(repo, &eval, lvt);
+                thrown = dispatch_invoke(repo, current_klass, cp_lookup, &mut eval).err();
             }
             opcode::INVOKESTATIC => {
                 let cp_lookup = ((instr[current] as u16) << 8) + instr[current + 1] as u16;
                 current += 2;
-                let current_klass = repo.lookup_klass(&klass_name).clone();
-                //                dbg!(cp_lookup);
-                let arg_count = current_klass.get_method_arg_count(cp_lookup);
-                dispatch_invoke(repo, current_klass, cp_lookup, &mut eval, arg_count);
+                let current_klass = current_klass();
+                publish_roots(repo, &eval, lvt);
+                thrown = dispatch_invoke(repo, current_klass, cp_lookup, &mut eval).err();
             }
             opcode::INVOKEVIRTUAL => {
                 // FIXME DOES NOT ACTUALLY DO VIRTUAL LOOKUP YET
                 let cp_lookup = ((instr[current] as u16) << 8) + instr[current + 1] as u16;
                 current += 2;
-                let current_klass = repo.lookup_klass(&klass_name).clone();
+                let current_klass = current_klass();
                 dbg!(current_klass.clone());
-                dispatch_invoke(repo, current_klass, cp_lookup, &mut eval, 1);
+                publish_roots(repo, &eval, lvt);
+                thrown = dispatch_invoke(repo, current_klass, cp_lookup, &mut eval).err();
             }
             opcode::IOR => eval.ior(),
 
-            opcode::IREM => eval.irem(),
+            opcode::IREM => raised = eval.irem().err(),
 
-            opcode::IRETURN => break Some(eval.pop()),
+            opcode::IRETURN => break Ok(Some(eval.pop())),
 
             opcode::ISHL => eval.ishl(),
 
             opcode::ISHR => eval.ishr(),
 
             opcode::ISTORE => {
-                lvt.store(instr[current], eval.pop());
+                lvt.store(instr[current] as u16, eval.pop());
                 current += 1;
             }
             opcode::ISTORE_0 => lvt.store(0, eval.pop()),
@@ -577,6 +690,10 @@ pub fn exec_bytecode_method(
 
             opcode::LADD => eval.ladd(),
 
+            opcode::LALOAD => raised = array_load(repo.heap(), &mut eval, "J", current - 1).err(),
+
+            opcode::LASTORE => raised = array_store(repo.heap_mut(), &mut eval, "J", current - 1).err(),
+
             opcode::LAND => eval.land(),
 
             opcode::LCMP => eval.lcmp(),
@@ -588,49 +705,34 @@ pub fn exec_bytecode_method(
             opcode::LDC => {
                 let cp_lookup = instr[current] as u16;
                 current += 1;
-                let current_klass = repo.lookup_klass(&klass_name).clone();
-
-                match current_klass.lookup_cp(cp_lookup) {
-                    // FIXME Actually look up the class object properly
-                    CpEntry::Class(_) => eval.aconst_null(),
-                    CpEntry::Double(dcon) => eval.dconst(dcon),
-                    CpEntry::Integer(icon) => eval.iconst(icon),
-                    // FIXME Actually look up the class object properly
-                    CpEntry::String(idx) => eval.aconst_null(),
-                    _ => panic!(
-                        "Non-handled entry found in LDC op {} at CP index {}",
-                        current_klass.get_name(),
-                        cp_lookup
-                    ),
-                }
+                raised = load_constant(repo, &current_klass(), cp_lookup, &mut eval, lvt).err();
+            }
+            opcode::LDC_W => {
+                let cp_lookup = ((instr[current] as u16) << 8) + instr[current + 1] as u16;
+                current += 2;
+                raised = load_constant(repo, &current_klass(), cp_lookup, &mut eval, lvt).err();
             }
             opcode::LDC2_W => {
                 let cp_lookup = ((instr[current] as u16) << 8) + instr[current + 1] as u16;
                 current += 2;
-                let current_klass = repo.lookup_klass(&klass_name).clone();
-
-                let entry: CpEntry = current_klass.lookup_cp(cp_lookup);
-                //                dbg!("Index: {} of type {}", cp_lookup, entry.name());
-                //                dbg!(current_klass.clone());
-                match entry {
-                    // FIXME Actually look up the class object properly
-                    CpEntry::Class(_) => eval.aconst_null(),
+                let current_klass = current_klass();
+
+                match current_klass.lookup_cp(cp_lookup) {
                     CpEntry::Double(dcon) => eval.dconst(dcon),
-                    CpEntry::Integer(icon) => eval.iconst(icon),
-                    // FIXME Actually look up the string object properly
-                    CpEntry::String(_) => eval.aconst_null(),
-                    _ => panic!(
-                        "Non-handled entry found in LDC op {} at CP index {}",
+                    CpEntry::Long(lcon) => eval.lconst(lcon),
+                    entry => panic!(
+                        "Non-handled entry {} found in LDC2_W op {} at CP index {}",
+                        entry.name(),
                         current_klass.get_name(),
                         cp_lookup
                     ),
                 }
             }
 
-            opcode::LDIV => eval.ldiv(),
+            opcode::LDIV => raised = eval.ldiv().err(),
 
             opcode::LLOAD => {
-                eval.push(lvt.load(instr[current]));
+                eval.push(lvt.load(instr[current] as u16));
                 current += 1
             }
 
@@ -646,18 +748,22 @@ pub fn exec_bytecode_method(
 
             opcode::LNEG => eval.lneg(),
 
+            opcode::LOOKUPSWITCH => {
+                let key = pop_switch_key(&mut eval, pc);
+                current = lookup_switch(instr, pc, key);
+            }
             opcode::LOR => eval.lor(),
 
-            opcode::LREM => eval.lrem(),
+            opcode::LREM => raised = eval.lrem().err(),
 
-            opcode::LRETURN => break Some(eval.pop()),
+            opcode::LRETURN => break Ok(Some(eval.pop())),
 
             opcode::LSHL => eval.lshl(),
 
             opcode::LSHR => eval.lshr(),
 
             opcode::LSTORE => {
-                lvt.store(instr[current], eval.pop());
+                lvt.store(instr[current] as u16, eval.pop());
                 current += 1;
             }
 
@@ -675,18 +781,46 @@ pub fn exec_bytecode_method(
 
             opcode::LXOR => eval.lxor(),
 
-            // FIXME TEMP
-            opcode::MONITORENTER => {
-                eval.pop();
-            }
-            // FIXME TEMP
-            opcode::MONITOREXIT => {
-                eval.pop();
+            // The lock count lives in the object's mark word
+            opcode::MONITORENTER => match pop_monitor_ref(&mut eval, current - 1) {
+                Ok(obj_id) if !repo.heap_mut().monitor_enter(obj_id) => {
+                    raised = Some(illegal_monitor_state(format!("monitor of {} entered too many times", obj_id)));
+                }
+                Ok(_) => (),
+                Err(ex) => raised = Some(ex),
+            },
+            opcode::MONITOREXIT => match pop_monitor_ref(&mut eval, current - 1) {
+                Ok(obj_id) if !repo.heap_mut().monitor_exit(obj_id) => {
+                    raised = Some(illegal_monitor_state(format!("monitor of {} is not held", obj_id)));
+                }
+                Ok(_) => (),
+                Err(ex) => raised = Some(ex),
+            },
+            opcode::MULTIANEWARRAY => {
+                let cp_lookup = ((instr[current] as u16) << 8) + instr[current + 1] as u16;
+                let dims = instr[current + 2] as usize;
+                current += 3;
+                let array_name = cp_klass_name(&current_klass(), cp_lookup);
+                let mut counts = Vec::with_capacity(dims);
+                for _ in 0..dims {
+                    let count = eval.pop().as_int().unwrap_or_else(|| panic!("Not an int on the stack at {}", current - 4));
+                    counts.insert(0, count);
+                }
+                let allocated = counts.iter().try_for_each(|c| check_array_size(repo, *c)).and_then(|()| {
+                    publish_roots(repo, &eval, lvt);
+                    repo.safepoint(multi_array_size(&array_name, &counts))?;
+                    new_multi_array(repo, loader, &array_name, &counts)
+                });
+                match allocated {
+                    Ok(arr_id) => eval.push(JvmValue::ObjRef(arr_id)),
+                    Err(ex) => raised = Some(ex),
+                }
             }
+
             opcode::NEW => {
                 let cp_lookup = ((instr[current] as u16) << 8) + instr[current + 1] as u16;
                 current += 2;
-                let current_klass = repo.lookup_klass(&klass_name).clone();
+                let current_klass = current_klass();
 
                 let alloc_klass_name = match current_klass.lookup_cp(cp_lookup) {
                     // FIXME Find class name from constant pool of the current class
@@ -698,33 +832,44 @@ pub fn exec_bytecode_method(
                     ),
                 };
                 //                dbg!(alloc_klass_name.clone());
-                let object_klass = repo.lookup_klass(&alloc_klass_name).clone();
-
-                let obj_id = HEAP.lock().unwrap().allocate_obj(&object_klass);
-                eval.push(JvmValue::ObjRef(obj_id));
+                let object_klass = repo.lookup_klass_in(loader, &alloc_klass_name).clone();
+
+                publish_roots(repo, &eval, lvt);
+                match repo.safepoint(OtObj::obj_size(object_klass.get_instance_fields().len()) as u64) {
+                    Ok(()) => {
+                        let obj_id = repo.heap_mut().allocate_obj(&object_klass);
+                        repo.register_finalizable(obj_id);
+                        eval.push(JvmValue::ObjRef(obj_id));
+                    }
+                    Err(ex) => raised = Some(ex),
+                }
             }
             opcode::NEWARRAY => {
                 let arr_type = instr[current];
                 current += 1;
 
-                // FIXME Other primitive array types needed
-                let arr_id = match arr_type {
-                    // boolean: 4
-                    // char: 5
-                    // float: 6
-                    // double: 7
-                    // byte: 8
-                    // short: 9
-                    // int: 10
-                    // long: 11
-                    10 => match eval.pop() {
-                        JvmValue::Int(arr_size) => HEAP.lock().unwrap().allocate_int_arr(arr_size),
-                        _ => panic!("Not an int on the stack at {}", (current - 1)),
-                    },
-                    _ => panic!("Unsupported primitive array type at {}", (current - 1)),
+                let elem_type = match arr_type {
+                    4 => 'Z',
+                    5 => 'C',
+                    6 => 'F',
+                    7 => 'D',
+                    8 => 'B',
+                    9 => 'S',
+                    10 => 'I',
+                    11 => 'J',
+                    _ => panic!("Illegal primitive array type {} at {}", arr_type, (current - 2)),
                 };
-
-                eval.push(JvmValue::ObjRef(arr_id));
+                let arr_size = eval.pop().as_int().unwrap_or_else(|| panic!("Not an int on the stack at {}", current - 2));
+                let allocated = check_array_size(repo, arr_size).and_then(|()| {
+                    publish_roots(repo, &eval, lvt);
+                    repo.safepoint(OtObj::array_size(elem_type, arr_size) as u64)?;
+                    let array_klass = repo.prim_array_klass(elem_type);
+                    repo.heap_mut().allocate_prim_arr(&array_klass, arr_size)
+                });
+                match allocated {
+                    Ok(arr_id) => eval.push(JvmValue::ObjRef(arr_id)),
+                    Err(ex) => raised = Some(ex),
+                }
             }
             opcode::NOP => (),
             opcode::POP => {
@@ -750,20 +895,28 @@ pub fn exec_bytecode_method(
                     _ => panic!("Not an object ref at {}", (current - 1)),
                 };
 
-                let putf = repo.lookup_instance_field(&klass_name, cp_lookup);
-
-                HEAP.lock().unwrap().put_field(obj_id, putf, val);
+                match repo.lookup_instance_field(&current_klass(), cp_lookup) {
+                    Ok(putf) if obj_id == 0 => {
+                        raised = Some(null_pointer(format!("putfield of {} on null at {}", putf, current - 3)));
+                    }
+                    Ok(putf) => repo.heap_mut().put_field(obj_id, putf, val),
+                    Err(ex) => raised = Some(ex),
+                }
             }
             opcode::PUTSTATIC => {
                 let cp_lookup = ((instr[current] as u16) << 8) + instr[current + 1] as u16;
                 current += 2;
 
-                let puts = repo.lookup_static_field(&klass_name, cp_lookup);
-                let klass = repo.lookup_klass(&puts.get_klass_name()).clone();
-
-                klass.put_static(&puts, eval.pop());
+                match repo.lookup_static_field(&current_klass(), cp_lookup) {
+                    Ok(puts) => repo.put_static(loader, &puts, eval.pop()),
+                    Err(ex) => raised = Some(ex),
+                }
             }
-            opcode::RETURN => break None,
+            opcode::RETURN => break Ok(None),
+            opcode::SALOAD => raised = array_load(repo.heap(), &mut eval, "S", current - 1).err(),
+
+            opcode::SASTORE => raised = array_store(repo.heap_mut(), &mut eval, "S", current - 1).err(),
+
             opcode::SIPUSH => {
                 let vtmp = ((instr[current] as i32) << 8) + instr[current + 1] as i32;
                 eval.iconst(vtmp);
@@ -775,13 +928,40 @@ pub fn exec_bytecode_method(
                 eval.push(val1);
                 eval.push(val2);
             }
+            opcode::TABLESWITCH => {
+                let index = pop_switch_key(&mut eval, pc);
+                current = table_switch(instr, pc, index);
+            }
             // Disallowed opcodes
-            opcode::BREAKPOINT => break Some(JvmValue::Boolean(false)),
-            opcode::IMPDEP1 => break Some(JvmValue::Boolean(false)),
-            opcode::IMPDEP2 => break Some(JvmValue::Boolean(false)),
-            opcode::JSR => break Some(JvmValue::Boolean(false)),
-            opcode::JSR_W => break Some(JvmValue::Boolean(false)),
-            opcode::RET => break Some(JvmValue::Boolean(false)),
+            opcode::BREAKPOINT => break Ok(Some(JvmValue::Boolean(false))),
+            opcode::IMPDEP1 => break Ok(Some(JvmValue::Boolean(false))),
+            opcode::IMPDEP2 => break Ok(Some(JvmValue::Boolean(false))),
+            opcode::JSR => break Ok(Some(JvmValue::Boolean(false))),
+            opcode::JSR_W => break Ok(Some(JvmValue::Boolean(false))),
+            opcode::RET => break Ok(Some(JvmValue::Boolean(false))),
+
+            // The index of the instruction it modifies is 16 bits, as is the
+            // constant of an IINC
+            opcode::WIDE => {
+                let op = instr[current];
+                let idx = ((instr[current + 1] as u16) << 8) + instr[current + 2] as u16;
+                current += 3;
+                match op {
+                    opcode::ILOAD | opcode::LLOAD | opcode::FLOAD | opcode::DLOAD | opcode::ALOAD => {
+                        eval.push(lvt.load(idx))
+                    }
+                    opcode::ISTORE | opcode::LSTORE | opcode::FSTORE | opcode::DSTORE | opcode::ASTORE => {
+                        lvt.store(idx, eval.pop())
+                    }
+                    opcode::IINC => {
+                        let incr = (((instr[current] as u16) << 8) + instr[current + 1] as u16) as i16;
+                        lvt.iinc(idx, incr);
+                        current += 2;
+                    }
+                    opcode::RET => break Ok(Some(JvmValue::Boolean(false))),
+                    _ => panic!("Illegal opcode byte: {} after WIDE at position {}. Stopping.", op, pc),
+                }
+            }
 
             _ => panic!(
                 "Illegal opcode byte: {} encountered at position {}. Stopping.",
@@ -789,6 +969,17 @@ pub fn exec_bytecode_method(
                 (current - 1)
             ),
         }
+
+        if let Some(ex) = raised {
+            publish_roots(repo, &eval, lvt);
+            thrown = Some(repo.new_exception(&ex));
+        }
+        if let Some(t) = thrown {
+            match catch(repo, &current_klass(), handlers, pc, t, &mut eval) {
+                Ok(handler_pc) => current = handler_pc,
+                Err(t) => break Err(t),
+            }
+        }
     }
 }
 
@@ -802,16 +993,274 @@ fn massage_to_int_and_compare(v1: JvmValue, v2: JvmValue, f: fn(i: i32, j: i32)
     }
 }
 
+fn pop_switch_key(eval: &mut InterpEvalStack, pc: usize) -> i32 {
+    match eval.pop() {
+        JvmValue::Int(i) => i,
+        JvmValue::Char(c) => c as i32,
+        JvmValue::Short(s) => s as i32,
+        JvmValue::Byte(b) => b as i32,
+        x => panic!("Non-int {} seen on stack for switch at {}", x, pc),
+    }
+}
+
+fn read_i32(instr: &[u8], at: usize) -> i32 {
+    i32::from_be_bytes([instr[at], instr[at + 1], instr[at + 2], instr[at + 3]])
+}
+
+// The operands of both switches start at the next multiple of 4 from the
+// start of the method, and every offset is relative to the switch itself
+fn switch_operands(pc: usize) -> usize {
+    (pc + 4) & !3
+}
+
+// default, low, high, then an offset for each index from low to high
+fn table_switch(instr: &[u8], pc: usize, index: i32) -> usize {
+    let at = switch_operands(pc);
+    let low = read_i32(instr, at + 4);
+    let high = read_i32(instr, at + 8);
+    let offset = if index < low || index > high {
+        read_i32(instr, at)
+    } else {
+        read_i32(instr, at + 12 + 4 * (index - low) as usize)
+    };
+    (pc as isize + offset as isize) as usize
+}
+
+// default, npairs, then (match, offset) pairs sorted by match
+fn lookup_switch(instr: &[u8], pc: usize, key: i32) -> usize {
+    let at = switch_operands(pc);
+    let npairs = read_i32(instr, at + 4) as usize;
+    let pairs = at + 8;
+    let (mut lo, mut hi) = (0, npairs);
+    let mut offset = read_i32(instr, at);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        let m = read_i32(instr, pairs + 8 * mid);
+        if m == key {
+            offset = read_i32(instr, pairs + 8 * mid + 4);
+            break;
+        } else if m < key {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    (pc as isize + offset as isize) as usize
+}
+
+fn cp_klass_name(klass: &OtKlass, cp_lookup: u16) -> String {
+    match klass.lookup_cp(cp_lookup) {
+        CpEntry::Class(c) => klass.cp_as_string(c.0),
+        _ => panic!("Non-class found in {} at CP index {}", klass.get_name(), cp_lookup),
+    }
+}
+
+// The longest array we will try to allocate, as for HotSpot
+const MAX_ARRAY_LENGTH: i32 = i32::MAX - 2;
+
+// Before allocating anything for an array of this size
+fn check_array_size(repo: &SharedKlassRepo, size: i32) -> Result<(), VmException> {
+    if size < 0 {
+        return Err(VmException::of("java/lang/NegativeArraySizeException", size.to_string()));
+    }
+    if size > MAX_ARRAY_LENGTH {
+        return Err(repo.out_of_memory("Requested array size exceeds VM limit"));
+    }
+    Ok(())
+}
+
+// Bytes taken up by the arrays new_multi_array makes - nothing is allocated
+// for the dimensions after a zero count
+fn multi_array_size(array_name: &str, counts: &[i32]) -> u64 {
+    let elem = &array_name[1..];
+    let elem_type = match elem.chars().next().unwrap() {
+        '[' | 'L' => 'A',
+        t => t,
+    };
+    let size = OtObj::array_size(elem_type, counts[0]) as u64;
+    match counts.len() {
+        1 => size,
+        _ => size.saturating_add((counts[0] as u64).saturating_mul(multi_array_size(elem, &counts[1..]))),
+    }
+}
+
+// The nested arrays of MULTIANEWARRAY - counts holds the length of each dimension
+// given, outermost first. Callers must have passed a safepoint, as the arrays
+// are unreachable until the outermost one is on the eval stack
+fn new_multi_array(repo: &mut SharedKlassRepo, loader: usize, array_name: &str, counts: &[i32]) -> Result<usize, VmException> {
+    let elem = array_name[1..].to_string();
+    let array_klass = repo.array_klass(loader, array_name)?;
+    if !elem.starts_with('[') && !elem.starts_with('L') {
+        return repo.heap_mut().allocate_prim_arr(&array_klass, counts[0]);
+    }
+    let arr_id = repo.heap_mut().allocate_obj_arr(&array_klass, counts[0])?;
+    if counts.len() > 1 {
+        for i in 0..counts[0] {
+            let sub_id = new_multi_array(repo, loader, &elem, &counts[1..])?;
+            repo.heap_mut().array_store(arr_id, i, JvmValue::ObjRef(sub_id));
+        }
+    }
+    Ok(arr_id)
+}
+
+// The one-word constants of LDC and LDC_W
+fn load_constant(
+    repo: &mut SharedKlassRepo,
+    current_klass: &OtKlass,
+    cp_lookup: u16,
+    eval: &mut InterpEvalStack,
+    lvt: &InterpLocalVars,
+) -> Result<(), VmException> {
+    match current_klass.lookup_cp(cp_lookup) {
+        // FIXME Actually look up the class object properly
+        CpEntry::Class(_) => eval.aconst_null(),
+        CpEntry::Integer(icon) => eval.iconst(icon),
+        CpEntry::Float(fcon) => eval.fconst(fcon),
+        CpEntry::String(idx) => {
+            let chars: Vec<u16> = current_klass.cp_as_string(idx.0).encode_utf16().collect();
+            // Only the first use of a literal allocates
+            let obj_id = match repo.find_interned(&chars) {
+                Some(obj_id) => obj_id,
+                None => {
+                    publish_roots(repo, eval, lvt);
+                    repo.safepoint(repo.string_size(chars.len()))?;
+                    repo.intern_string(&chars)
+                }
+            };
+            eval.push(JvmValue::ObjRef(obj_id));
+        }
+        entry => panic!(
+            "Non-handled entry {} found in LDC op {} at CP index {}",
+            entry.name(),
+            current_klass.get_name(),
+            cp_lookup
+        ),
+    }
+    Ok(())
+}
+
+fn null_pointer(message: String) -> VmException {
+    VmException::of("java/lang/NullPointerException", message)
+}
+
+fn illegal_monitor_state(message: String) -> VmException {
+    VmException::of("java/lang/IllegalMonitorStateException", message)
+}
+
+fn pop_array_ref(eval: &mut InterpEvalStack, pos: usize) -> Result<usize, VmException> {
+    match eval.pop() {
+        JvmValue::ObjRef(0) => Err(null_pointer(format!("array is null at {}", pos))),
+        JvmValue::ObjRef(v) => Ok(v),
+        _ => panic!("Non-objref seen on stack for array access at {}", pos),
+    }
+}
+
+fn pop_monitor_ref(eval: &mut InterpEvalStack, pos: usize) -> Result<usize, VmException> {
+    match eval.pop() {
+        JvmValue::ObjRef(0) => Err(null_pointer(format!("monitor is null at {}", pos))),
+        JvmValue::ObjRef(v) => Ok(v),
+        _ => panic!("Non-objref seen on stack for monitor at {}", pos),
+    }
+}
+
+// The checks every array access makes before the heap is touched
+fn check_array_access(heap: &dyn Heap, arrayid: usize, index: i32, elem_types: &str, pos: usize) -> Result<(), VmException> {
+    let (elem_type, length) = match heap.get_obj(arrayid) {
+        OtObj::VmObj { .. } => (None, 0),
+        arr => (Some(arr.elem_type()), arr.length()),
+    };
+    match elem_type {
+        None => panic!("Non-array seen on stack for array access at {}", pos),
+        Some(t) if !elem_types.contains(t) => {
+            panic!("{}[] seen on stack where {}[] expected at {}", t, elem_types, pos)
+        }
+        _ => (),
+    }
+    if index < 0 || index >= length {
+        return Err(VmException::of(
+            "java/lang/ArrayIndexOutOfBoundsException",
+            format!("Index {} out of bounds for length {}", index, length),
+        ));
+    }
+    Ok(())
+}
+
+// xALOAD - elem_types are the descriptor letters of the arrays the opcode works on
+fn array_load(heap: &dyn Heap, eval: &mut InterpEvalStack, elem_types: &str, pos: usize) -> Result<(), VmException> {
+    let index = eval.pop().as_int().unwrap_or_else(|| panic!("Non-int array index seen on stack at {}", pos));
+    let arrayid = pop_array_ref(eval, pos)?;
+    check_array_access(heap, arrayid, index, elem_types, pos)?;
+    let val = heap.get_obj(arrayid).array_load(index);
+    eval.push(val);
+    Ok(())
+}
+
+// xASTORE - as for array_load
+fn array_store(heap: &mut dyn Heap, eval: &mut InterpEvalStack, elem_types: &str, pos: usize) -> Result<(), VmException> {
+    let val = eval.pop();
+    let index = eval.pop().as_int().unwrap_or_else(|| panic!("Non-int array index seen on stack at {}", pos));
+    let arrayid = pop_array_ref(eval, pos)?;
+    check_array_access(heap, arrayid, index, elem_types, pos)?;
+    heap.array_store(arrayid, index, val);
+    Ok(())
+}
+
+// AASTORE also checks that the value's klass fits the array's component type
+fn aastore(repo: &mut SharedKlassRepo, eval: &mut InterpEvalStack, pos: usize) -> Result<(), VmException> {
+    let val = eval.pop();
+    let index = eval.pop().as_int().unwrap_or_else(|| panic!("Non-int array index seen on stack at {}", pos));
+    let arrayid = pop_array_ref(eval, pos)?;
+    check_array_access(repo.heap(), arrayid, index, "A", pos)?;
+    let value_id = val.as_objref().unwrap_or_else(|| panic!("Non-objref seen on stack during AASTORE at {}", pos));
+    if !repo.can_store(arrayid, value_id) {
+        return Err(VmException::of("java/lang/ArrayStoreException", repo.klass_name_of(value_id)));
+    }
+    repo.heap_mut().array_store(arrayid, index, val);
+    Ok(())
+}
+
+// Where a frame carries on after thrown reaches pc - the first entry in the
+// exception table that covers pc and catches the exception's klass or one of
+// its supers. The handler starts with only the exception on the eval stack. If
+// nothing here catches it, the frame completes abruptly and thrown goes on up
+fn catch(
+    repo: &SharedKlassRepo,
+    current_klass: &OtKlass,
+    handlers: &[ExceptionHandler],
+    pc: usize,
+    thrown: Thrown,
+    eval: &mut InterpEvalStack,
+) -> Result<usize, Thrown> {
+    let Thrown(ex_id) = thrown;
+    let ex_klass = repo.lookup_klass_by_id(repo.heap().get_obj(ex_id).get_klassid());
+    let handler = handlers.iter().find(|h| {
+        h.covers(pc)
+            && (h.catch_type == 0
+                || repo.is_assignable(ex_klass.get_loader(), &ex_klass.get_name(), &cp_klass_name(current_klass, h.catch_type)))
+    });
+    match handler {
+        Some(h) => {
+            eval.clear();
+            eval.push(JvmValue::ObjRef(ex_id));
+            Ok(h.handler_pc as usize)
+        }
+        None => Err(thrown),
+    }
+}
+
+// An exception thrown by the callee comes back as Err, for the caller to catch.
+// Callers must have published their roots, as the args are still on the stack
 fn dispatch_invoke(
     repo: &mut SharedKlassRepo,
     current_klass: OtKlass,
     cp_lookup: u16,
     eval: &mut InterpEvalStack,
-    additional_args: u8,
-) {
+) -> Result<(), Thrown> {
     let fq_name_desc = current_klass.cp_as_string(cp_lookup);
+    // Static and private interface methods come as InterfaceMethodrefs
     let klz_idx = match current_klass.lookup_cp(cp_lookup) {
         CpEntry::MethodRef(mr) => mr.clz_idx,
+        CpEntry::InterfaceMethodRef(imr) => imr.clz_idx,
         _ => panic!(
             "Non-methodref found in {} at CP index {}",
             current_klass.get_name(),
@@ -820,18 +1269,74 @@ fn dispatch_invoke(
     };
     let dispatch_klass_name = current_klass.cp_as_string(klz_idx);
 
-    let callee = repo.lookup_method_exact(&dispatch_klass_name, fq_name_desc);
+    let callee = repo
+        .lookup_method_exact(current_klass.get_loader(), &dispatch_klass_name, fq_name_desc)
+        .map_err(|ex| repo.new_exception(&ex))?;
+    invoke(repo, &callee, eval)
+}
+
+// The method run is selected by the receiver's klass, which must implement
+// the interface. As for dispatch_invoke, anything thrown comes back as Err
+fn invoke_interface(
+    repo: &mut SharedKlassRepo,
+    current_klass: OtKlass,
+    cp_lookup: u16,
+    eval: &mut InterpEvalStack,
+) -> Result<(), Thrown> {
+    let (iface_idx, nt_idx) = match current_klass.lookup_cp(cp_lookup) {
+        CpEntry::InterfaceMethodRef(imr) => (imr.clz_idx, imr.nt_idx),
+        _ => panic!(
+            "Non-interface methodref found in {} at CP index {}",
+            current_klass.get_name(),
+            cp_lookup
+        ),
+    };
+    let iface_name = current_klass.cp_as_string(iface_idx);
+    let name_desc = current_klass.cp_as_string(nt_idx);
+    let resolved = repo
+        .resolve_interface_method(current_klass.get_loader(), &iface_name, &name_desc)
+        .map_err(|ex| repo.new_exception(&ex))?;
+
+    // The receiver is underneath the args
+    let obj_id = match eval.peek(resolved.get_param_slots().len()) {
+        JvmValue::ObjRef(0) => {
+            return Err(repo.new_exception(&null_pointer(format!("invoking {} on null", resolved))));
+        }
+        JvmValue::ObjRef(v) => v,
+        _ => panic!("Non-objref receiver seen on stack invoking {}", resolved),
+    };
+    let klass = repo.lookup_klass_by_id(repo.heap().get_obj(obj_id).get_klassid());
+    if !repo.is_assignable(klass.get_loader(), &klass.get_name(), &iface_name) {
+        let ex = VmException::of(
+            "java/lang/IncompatibleClassChangeError",
+            format!("Class {} does not implement the requested interface {}", klass.get_name(), iface_name),
+        );
+        return Err(repo.new_exception(&ex));
+    }
+    let callee = repo.select_interface_method(&klass, &resolved).map_err(|ex| repo.new_exception(&ex))?;
+    invoke(repo, &callee, eval)
+}
 
-    // FIXME - General setup requires call args from the stack
-    let mut vars = InterpLocalVars::of(255);
-    if additional_args > 0 {
-        vars.store(0, eval.pop());
+// Calls callee with its args from the top of the stack, pushing whatever it returns
+fn invoke(repo: &mut SharedKlassRepo, callee: &OtMethod, eval: &mut InterpEvalStack) -> Result<(), Thrown> {
+    // The args come off the stack last first, into the slots the callee's
+    // descriptor puts them in
+    let mut vars = InterpLocalVars::of(callee.get_local_var_size());
+    for slot in callee.get_param_slots().iter().rev() {
+        vars.store(*slot, eval.pop());
+    }
+    if !callee.is_static() {
+        let receiver = eval.pop();
+        if let JvmValue::ObjRef(0) = receiver {
+            return Err(repo.new_exception(&null_pointer(format!("invoking {} on null", callee))));
+        }
+        vars.store(0, receiver);
     }
     // Explicit use of match expression to be clear about the semantics
-    match exec_method(repo, &callee, &mut vars) {
-        Some(val) => eval.push(val),
-        None => (),
+    if let Some(val) = exec_method(repo, callee, &mut vars)? {
+        eval.push(val);
     }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -1,48 +1,90 @@
 use std::path::Path;
 
 use ocelotter_runtime::klass_parser::*;
-use ocelotter_runtime::klass_repo::SharedKlassRepo;
+use ocelotter_runtime::klass_repo::{KlassLoadError, SharedKlassRepo, BOOTSTRAP_SNAPSHOT, JAVA_RELEASE};
+use ocelotter_runtime::copying_heap::CopyingHeap;
 use ocelotter_runtime::InterpLocalVars;
 use ocelotter_runtime::JvmValue::*;
+use ocelotter_runtime::Thrown;
 use ocelotter_util::file_to_bytes;
 use structopt::StructOpt;
 
 use ocelotter::exec_method;
-use ocelotter_util::ZipFiles;
+use ocelotter_util::{class_source_for, jar_with_class_path, read_manifest};
 use options::Options;
 
 mod options;
 
+// Classes that fail to load are skipped - the run only fails if one is used
+fn report_load_errors(source: &str, errors: Vec<KlassLoadError>) {
+    for e in errors {
+        eprintln!("Error loading {} from {}", e, source);
+    }
+}
+
+// The heap as the program left it, however it ended
+fn print_class_histogram(options: &Options, repo: &SharedKlassRepo) {
+    if options.xx_flag("PrintClassHistogram") {
+        print!("{}", repo.class_histogram());
+    }
+}
+
 pub fn main() {
-    // Parse any command-line arguments
-    let options = Options::from_args();
+    // Parse any command-line arguments - java spells the jar option with a single dash,
+    // -XX flags with a colon and the heap sizes with no separator at all
+    let options = Options::from_iter(std::env::args().map(|a| match a.as_str() {
+        "-jar" => "--jar".to_string(),
+        _ if a.starts_with("-XX:") => format!("--XX={}", &a[4..]),
+        _ if a.starts_with("-Xms") || a.starts_with("-Xmx") => format!("-{}={}", &a[..4], &a[4..]),
+        _ => a,
+    }));
 
     let mut repo = SharedKlassRepo::of();
-    repo.bootstrap(exec_method);
-
-    let fq_klass_name = options.fq_klass_name();
-    let f_name = options.f_name();
+    // The heap has to be chosen before bootstrapping allocates anything
+    if options.xx_flag("UseCopyingGC") {
+        repo.use_heap(Box::new(CopyingHeap::of()));
+    }
+    let (initial_heap, max_heap) = options.heap_limits();
+    repo.set_heap_limits(initial_heap, max_heap);
+    repo.set_print_gc(options.xx_flag("PrintGC"));
+    if options.xx_flag("HeapDumpOnOutOfMemoryError") {
+        // HotSpot's default is a file named for the process in the working directory
+        repo.set_heap_dump_path(Some(options.xx_value("HeapDumpPath").unwrap_or_else(|| ".".to_string())));
+    }
+    if options.no_snapshot {
+        repo.bootstrap(exec_method);
+    } else {
+        repo.bootstrap_cached(exec_method, BOOTSTRAP_SNAPSHOT);
+    }
 
-    if let Some(file) = &options.classpath {
-        ZipFiles::new(file)
-            .into_iter()
-            .filter(|f| matches!(f, Ok((name, _)) if name.ends_with(".class")))
-            .for_each(|z| {
-                if let Ok((name, bytes)) = z {
-                    let mut parser = OtKlassParser::of(bytes, name);
-                    parser.parse();
-                    repo.add_klass(&parser.klass());
-                }
-            });
+    let f_name = if let Some(jar) = &options.jar {
+        // Executable jar - the manifest names the main class and any other jars needed
+        let manifest =
+            read_manifest(jar).unwrap_or_else(|| panic!("Problem reading manifest of {}", jar));
+        let main_klass_name = manifest
+            .main_class()
+            .unwrap_or_else(|| panic!("No Main-Class manifest attribute in {}", jar));
+        for file in jar_with_class_path(jar) {
+            report_load_errors(&file, repo.add_jar(&file, JAVA_RELEASE));
+        }
+        main_klass_name
+    } else if let Some(path) = &options.classpath {
+        for element in path.split(':').filter(|e| !e.is_empty()) {
+            let errors = repo.add_class_source(class_source_for(element, JAVA_RELEASE).as_ref());
+            report_load_errors(element, errors);
+        }
+        options.f_name()
     //Not using a classpath jar, just a class
     } else {
+        let fq_klass_name = options.fq_klass_name();
         let bytes = file_to_bytes(Path::new(&fq_klass_name))
             .unwrap_or_else(|_| panic!("Problem reading {}", &fq_klass_name));
         let mut parser = OtKlassParser::of(bytes, fq_klass_name);
         parser.parse();
         let k = parser.klass();
         repo.add_klass(&k);
-    }
+        options.f_name()
+    };
 
     // FIXME Real main() signature required, dummying for ease of testing
     let main_str: String = f_name.clone() + ".main2:([Ljava/lang/String;)I";
@@ -51,15 +93,24 @@ pub fn main() {
         .get_method_by_name_and_desc(&main_str)
         .unwrap_or_else(|| panic!("Error: Main method not found {}", main_str.clone()));
 
-    // FIXME Parameter passing
-    let mut vars = InterpLocalVars::of(5);
+    let args = repo
+        .new_string_array(options.program_args())
+        .unwrap_or_else(|ex| panic!("Error creating arguments for {}: {:?}", &f_name, ex));
+    let mut vars = InterpLocalVars::of(main.get_local_var_size());
+    vars.store(0, ObjRef(args));
 
-    let ret = exec_method(&mut repo, main, &mut vars)
-        .map(|return_value| match return_value {
-            Int(i) => i,
-            _ => panic!("Error executing {} - non-int value returned", &f_name),
-        })
-        .unwrap_or_else(|| panic!("Error executing {} - no value returned", &f_name));
+    let ret = match exec_method(&mut repo, main, &mut vars) {
+        Ok(Some(Int(i))) => i,
+        Ok(Some(_)) => panic!("Error executing {} - non-int value returned", &f_name),
+        Ok(None) => panic!("Error executing {} - no value returned", &f_name),
+        // As with java, an uncaught exception is reported and the run fails
+        Err(Thrown(ex_id)) => {
+            eprintln!("Exception in thread \"main\" {}", repo.describe_throwable(ex_id));
+            print_class_histogram(&options, &repo);
+            std::process::exit(1);
+        }
+    };
 
     println!("Ret: {}", ret);
+    print_class_histogram(&options, &repo);
 }
@@ -1,26 +1,105 @@
 use structopt::StructOpt;
 
+use ocelotter_runtime::heap::{DEFAULT_MAX_BYTES, INITIAL_COMMITTED_BYTES};
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "ocelotter", about = "A minimal implementation of a JVM")]
 pub struct Options {
     #[structopt(short, long)]
-    /// class search path of directories and zip/jar files
+    /// class search path of jar and jmod files and exploded module directories, separated by ':'
     pub classpath: Option<String>,
 
+    #[structopt(long)]
+    /// executable jar file - the main class comes from its manifest
+    pub jar: Option<String>,
+
+    #[structopt(long)]
+    /// always bootstrap from classes.jar, rather than from its snapshot
+    pub no_snapshot: bool,
+
+    #[structopt(long = "Xms")]
+    /// initial heap size, e.g. -Xms16m (suffixes k, m and g are accepted)
+    pub xms: Option<String>,
+
+    #[structopt(long = "Xmx")]
+    /// maximum heap size, e.g. -Xmx512m - OutOfMemoryError is thrown past this
+    pub xmx: Option<String>,
+
+    #[structopt(long = "XX", number_of_values = 1)]
+    /// VM flags, given as -XX:+Flag or -XX:-Flag (UseCopyingGC, PrintGC,
+    /// HeapDumpOnOutOfMemoryError, PrintClassHistogram), or -XX:Flag=value
+    /// (HeapDumpPath)
+    pub xx: Vec<String>,
+
     #[structopt()]
-    /// Class name
+    /// Class name followed by program arguments (just the arguments with -jar)
     pub classname: Vec<String>,
 }
 
 impl Options {
+    // The last setting of a -XX flag wins
+    pub fn xx_flag(&self, name: &str) -> bool {
+        self.xx
+            .iter()
+            .rev()
+            .find(|f| f.get(1..) == Some(name))
+            .is_some_and(|f| f.starts_with('+'))
+    }
+
+    // The value of the last -XX:name=value given
+    pub fn xx_value(&self, name: &str) -> Option<String> {
+        self.xx
+            .iter()
+            .rev()
+            .find_map(|f| f.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')))
+            .map(|v| v.to_string())
+    }
+
+    // -Xms and -Xmx in bytes. As with HotSpot, an initial size larger than
+    // the default maximum raises the maximum to match
+    pub fn heap_limits(&self) -> (u64, u64) {
+        let initial = self.xms.as_ref().map(|s| parse_heap_size(s, "initial"));
+        let max = self
+            .xmx
+            .as_ref()
+            .map(|s| parse_heap_size(s, "maximum"))
+            .unwrap_or_else(|| initial.unwrap_or(0).max(DEFAULT_MAX_BYTES));
+        (initial.unwrap_or_else(|| INITIAL_COMMITTED_BYTES.min(max)), max)
+    }
+
     pub fn fq_klass_name(&self) -> String {
         format!("{}.class", self.f_name())
     }
 
+    // Everything after the class name, passed to main as its String[] - with
+    // -jar the main class comes from the manifest, so every one is an argument
+    pub fn program_args(&self) -> &[String] {
+        if self.jar.is_some() {
+            &self.classname
+        } else {
+            self.classname.get(1..).unwrap_or_default()
+        }
+    }
+
     pub fn f_name(&self) -> String {
         self.classname
-            .get(0)
+            .first()
             .expect("Classname should be specified")
             .into()
     }
 }
+
+// A size in bytes, optionally followed by k, m or g
+fn parse_heap_size(s: &str, which: &str) -> u64 {
+    let (digits, unit) = match s.char_indices().last() {
+        Some((i, 'k')) | Some((i, 'K')) => (&s[..i], 1 << 10),
+        Some((i, 'm')) | Some((i, 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'g')) | Some((i, 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .unwrap_or_else(|| panic!("Invalid {} heap size: {}", which, s))
+}
@@ -1,12 +1,17 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
 use std::path::Path;
 
+use byteorder::{BigEndian, ReadBytesExt};
+
 use super::*;
 
 use ocelotter_runtime::constant_pool::ACC_PUBLIC;
+use ocelotter_runtime::hprof;
+use ocelotter_runtime::klass_repo::{BOOTSTRAP_JAR, BOOTSTRAP_LOADER, BOOTSTRAP_SNAPSHOT, JAVA_RELEASE};
+use ocelotter_runtime::native_methods;
 // this crate is presumably old and not very good.
-use assert_float_eq::{
-    afe_is_f32_near, afe_is_f64_near, afe_near_error_msg, assert_f32_near, assert_f64_near,
-};
+use assert_float_eq::{assert_f32_near, assert_f64_near};
 
 use ocelotter_util::file_to_bytes;
 
@@ -14,17 +19,33 @@ use ocelotter_util::file_to_bytes;
 
 fn init_repo() -> SharedKlassRepo {
     let mut repo = SharedKlassRepo::of();
-    repo.bootstrap(exec_method);
+    repo.bootstrap_cached(exec_method, BOOTSTRAP_SNAPSHOT);
     repo
 }
 
 fn execute_simple_bytecode(buf: &[u8]) -> JvmValue {
     let mut repo = init_repo();
     let mut lvt = InterpLocalVars::of(10); // FIXME
-    exec_bytecode_method(&mut repo, "DUMMY".to_string(), buf, &mut lvt)
+    exec_bytecode_method(&mut repo, BOOTSTRAP_LOADER, "DUMMY".to_string(), buf, &mut lvt)
+        .unwrap()
         .unwrap_or(JvmValue::ObjRef(0)) // object::OtObj::get_null(),
 }
 
+// Runs a static method of a klass the bootstrap loader has, with args in the
+// slots its descriptor gives them
+fn exec_in(repo: &mut SharedKlassRepo, klass_name: &str, name_desc: &str, args: &[JvmValue]) -> JvmResult {
+    let k = repo.lookup_klass(&klass_name.to_string());
+    let meth = k
+        .get_method_by_name_and_desc(&format!("{}.{}", klass_name, name_desc))
+        .unwrap_or_else(|| panic!("No method {} on {}", name_desc, klass_name))
+        .clone();
+    let mut vars = InterpLocalVars::of(meth.get_local_var_size());
+    for (slot, arg) in meth.get_param_slots().into_iter().zip(args) {
+        vars.store(slot, *arg);
+    }
+    exec_method(repo, &meth, &mut vars)
+}
+
 fn simple_parse_klass(cname: String) -> OtKlass {
     let mut path = "./resources/test/".to_string();
     path.push_str(&cname);
@@ -264,9 +285,10 @@ fn bc_goto() {
         opcode::ICONST_1,
         opcode::ICONST_1,
         opcode::IADD,
+        // Branch offsets count from the GOTO itself
         opcode::GOTO,
         0,
-        3,
+        4,
         0xff,
         opcode::IRETURN,
     ];
@@ -431,7 +453,7 @@ fn interp_invoke_simple() {
         assert_eq!(ACC_PUBLIC | ACC_STATIC, meth.get_flags());
 
         let mut vars = InterpLocalVars::of(5);
-        let ret = exec_method(&mut repo, meth, &mut vars).unwrap();
+        let ret = exec_method(&mut repo, meth, &mut vars).unwrap().unwrap();
         let ret2 = match ret {
             JvmValue::Int(i) => i,
             _ => panic!("Error executing SampleInvoke.bar:()I - non-int value returned"),
@@ -447,7 +469,7 @@ fn interp_invoke_simple() {
         assert_eq!(ACC_PUBLIC | ACC_STATIC, meth.get_flags());
 
         let mut vars = InterpLocalVars::of(5);
-        let ret = exec_method(&mut repo, meth, &mut vars).unwrap();
+        let ret = exec_method(&mut repo, meth, &mut vars).unwrap().unwrap();
         let ret2 = match ret {
             JvmValue::Int(i) => i,
             _ => panic!("Error executing SampleInvoke.foo:()I - non-int value returned"),
@@ -470,7 +492,7 @@ fn test_math_sin() {
         assert_eq!(ACC_PUBLIC | ACC_STATIC, meth.get_flags());
 
         let mut vars = InterpLocalVars::of(5);
-        let ret = exec_method(&mut repo, meth, &mut vars).unwrap();
+        let ret = exec_method(&mut repo, meth, &mut vars).unwrap().unwrap();
         let ret2 = match ret {
             JvmValue::Int(i) => i,
             _ => panic!("Error executing {} - non-int value returned", fq_meth),
@@ -486,7 +508,7 @@ fn test_math_sin() {
         assert_eq!(ACC_PUBLIC | ACC_STATIC, meth.get_flags());
 
         let mut vars = InterpLocalVars::of(5);
-        let ret = exec_method(&mut repo, meth, &mut vars).unwrap();
+        let ret = exec_method(&mut repo, meth, &mut vars).unwrap().unwrap();
         let ret2 = match ret {
             JvmValue::Int(i) => i,
             _ => panic!("Error executing {} - non-int value returned", fq_meth),
@@ -502,7 +524,7 @@ fn test_math_sin() {
         assert_eq!(ACC_PUBLIC | ACC_STATIC, meth.get_flags());
 
         let mut vars = InterpLocalVars::of(5);
-        let ret = exec_method(&mut repo, meth, &mut vars).unwrap();
+        let ret = exec_method(&mut repo, meth, &mut vars).unwrap().unwrap();
         let ret2 = match ret {
             JvmValue::Int(i) => i,
             _ => panic!("Error executing {} - non-int value returned", fq_meth),
@@ -525,7 +547,7 @@ fn interp_iffer() {
         assert_eq!(ACC_PUBLIC | ACC_STATIC, meth.get_flags());
 
         let mut vars = InterpLocalVars::of(5);
-        let ret = exec_method(&mut repo, meth, &mut vars).unwrap();
+        let ret = exec_method(&mut repo, meth, &mut vars).unwrap().unwrap();
         let ret2 = match ret {
             JvmValue::Int(i) => i,
             _ => panic!("Error executing Iffer.baz:()I - non-int value returned"),
@@ -547,7 +569,7 @@ fn interp_array_set() {
         assert_eq!(ACC_PUBLIC | ACC_STATIC, meth.get_flags());
 
         let mut vars = InterpLocalVars::of(5);
-        let ret = exec_method(&mut repo, meth, &mut vars).unwrap();
+        let ret = exec_method(&mut repo, meth, &mut vars).unwrap().unwrap();
         let ret2 = match ret {
             JvmValue::Int(i) => i,
             _ => panic!("Error executing {} - non-int value returned", fqname),
@@ -556,6 +578,161 @@ fn interp_array_set() {
     }
 }
 
+fn prim_arrays_repo() -> SharedKlassRepo {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("PrimArrays".to_string()));
+    repo
+}
+
+fn exec_prim_arrays(method: &str) -> JvmValue {
+    exec_in(&mut prim_arrays_repo(), "PrimArrays", method, &[]).unwrap().unwrap()
+}
+
+// The klass of what an uncaught exception left behind
+fn thrown_klass_name(repo: &SharedKlassRepo, ret: JvmResult) -> String {
+    match ret {
+        Err(Thrown(ex_id)) => repo.klass_name_of(ex_id),
+        ret => panic!("Expected an exception, not {:?}", ret),
+    }
+}
+
+#[test]
+fn interp_primitive_arrays() {
+    // Stores narrow to the element type, and loads widen back to int
+    assert_eq!(Some(-56 + 100 + 3), exec_prim_arrays("bytes:()I").as_int());
+    assert_eq!(Some(65535), exec_prim_arrays("chars:()I").as_int());
+    assert_eq!(Some(-25536), exec_prim_arrays("shorts:()I").as_int());
+    assert_eq!(Some(4), exec_prim_arrays("booleans:()I").as_int());
+    assert_eq!(Some(4), exec_prim_arrays("longs:()J").as_long());
+    assert_f32_near!(2.0, exec_prim_arrays("floats:()F").as_float().unwrap());
+    assert_f64_near!(2.5, exec_prim_arrays("doubles:()D").as_double().unwrap());
+}
+
+#[test]
+fn interp_array_index_out_of_bounds() {
+    let mut repo = prim_arrays_repo();
+    let ret = exec_in(&mut repo, "PrimArrays", "outOfBounds:()I", &[]);
+    assert_eq!("java/lang/ArrayIndexOutOfBoundsException", thrown_klass_name(&repo, ret));
+}
+
+#[test]
+fn bc_newarray_arraylength() {
+    let buf = vec![
+        opcode::BIPUSH,
+        7,
+        opcode::NEWARRAY,
+        11, // long
+        opcode::ARRAYLENGTH,
+        opcode::IRETURN,
+    ];
+    assert_eq!(Some(7), execute_simple_bytecode(&buf).as_int());
+}
+
+fn ref_arrays_repo() -> SharedKlassRepo {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("arrays/RefArrays".to_string()));
+    repo.add_klass(&simple_parse_klass("arrays/Task".to_string()));
+    repo
+}
+
+fn exec_ref_arrays(method: &str) -> JvmValue {
+    exec_in(&mut ref_arrays_repo(), "RefArrays", method, &[]).unwrap().unwrap()
+}
+
+#[test]
+fn interp_reference_arrays() {
+    assert_eq!(Some(4), exec_ref_arrays("lengths:()I").as_int());
+    assert_eq!(Some(3 + 4 + 7), exec_ref_arrays("multi:()I").as_int());
+    assert_eq!(Some(3), exec_ref_arrays("partial:()I").as_int());
+    assert_eq!(Some(3 + 1), exec_ref_arrays("covariant:()I").as_int());
+}
+
+#[test]
+fn interp_array_store_exception() {
+    let mut repo = ref_arrays_repo();
+    let ret = exec_in(&mut repo, "RefArrays", "badStore:()I", &[]);
+    assert_eq!("java/lang/ArrayStoreException", thrown_klass_name(&repo, ret));
+}
+
+#[test]
+fn interp_anewarray_of_missing_klass() {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("arrays/RefArrays".to_string()));
+    let ret = exec_in(&mut repo, "RefArrays", "missingElement:()I", &[]);
+    assert_eq!("java/lang/NoClassDefFoundError", thrown_klass_name(&repo, ret));
+}
+
+#[test]
+fn array_klasses_are_assignable() {
+    let mut repo = init_repo();
+    let strings = repo.array_klass(BOOTSTRAP_LOADER, "[Ljava/lang/String;").unwrap();
+    assert_eq!("[Ljava/lang/String;", strings.get_name());
+    assert_eq!("java/lang/Object", strings.get_super_name());
+
+    assert!(repo.is_assignable(BOOTSTRAP_LOADER, "[Ljava/lang/String;", "[Ljava/lang/Object;"));
+    assert!(repo.is_assignable(BOOTSTRAP_LOADER, "[[I", "[Ljava/lang/Object;"));
+    assert!(repo.is_assignable(BOOTSTRAP_LOADER, "[I", "java/lang/Cloneable"));
+    assert!(!repo.is_assignable(BOOTSTRAP_LOADER, "[I", "[Ljava/lang/Object;"));
+    assert!(!repo.is_assignable(BOOTSTRAP_LOADER, "[Ljava/lang/Object;", "[Ljava/lang/String;"));
+}
+
+fn exec_hashes(method: &str) -> JvmValue {
+    let mut repo = init_repo();
+    let k = simple_parse_klass("mark/Hashes".to_string());
+    repo.add_klass(&k);
+    let meth = k.get_method_by_name_and_desc(&format!("Hashes.{}", method)).unwrap();
+    let mut vars = InterpLocalVars::of(5);
+    exec_method(&mut repo, meth, &mut vars).unwrap().unwrap()
+}
+
+#[test]
+fn interp_identity_hash_codes() {
+    assert_eq!(Some(0), exec_hashes("stable:()I").as_int());
+    assert_ne!(Some(0), exec_hashes("distinct:()I").as_int());
+    // Nested synchronized blocks enter and exit the same monitor twice
+    assert_eq!(Some(2), exec_hashes("locked:()I").as_int());
+}
+
+#[test]
+fn main_gets_program_args() {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("args/Echo".to_string()));
+    let args = repo.new_string_array(&["a".to_string(), "bcd".to_string()]).unwrap();
+    assert_eq!("[Ljava/lang/String;", repo.klass_name_of(args));
+    let ret = exec_in(&mut repo, "Echo", "main2:([Ljava/lang/String;)I", &[JvmValue::ObjRef(args)]);
+    assert_eq!(Some(2 * 100 + 3), ret.unwrap().unwrap().as_int());
+}
+
+#[test]
+fn each_repo_has_its_own_heap() {
+    let mut first = init_repo();
+    let mut second = init_repo();
+    assert_eq!(first.heap().live_count(), second.heap().live_count());
+
+    // The same id names a different object in each VM
+    let object_klass = first.lookup_klass(&"java/lang/Object".to_string());
+    let ints = second.prim_array_klass('I');
+    let a = first.heap_mut().allocate_obj(&object_klass);
+    let b = second.heap_mut().allocate_int_arr(&ints, 3).unwrap();
+    assert_eq!(a, b);
+    assert_eq!(object_klass.get_id(), first.heap().get_obj(a).get_klassid());
+    assert_eq!("[I", second.klass_name_of(b));
+    assert_eq!(3, second.heap().get_obj(b).length());
+
+    // Collecting one leaves the other alone
+    first.collect_garbage();
+    assert!(!first.heap().is_live(a));
+    assert!(second.heap().is_live(b));
+
+    // Whole programs run side by side
+    let runs: Vec<_> = (0..4)
+        .map(|_| std::thread::spawn(|| exec_ref_arrays("multi:()I").as_int()))
+        .collect();
+    for run in runs {
+        assert_eq!(Some(3 + 4 + 7), run.join().unwrap());
+    }
+}
+
 #[test]
 fn interp_field_set() {
     let mut repo = init_repo();
@@ -569,7 +746,7 @@ fn interp_field_set() {
         assert_eq!(ACC_PUBLIC | ACC_STATIC, meth.get_flags());
 
         let mut vars = InterpLocalVars::of(5);
-        let ret = match exec_method(&mut repo, meth, &mut vars).unwrap() {
+        let ret = match exec_method(&mut repo, meth, &mut vars).unwrap().unwrap() {
             JvmValue::Int(i) => i,
             _ => panic!("Error executing {} - non-int value returned", fqname),
         };
@@ -591,13 +768,13 @@ fn interp_system_current_timemillis() {
         assert_eq!(ACC_PUBLIC | ACC_STATIC, meth.get_flags());
 
         let mut vars = InterpLocalVars::of(5);
-        let ret = exec_method(&mut repo, meth, &mut vars).unwrap();
+        let ret = exec_method(&mut repo, meth, &mut vars).unwrap().unwrap();
         let ctm1 = match ret {
             JvmValue::Int(i) => i,
             _ => panic!("Error executing {} - non-int value returned", fqname),
         };
         vars = InterpLocalVars::of(5);
-        let opt_ret = exec_method(&mut repo, meth, &mut vars);
+        let opt_ret = exec_method(&mut repo, meth, &mut vars).unwrap();
         let ret2 = match opt_ret {
             Some(value) => value,
             None => panic!("Error executing {} - no value returned", fqname),
@@ -626,7 +803,7 @@ fn interp_class_based_addition() {
         assert_eq!(ACC_PUBLIC | ACC_STATIC, meth.get_flags());
 
         let mut vars = InterpLocalVars::of(5);
-        let ret = exec_method(&mut repo, meth, &mut vars).unwrap();
+        let ret = exec_method(&mut repo, meth, &mut vars).unwrap().unwrap();
         let ret2 = match ret {
             JvmValue::Int(i) => i,
             _ => panic!("Error executing {} - non-int value returned", fqname),
@@ -648,7 +825,7 @@ fn interp_ldc_based_addition() {
         assert_eq!(ACC_PUBLIC | ACC_STATIC, meth.get_flags());
 
         let mut vars = InterpLocalVars::of(5);
-        let ret = exec_method(&mut repo, meth, &mut vars).unwrap();
+        let ret = exec_method(&mut repo, meth, &mut vars).unwrap().unwrap();
         let ret2 = match ret {
             JvmValue::Int(i) => i,
             _ => panic!("Error executing {} - non-int value returned", fqname),
@@ -656,3 +833,1205 @@ fn interp_ldc_based_addition() {
         assert_eq!(44451, ret2);
     }
 }
+
+fn exec_literals(repo: &mut SharedKlassRepo, fq_meth: &str) -> JvmValue {
+    let klass_name = fq_meth.split('.').next().unwrap().to_string();
+    let k = repo.lookup_klass(&klass_name);
+    let meth = k.get_method_by_name_and_desc(&fq_meth.to_string()).unwrap();
+    let mut vars = InterpLocalVars::of(5);
+    exec_method(repo, meth, &mut vars).unwrap().unwrap()
+}
+
+#[test]
+fn interp_string_literals() {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("strings/Literals".to_string()));
+    repo.add_klass(&simple_parse_klass("strings/Echo".to_string()));
+
+    let greeting = exec_literals(&mut repo, "Literals.greeting:()Ljava/lang/String;").as_objref().unwrap();
+    assert_ne!(0, greeting);
+    assert_eq!("java/lang/String", repo.klass_name_of(greeting));
+    assert_eq!("h\u{e9}llo\u{0}\u{1f600}", repo.string_to_rust(greeting));
+    assert_eq!(vec![0x68, 0xe9, 0x6c, 0x6c, 0x6f, 0, 0xd83d, 0xde00], repo.string_chars(greeting));
+
+    // Every load of the literal, from any class, is the same object
+    assert_eq!(Some(greeting), exec_literals(&mut repo, "Literals.greeting:()Ljava/lang/String;").as_objref());
+    assert_eq!(Some(greeting), exec_literals(&mut repo, "Echo.greeting:()Ljava/lang/String;").as_objref());
+    repo.collect_garbage();
+    assert!(repo.heap().is_live(greeting));
+
+    assert_eq!(Some(12), exec_literals(&mut repo, "Literals.length:()I").as_int());
+}
+
+#[test]
+fn interned_strings_are_shared() {
+    let mut repo = init_repo();
+    let literal = repo.intern_string(&"abc".encode_utf16().collect::<Vec<u16>>());
+    let copy = repo.new_string_from_rust("abc");
+    assert_ne!(literal, copy);
+    assert_eq!("abc", repo.string_to_rust(copy));
+
+    let mut vars = InterpLocalVars::of(1);
+    vars.store(0, JvmValue::ObjRef(copy));
+    assert_eq!(Some(literal), native_methods::java_lang_String__intern(&mut repo, &vars).unwrap().as_objref());
+
+    // A string nobody interned before becomes the pooled one itself
+    let fresh = repo.new_string_from_rust("xyz");
+    vars.store(0, JvmValue::ObjRef(fresh));
+    assert_eq!(Some(fresh), native_methods::java_lang_String__intern(&mut repo, &vars).unwrap().as_objref());
+    assert_eq!(Some(fresh), repo.find_interned(&"xyz".encode_utf16().collect::<Vec<u16>>()));
+}
+
+fn exceptions_repo() -> SharedKlassRepo {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("exceptions/Exceptions".to_string()));
+    repo.add_klass(&simple_parse_klass("exceptions/Oops".to_string()));
+    repo
+}
+
+#[test]
+fn exception_table_is_parsed() {
+    let k = simple_parse_klass("exceptions/Exceptions".to_string());
+    let meth = k.get_method_by_name_and_desc(&"Exceptions.firstMatchingHandler:()I".to_string()).unwrap();
+    let handlers = meth.get_exception_table();
+    assert_eq!(3, handlers.len());
+    assert!(handlers.iter().all(|h| h.start_pc == 0 && h.end_pc == 3 && h.catch_type != 0));
+    assert!(handlers[0].handler_pc < handlers[1].handler_pc && handlers[1].handler_pc < handlers[2].handler_pc);
+
+    // finally catches anything
+    let meth = k.get_method_by_name_and_desc(&"Exceptions.withFinally:()I".to_string()).unwrap();
+    assert_eq!(0, meth.get_exception_table()[0].catch_type);
+}
+
+#[test]
+fn exceptions_are_caught_by_the_right_handler() {
+    let mut repo = exceptions_repo();
+    let cases = [
+        ("sameFrame", 2),
+        ("callerCatches", 3),
+        ("superclassCatches", 4),
+        ("firstMatchingHandler", 6),
+        ("unwindsTwoFrames", 8),
+        ("finallyRuns", 11),
+        ("catchesThrownObject", 12),
+    ];
+    for (name, expected) in cases.iter() {
+        let ret = exec_in(&mut repo, "Exceptions", &format!("{}:()I", name), &[]).unwrap_or_else(|t| panic!("{} threw {:?}", name, t));
+        assert_eq!(Some(*expected), ret.and_then(|v| v.as_int()), "{}", name);
+    }
+    // The handler is given the very object that was thrown
+    let thrown = get_static_of(&repo, "Exceptions", "thrown:LOops;").as_objref();
+    assert_ne!(Some(0), thrown);
+    assert_eq!(thrown, get_static_of(&repo, "Exceptions", "caught:LOops;").as_objref());
+}
+
+#[test]
+fn uncaught_exceptions_unwind_every_frame() {
+    let mut repo = exceptions_repo();
+    let ex_id = match exec_in(&mut repo, "Exceptions", "uncaught:()I", &[]) {
+        Err(Thrown(ex_id)) => ex_id,
+        ret => panic!("uncaught() should have thrown, not returned {:?}", ret),
+    };
+    assert_eq!("Oops", repo.klass_name_of(ex_id));
+    assert_eq!("Oops", repo.describe_throwable(ex_id));
+    // No frame is left behind holding on to it
+    repo.collect_garbage();
+    assert!(!repo.heap().is_live(ex_id));
+}
+
+#[test]
+fn vm_raised_exceptions_are_caught() {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("exceptions/VmExceptions".to_string()));
+    let cases = [
+        ("divideByZero", 1),
+        ("remainderByZero", 2),
+        ("nullField", 3),
+        ("nullArray", 4),
+        ("outOfBounds", 5),
+        ("negativeSize", 6),
+        ("nullThrow", 7),
+        ("callerCatches", 8),
+        ("negativeObjectSize", 9),
+        ("negativeMultiSize", 10),
+        ("tooLarge", 11),
+    ];
+    for (name, expected) in cases.iter() {
+        let ret = exec_in(&mut repo, "VmExceptions", &format!("{}:()I", name), &[]).unwrap_or_else(|t| panic!("{} threw {:?}", name, t));
+        assert_eq!(Some(*expected), ret.and_then(|v| v.as_int()), "{}", name);
+    }
+
+    let ret = exec_in(&mut repo, "VmExceptions", "uncaught:()I", &[]);
+    assert_eq!("java/lang/ArithmeticException", thrown_klass_name(&repo, ret));
+}
+
+#[test]
+fn args_are_laid_out_by_descriptor() {
+    let k = simple_parse_klass("invoke/Args".to_string());
+    let wide = k.get_method_by_name_and_desc(&"Args.wide:(IJID)I".to_string()).unwrap();
+    assert_eq!(vec![0, 1, 3, 4], wide.get_param_slots());
+    assert_eq!(6, wide.get_arg_slots());
+    assert_eq!(6, wide.get_max_locals());
+    // The receiver comes first
+    let scaled = k.get_method_by_name_and_desc(&"Args.scaled:(IJ)I".to_string()).unwrap();
+    assert_eq!(vec![1, 2], scaled.get_param_slots());
+    assert_eq!(4, scaled.get_local_var_size());
+
+    let cases = [
+        ("staticArgs", 123),
+        ("wideArgs", 4321),
+        ("instanceArgs", 7 * 6 + 3),
+        ("nativeArgs", 32),
+        ("nullReceiver", 9),
+    ];
+    let mut repo = init_repo();
+    repo.add_klass(&k);
+    for (name, expected) in cases.iter() {
+        let ret = exec_in(&mut repo, "Args", &format!("{}:()I", name), &[]).unwrap_or_else(|t| panic!("{} threw {:?}", name, t));
+        assert_eq!(Some(*expected), ret.and_then(|v| v.as_int()), "{}", name);
+    }
+}
+
+fn interfaces_repo() -> SharedKlassRepo {
+    let mut repo = init_repo();
+    let cnames = [
+        "Interfaces", "Shape", "Named", "Renamed", "Square", "Triangle", "Base", "Pentagon", "Left", "Right",
+        "Conflict", "Later", "Early", "Quitter", "Watcher",
+    ];
+    for cname in cnames {
+        repo.add_klass(&simple_parse_klass(format!("interfaces/{}", cname)));
+    }
+    repo
+}
+
+#[test]
+fn interface_methods_are_selected_by_receiver() {
+    let mut repo = interfaces_repo();
+    let cases = [
+        ("implemented", 4),
+        ("defaultMethod", 4),
+        ("classBeatsDefault", 30),
+        ("fromSuperclass", 5),
+        ("mostSpecificDefault", 2),
+        ("staticMethod", 1),
+        ("privateMethod", 8),
+        ("bootstrapInterfaces", 10),
+        ("nullReceiver", 9),
+    ];
+    for (name, expected) in cases.iter() {
+        let ret = exec_in(&mut repo, "Interfaces", &format!("{}:()I", name), &[]).unwrap_or_else(|t| panic!("{} threw {:?}", name, t));
+        assert_eq!(Some(*expected), ret.and_then(|v| v.as_int()), "{}", name);
+    }
+}
+
+#[test]
+fn interface_selection_errors_are_thrown() {
+    let mut repo = interfaces_repo();
+    let cases = [
+        ("conflictingDefaults", "java/lang/IncompatibleClassChangeError"),
+        ("notImplemented", "java/lang/AbstractMethodError"),
+        ("notAnImplementor", "java/lang/IncompatibleClassChangeError"),
+    ];
+    for (name, expected) in cases.iter() {
+        let ret = exec_in(&mut repo, "Interfaces", &format!("{}:()I", name), &[]);
+        assert_eq!(*expected, thrown_klass_name(&repo, ret), "{}", name);
+    }
+}
+
+fn finalize_repo() -> SharedKlassRepo {
+    let mut repo = init_repo();
+    for cname in ["Finalizable", "Heir", "Phoenix", "Faulty"] {
+        repo.add_klass(&simple_parse_klass(format!("finalize/{}", cname)));
+    }
+    repo
+}
+
+fn make_garbage(repo: &mut SharedKlassRepo, klass_name: &str) {
+    let k = repo.lookup_klass(&klass_name.to_string());
+    let meth = k.get_method_by_name_and_desc(&format!("{}.make:()I", klass_name)).unwrap();
+    let mut vars = InterpLocalVars::of(5);
+    exec_method(repo, meth, &mut vars).unwrap();
+}
+
+fn get_static_of(repo: &SharedKlassRepo, klass_name: &str, name_desc: &str) -> JvmValue {
+    let k = repo.lookup_klass(&klass_name.to_string());
+    let f = k.get_static_field_by_name_and_desc(&format!("{}.{}", klass_name, name_desc)).unwrap();
+    k.get_static(f)
+}
+
+fn instances_of(repo: &SharedKlassRepo, klass_name: &str) -> usize {
+    repo.class_histogram().get(klass_name).map_or(0, |e| e.instances)
+}
+
+#[test]
+fn finalizers_run_exactly_once() {
+    let mut repo = finalize_repo();
+    make_garbage(&mut repo, "Finalizable");
+    assert_eq!(2, instances_of(&repo, "Finalizable"));
+    assert_eq!(1, instances_of(&repo, "Heir"));
+
+    // Found unreachable and queued, but kept until the finalizers have run
+    repo.collect_garbage();
+    assert_eq!(2, instances_of(&repo, "Finalizable"));
+    assert_eq!(1, instances_of(&repo, "Heir"));
+    assert_eq!(Some(0), get_static_of(&repo, "Finalizable", "finalized:I").as_int());
+
+    native_methods::java_lang_Runtime__runFinalization(&mut repo, &InterpLocalVars::of(1));
+    assert_eq!(Some(3), get_static_of(&repo, "Finalizable", "finalized:I").as_int());
+
+    repo.collect_garbage();
+    assert_eq!(0, instances_of(&repo, "Finalizable"));
+    assert_eq!(0, instances_of(&repo, "Heir"));
+    repo.collect_garbage();
+    assert_eq!(0, repo.run_finalization());
+    assert_eq!(Some(3), get_static_of(&repo, "Finalizable", "finalized:I").as_int());
+}
+
+#[test]
+fn resurrected_objects_are_not_finalized_again() {
+    let mut repo = finalize_repo();
+    make_garbage(&mut repo, "Phoenix");
+    let phoenix = repo.heap().live_ids().into_iter().find(|id| repo.klass_name_of(*id) == "Phoenix").unwrap();
+    let weak = repo.new_weak_ref(phoenix);
+    assert_eq!(Some(phoenix), repo.weak_ref_get(weak));
+
+    repo.collect_garbage();
+    assert_eq!(1, repo.run_finalization());
+    assert_eq!(Some(phoenix), get_static_of(&repo, "Phoenix", "saved:LPhoenix;").as_objref());
+    // The weak reference went when the object first became unreachable
+    assert_eq!(None, repo.weak_ref_get(weak));
+
+    repo.collect_garbage();
+    assert!(repo.heap().is_live(phoenix));
+
+    let k = repo.lookup_klass(&"Phoenix".to_string());
+    let saved = k.get_static_field_by_name_and_desc(&"Phoenix.saved:LPhoenix;".to_string()).unwrap();
+    repo.put_static(BOOTSTRAP_LOADER, saved, JvmValue::ObjRef(0));
+    repo.collect_garbage();
+    assert!(!repo.heap().is_live(phoenix));
+    assert_eq!(0, repo.run_finalization());
+    assert_eq!(Some(1), get_static_of(&repo, "Phoenix", "finalized:I").as_int());
+}
+
+#[test]
+fn exceptions_in_finalizers_are_ignored() {
+    let mut repo = finalize_repo();
+    make_garbage(&mut repo, "Faulty");
+    repo.collect_garbage();
+    assert_eq!(2, repo.run_finalization());
+    assert_eq!(Some(2), get_static_of(&repo, "Faulty", "finalized:I").as_int());
+
+    repo.collect_garbage();
+    assert_eq!(0, instances_of(&repo, "Faulty"));
+    assert_eq!(0, repo.run_finalization());
+}
+
+/////////////////////////////////////////////////////////////////
+//
+// Tests for user-defined class loaders
+
+fn new_loader(repo: &mut SharedKlassRepo) -> usize {
+    let cl_klass = repo.lookup_klass(&"java/lang/ClassLoader".to_string());
+    let loader = repo.heap_mut().allocate_obj(&cl_klass);
+
+    let mut vars = InterpLocalVars::of(1);
+    vars.store(0, JvmValue::ObjRef(loader));
+    native_methods::java_lang_ClassLoader__init(repo, &vars);
+    loader
+}
+
+fn class_bytes(cname: &str) -> Vec<u8> {
+    let path = format!("./resources/test/{}.class", cname);
+    file_to_bytes(Path::new(&path)).unwrap_or_else(|_| panic!("Error reading {}", cname))
+}
+
+// Runs ClassLoader.defineClass0(), so that whatever it throws comes back as Err
+fn define_class0(repo: &mut SharedKlassRepo, loader: usize, bytes: &[u8]) -> JvmResult {
+    let byte_arr_klass = repo.prim_array_klass('B');
+    let arr = repo.heap_mut().allocate_byte_arr_from(&byte_arr_klass, bytes);
+    let define = "java/lang/ClassLoader.defineClass0:([BII)Ljava/lang/Class;".to_string();
+    let meth = repo.lookup_method_exact(BOOTSTRAP_LOADER, &"java/lang/ClassLoader".to_string(), define).unwrap();
+    let mut vars = InterpLocalVars::of(4);
+    vars.store(0, JvmValue::ObjRef(loader));
+    vars.store(1, JvmValue::ObjRef(arr));
+    vars.store(2, JvmValue::Int(0));
+    vars.store(3, JvmValue::Int(bytes.len() as i32));
+    exec_method(repo, &meth, &mut vars)
+}
+
+fn define_via_native(repo: &mut SharedKlassRepo, loader: usize, cname: &str) -> usize {
+    match define_class0(repo, loader, &class_bytes(cname)) {
+        Ok(Some(JvmValue::ObjRef(mirror))) => mirror,
+        ret => panic!("defineClass0 did not return a Class for {}: {:?}", cname, ret),
+    }
+}
+
+fn exec_in_loader(repo: &mut SharedKlassRepo, loader: usize, kname: &str, fq_meth: &str) -> i32 {
+    let k = repo.lookup_klass_in(loader, &kname.to_string());
+    let meth = k
+        .get_method_by_name_and_desc(&fq_meth.to_string())
+        .unwrap_or_else(|| panic!("{} not found", fq_meth))
+        .clone();
+    let mut vars = InterpLocalVars::of(5);
+    match exec_method(repo, &meth, &mut vars).unwrap().unwrap() {
+        JvmValue::Int(i) => i,
+        _ => panic!("Error executing {} - non-int value returned", fq_meth),
+    }
+}
+
+#[test]
+fn loaders_isolate_same_named_klasses() {
+    let mut repo = init_repo();
+    let l1 = new_loader(&mut repo);
+    let l2 = new_loader(&mut repo);
+
+    for (loader, dir) in [(l1, "v1"), (l2, "v2")] {
+        repo.define_klass(loader, &simple_parse_klass(format!("loader/{}/Plugin", dir))).unwrap();
+        repo.define_klass(loader, &simple_parse_klass(format!("loader/{}/PluginHelper", dir))).unwrap();
+    }
+
+    assert_eq!(11, exec_in_loader(&mut repo, l1, "Plugin", "Plugin.version:()I"));
+    assert_eq!(21, exec_in_loader(&mut repo, l2, "Plugin", "Plugin.version:()I"));
+
+    let p1 = repo.lookup_klass_in(l1, &"Plugin".to_string());
+    let p2 = repo.lookup_klass_in(l2, &"Plugin".to_string());
+    assert_ne!(p1.get_id(), p2.get_id());
+    assert_eq!(l1, p1.get_loader());
+    assert_eq!(l2, p2.get_loader());
+
+    // Bootstrap classes are still visible through the delegating lookup
+    let obj = repo.lookup_klass_in(l1, &"java/lang/Object".to_string());
+    assert_eq!(BOOTSTRAP_LOADER, obj.get_loader());
+}
+
+#[test]
+fn class_loader_natives() {
+    let mut repo = init_repo();
+    let loader = new_loader(&mut repo);
+
+    let helper = define_via_native(&mut repo, loader, "loader/v2/PluginHelper");
+    let plugin = define_via_native(&mut repo, loader, "loader/v2/Plugin");
+    assert_ne!(helper, plugin);
+
+    let mut vars = InterpLocalVars::of(2);
+    vars.store(0, JvmValue::ObjRef(loader));
+    vars.store(1, JvmValue::ObjRef(plugin));
+    native_methods::java_lang_ClassLoader__resolveClass0(&mut repo, &vars);
+    assert_eq!(21, exec_in_loader(&mut repo, loader, "Plugin", "Plugin.version:()I"));
+
+    let mut vars = InterpLocalVars::of(1);
+    vars.store(0, JvmValue::ObjRef(plugin));
+    match native_methods::java_lang_Class__getClassLoader(&mut repo, &vars) {
+        Some(JvmValue::ObjRef(l)) => assert_eq!(loader, l),
+        _ => panic!("Class.getClassLoader() did not return a reference"),
+    }
+
+    // Mirrors are canonical per klass
+    let klass_id = repo.klass_id_for_mirror(plugin);
+    assert_eq!(plugin, repo.get_mirror(klass_id));
+}
+
+#[test]
+fn resolve_class_with_missing_superclass() {
+    let mut repo = init_repo();
+    let loader = new_loader(&mut repo);
+    let orphan = define_via_native(&mut repo, loader, "loader/orphan/Orphan");
+
+    let mut vars = InterpLocalVars::of(2);
+    vars.store(0, JvmValue::ObjRef(loader));
+    vars.store(1, JvmValue::ObjRef(orphan));
+    assert!(native_methods::java_lang_ClassLoader__resolveClass0(&mut repo, &vars).is_none());
+    let ex = repo.take_pending_exception().unwrap();
+    assert_eq!("java/lang/NoClassDefFoundError", ex.klass_name);
+    assert_eq!("Parent (superclass of Orphan)", ex.message);
+}
+
+#[test]
+fn find_system_class_of_missing_klass() {
+    let mut repo = init_repo();
+    let loader = new_loader(&mut repo);
+    let find = |repo: &mut SharedKlassRepo, name: &str| {
+        let mut vars = InterpLocalVars::of(2);
+        vars.store(0, JvmValue::ObjRef(loader));
+        vars.store(1, JvmValue::ObjRef(repo.new_string_from_rust(name)));
+        native_methods::java_lang_ClassLoader__findSystemClass0(repo, &vars)
+    };
+
+    let string_klass = repo.lookup_klass(&"java/lang/String".to_string());
+    let mirror = repo.get_mirror(string_klass.get_id());
+    assert_eq!(Some(mirror), find(&mut repo, "java.lang.String").and_then(|v| v.as_objref()));
+    assert_eq!(None, repo.take_pending_exception());
+
+    assert!(find(&mut repo, "no.such.Klass").is_none());
+    let ex = repo.take_pending_exception().unwrap();
+    assert_eq!("java/lang/ClassNotFoundException", ex.klass_name);
+    assert_eq!("no/such/Klass", ex.message);
+}
+
+#[test]
+fn loader_rejects_duplicate_definition() {
+    let mut repo = init_repo();
+    let loader = new_loader(&mut repo);
+    define_via_native(&mut repo, loader, "loader/v1/PluginHelper");
+    let ret = define_class0(&mut repo, loader, &class_bytes("loader/v1/PluginHelper"));
+    assert_eq!("java/lang/LinkageError", thrown_klass_name(&repo, ret));
+}
+
+#[test]
+fn loader_constraints_are_enforced_on_definition() {
+    let mut repo = init_repo();
+    let l1 = new_loader(&mut repo);
+    let l2 = new_loader(&mut repo);
+    repo.define_klass(l1, &simple_parse_klass("loader/v1/PluginHelper".to_string())).unwrap();
+
+    // A klass in l1 resolves something in l2 whose signature mentions PluginHelper,
+    // so the two loaders must now agree on what PluginHelper is
+    let desc = "(LPluginHelper;)V".to_string();
+    assert!(repo.check_loader_constraints(l1, l2, &desc).is_ok());
+    let ret = define_class0(&mut repo, l2, &class_bytes("loader/v2/PluginHelper"));
+    assert_eq!("java/lang/LinkageError", thrown_klass_name(&repo, ret));
+}
+
+#[test]
+fn loader_definition_errors_are_thrown() {
+    let mut repo = init_repo();
+    let loader = new_loader(&mut repo);
+    let ret = define_class0(&mut repo, loader, &[0xca, 0xfe, 0xba, 0xbe]);
+    assert_eq!("java/lang/ClassFormatError", thrown_klass_name(&repo, ret));
+
+    let object = repo.lookup_klass(&"java/lang/Object".to_string());
+    let err = repo.define_klass(loader, &object).unwrap_err();
+    assert_eq!("java/lang/SecurityException", err.klass_name);
+}
+
+/////////////////////////////////////////////////////////////////
+//
+// Tests for executable jars
+
+#[test]
+fn interp_executable_jar() {
+    let mut repo = init_repo();
+    let jar = "./resources/test/mrjar/app.jar";
+    let main_klass_name = ocelotter_util::read_manifest(jar)
+        .and_then(|m| m.main_class())
+        .expect("No Main-Class in app.jar");
+    for file in ocelotter_util::jar_with_class_path(jar) {
+        assert!(repo.add_jar(&file, JAVA_RELEASE).is_empty());
+    }
+
+    // Version comes from META-INF/versions/11 and LibValue from lib/lib.jar
+    let k = repo.lookup_klass(&main_klass_name);
+    let fqname = "App.main2:([Ljava/lang/String;)I".to_string();
+    let meth = k.get_method_by_name_and_desc(&fqname).unwrap();
+    let mut vars = InterpLocalVars::of(5);
+    let ret = match exec_method(&mut repo, meth, &mut vars).unwrap().unwrap() {
+        JvmValue::Int(i) => i,
+        _ => panic!("Error executing {} - non-int value returned", fqname),
+    };
+    assert_eq!(111, ret);
+}
+
+#[test]
+fn interp_module_sources() {
+    for path in &[
+        "./resources/test/modules/greet.jmod",
+        "./resources/test/modules/exploded",
+    ] {
+        let mut repo = init_repo();
+        assert!(repo.add_class_source(ocelotter_util::class_source_for(path, JAVA_RELEASE).as_ref()).is_empty());
+
+        let k = repo.lookup_klass(&"com/example/greet/Greeter".to_string());
+        let fqname = "com/example/greet/Greeter.answer:()I".to_string();
+        let meth = k.get_method_by_name_and_desc(&fqname).unwrap();
+        let mut vars = InterpLocalVars::of(5);
+        let ret = match exec_method(&mut repo, meth, &mut vars).unwrap().unwrap() {
+            JvmValue::Int(i) => i,
+            _ => panic!("Error executing {} - non-int value returned", fqname),
+        };
+        assert_eq!(42, ret);
+    }
+}
+
+fn snapshot_path(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("ocelotter-{}-{}.snapshot", name, std::process::id()))
+        .to_string_lossy()
+        .to_string()
+}
+
+#[test]
+fn snapshot_restores_bootstrap_repo() {
+    let mut fresh = SharedKlassRepo::of();
+    fresh.bootstrap(exec_method);
+    // FIXME Invokes only pass one argument, so FileDescriptor.<clinit> can't
+    // store a real FileDescriptor yet - put one there ourselves
+    let fd_name = "java/io/FileDescriptor".to_string();
+    let out_name = "java/io/FileDescriptor.out:Ljava/io/FileDescriptor;".to_string();
+    {
+        let fd_klass = fresh.lookup_klass(&fd_name);
+        let out_field = fd_klass.get_static_field_by_name_and_desc(&out_name).unwrap();
+        let fd = fresh.heap_mut().allocate_obj(&fd_klass);
+        fresh.put_static(BOOTSTRAP_LOADER, out_field, JvmValue::ObjRef(fd));
+    }
+    let abc: Vec<u16> = "abc".encode_utf16().collect();
+    fresh.intern_string(&abc);
+    let path = snapshot_path("restore");
+    let hash = ocelotter_runtime::snapshot::jar_hash(BOOTSTRAP_JAR);
+    fresh.save_snapshot(&path, hash).unwrap();
+
+    let mut repo = SharedKlassRepo::of();
+    assert!(repo.load_snapshot(&path, hash));
+    std::fs::remove_file(&path).unwrap();
+
+    // Klass ids survive, as do natives (by symbol) and initialised statics
+    let object_name = "java/lang/Object".to_string();
+    assert_eq!(fresh.lookup_klass(&object_name).get_id(), repo.lookup_klass(&object_name).get_id());
+    let hashcode = repo.lookup_method_exact(BOOTSTRAP_LOADER, &object_name, "java/lang/Object.hashCode:()I".to_string()).unwrap();
+    assert_eq!(Some("java_lang_Object__hashcode"), hashcode.get_native_symbol());
+    let string_hash =
+        repo.lookup_method_exact(BOOTSTRAP_LOADER, &"java/lang/String".to_string(), "java/lang/String.hashCode:()I".to_string()).unwrap();
+    assert!(string_hash.is_intrinsic());
+
+    let fd_klass = repo.lookup_klass(&fd_name);
+    let out_field = fd_klass.get_static_field_by_name_and_desc(&out_name).unwrap();
+    let before = fresh.lookup_klass(&fd_name).get_static(out_field).as_objref().unwrap();
+    let after = fd_klass.get_static(out_field).as_objref().unwrap();
+    assert_ne!(0, before);
+    // Object ids are per repo, so the copy can only be recognised by its klass
+    assert_ne!(0, after);
+    assert_eq!(fd_klass.get_id(), repo.heap().get_obj(after).get_klassid());
+
+    // So does the string pool
+    let interned = repo.find_interned(&abc).unwrap();
+    assert_eq!("abc", repo.string_to_rust(interned));
+
+    // The restored repo runs code just like a freshly bootstrapped one
+    let k = simple_parse_klass("SampleInvoke".to_string());
+    repo.add_klass(&k);
+    let meth = k.get_method_by_name_and_desc(&"SampleInvoke.bar:()I".to_string()).unwrap();
+    let mut vars = InterpLocalVars::of(5);
+    let ret = exec_method(&mut repo, meth, &mut vars).unwrap().unwrap();
+    assert_eq!(7, ret.as_int().unwrap());
+}
+
+#[test]
+fn snapshot_is_invalidated_by_jar_hash() {
+    let mut fresh = SharedKlassRepo::of();
+    fresh.bootstrap(exec_method);
+    let path = snapshot_path("stale");
+    let hash = ocelotter_runtime::snapshot::jar_hash(BOOTSTRAP_JAR);
+    fresh.save_snapshot(&path, hash).unwrap();
+
+    let mut repo = SharedKlassRepo::of();
+    assert!(!repo.load_snapshot(&path, hash ^ 1));
+    assert!(!repo.load_snapshot(&snapshot_path("missing"), hash));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn snapshot_is_invalidated_by_build() {
+    let mut fresh = SharedKlassRepo::of();
+    fresh.bootstrap(exec_method);
+    let path = snapshot_path("other-build");
+    let hash = ocelotter_runtime::snapshot::jar_hash(BOOTSTRAP_JAR);
+    fresh.save_snapshot(&path, hash).unwrap();
+
+    // The build hash follows the magic and version
+    let mut bytes = std::fs::read(&path).unwrap();
+    assert_eq!(ocelotter_runtime::snapshot::build_hash().to_be_bytes(), bytes[6..14]);
+    bytes[6] ^= 1;
+    std::fs::write(&path, bytes).unwrap();
+
+    let mut repo = SharedKlassRepo::of();
+    assert!(!repo.load_snapshot(&path, hash));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn snapshot_with_unknown_native_is_ignored() {
+    let mut fresh = SharedKlassRepo::of();
+    fresh.bootstrap(exec_method);
+    let path = snapshot_path("unknown-native");
+    let hash = ocelotter_runtime::snapshot::jar_hash(BOOTSTRAP_JAR);
+    fresh.save_snapshot(&path, hash).unwrap();
+
+    // As if written by a build where the native has since been renamed
+    let bytes = std::fs::read(&path).unwrap();
+    let (from, to) = (b"java_lang_Object__hashcode", b"java_lang_Object__renamed0");
+    let at = bytes.windows(from.len()).position(|w| w == from).unwrap();
+    let mut renamed = bytes.clone();
+    renamed[at..at + to.len()].copy_from_slice(to);
+    std::fs::write(&path, renamed).unwrap();
+
+    let mut repo = SharedKlassRepo::of();
+    assert!(!repo.load_snapshot(&path, hash));
+    std::fs::remove_file(&path).unwrap();
+}
+
+/////////////////////////////////////////////////////////////////
+//
+// Tests for heap dumps
+
+fn dump_path(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("ocelotter-{}-{}.hprof", name, std::process::id()))
+        .to_string_lossy()
+        .to_string()
+}
+
+// How many of each kind of record an HPROF dump holds
+#[derive(Default)]
+struct HprofCounts {
+    klasses: usize,
+    instances: usize,
+    obj_arrays: usize,
+    prim_arrays: usize,
+    roots: usize,
+}
+
+fn skip(buf: &mut Cursor<&[u8]>, n: u64) {
+    buf.set_position(buf.position() + n);
+}
+
+// Reads a dump back, checking every instance against the field layout that
+// the class dumps of its klass and superclasses declare
+fn read_hprof(bytes: &[u8]) -> HprofCounts {
+    let mut buf = Cursor::new(bytes);
+    let mut header = [0u8; 19];
+    buf.read_exact(&mut header).unwrap();
+    assert_eq!(b"JAVA PROFILE 1.0.2\0", &header);
+    assert_eq!(8, buf.read_u32::<BigEndian>().unwrap());
+    skip(&mut buf, 8);
+
+    let mut counts = HprofCounts::default();
+    // Class id -> (superclass id, bytes of instance fields it declares)
+    let mut layouts: HashMap<u64, (u64, usize)> = HashMap::new();
+    let mut instances = Vec::new();
+    while (buf.position() as usize) < bytes.len() {
+        let tag = buf.read_u8().unwrap();
+        skip(&mut buf, 4);
+        let end = buf.read_u32::<BigEndian>().unwrap() as u64 + buf.position();
+        if tag != hprof::TAG_HEAP_DUMP_SEGMENT {
+            buf.set_position(end);
+            continue;
+        }
+        while buf.position() < end {
+            match buf.read_u8().unwrap() {
+                hprof::ROOT_STICKY_CLASS => {
+                    skip(&mut buf, 8);
+                    counts.roots += 1;
+                }
+                hprof::ROOT_JNI_GLOBAL | hprof::ROOT_JAVA_FRAME => {
+                    skip(&mut buf, 16);
+                    counts.roots += 1;
+                }
+                hprof::CLASS_DUMP => {
+                    let id = buf.read_u64::<BigEndian>().unwrap();
+                    skip(&mut buf, 4);
+                    let super_id = buf.read_u64::<BigEndian>().unwrap();
+                    skip(&mut buf, 5 * 8 + 4);
+                    assert_eq!(0, buf.read_u16::<BigEndian>().unwrap());
+                    for _ in 0..buf.read_u16::<BigEndian>().unwrap() {
+                        skip(&mut buf, 8);
+                        let t = buf.read_u8().unwrap();
+                        skip(&mut buf, hprof::basic_type_size(t) as u64);
+                    }
+                    let mut size = 0;
+                    for _ in 0..buf.read_u16::<BigEndian>().unwrap() {
+                        skip(&mut buf, 8);
+                        size += hprof::basic_type_size(buf.read_u8().unwrap());
+                    }
+                    layouts.insert(id, (super_id, size));
+                    counts.klasses += 1;
+                }
+                hprof::INSTANCE_DUMP => {
+                    skip(&mut buf, 12);
+                    let klass = buf.read_u64::<BigEndian>().unwrap();
+                    let size = buf.read_u32::<BigEndian>().unwrap();
+                    skip(&mut buf, size as u64);
+                    instances.push((klass, size as usize));
+                    counts.instances += 1;
+                }
+                hprof::OBJ_ARRAY_DUMP => {
+                    skip(&mut buf, 12);
+                    let length = buf.read_u32::<BigEndian>().unwrap() as u64;
+                    skip(&mut buf, 8 + length * 8);
+                    counts.obj_arrays += 1;
+                }
+                hprof::PRIM_ARRAY_DUMP => {
+                    skip(&mut buf, 12);
+                    let length = buf.read_u32::<BigEndian>().unwrap() as u64;
+                    let t = buf.read_u8().unwrap();
+                    skip(&mut buf, length * hprof::basic_type_size(t) as u64);
+                    counts.prim_arrays += 1;
+                }
+                t => panic!("Unexpected heap dump sub-record {:#x}", t),
+            }
+        }
+    }
+
+    for (klass, size) in instances {
+        let mut expected = 0;
+        let mut current = klass;
+        while current != 0 {
+            let (super_id, declared) = layouts[&current];
+            expected += declared;
+            current = super_id;
+        }
+        assert_eq!(expected, size);
+    }
+    counts
+}
+
+#[test]
+fn heap_dump_holds_every_object() {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("gc/Node".to_string()));
+    let node = repo.lookup_klass(&"Node".to_string());
+    let nodes = repo.array_klass(BOOTSTRAP_LOADER, "[LNode;").unwrap();
+    let longs = repo.prim_array_klass('J');
+    let held = repo.heap_mut().allocate_obj(&node);
+    repo.heap_mut().allocate_obj_arr(&nodes, 2).unwrap();
+    repo.heap_mut().allocate_prim_arr(&longs, 3).unwrap();
+    repo.push_frame(vec![held]);
+
+    let path = dump_path("dump");
+    repo.dump_heap(&path).unwrap();
+    let counts = read_hprof(&std::fs::read(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+
+    let heap = repo.heap();
+    let objs: Vec<&OtObj> = heap.live_ids().into_iter().map(|id| heap.get_obj(id)).collect();
+    let instances = objs.iter().filter(|o| matches!(o, OtObj::VmObj { .. })).count();
+    let obj_arrays = objs.iter().filter(|o| matches!(o, OtObj::VmArrObj { .. })).count();
+    assert_eq!(repo.loaded_klasses().len(), counts.klasses);
+    assert_eq!(instances, counts.instances);
+    assert_eq!(obj_arrays, counts.obj_arrays);
+    assert_eq!(objs.len() - instances - obj_arrays, counts.prim_arrays);
+    assert!(counts.obj_arrays >= 1 && counts.prim_arrays >= 1);
+    assert!(counts.roots > 0);
+}
+
+#[test]
+fn out_of_memory_leaves_a_heap_dump() {
+    let path = dump_path("oom");
+    let mut repo = init_repo();
+    repo.set_heap_dump_path(Some(path.clone()));
+    let k = simple_parse_klass("hprof/Huge".to_string());
+    repo.add_klass(&k);
+    let meth = k.get_method_by_name_and_desc(&"Huge.allocate:()I".to_string()).unwrap();
+
+    let ret = exec_method(&mut repo, meth, &mut InterpLocalVars::of(5));
+    assert_eq!("java/lang/OutOfMemoryError", thrown_klass_name(&repo, ret));
+
+    let counts = read_hprof(&std::fs::read(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(repo.heap().live_count(), counts.instances + counts.obj_arrays + counts.prim_arrays);
+}
+
+#[test]
+fn class_histogram_matches_heap_stats() {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("gc/Node".to_string()));
+    let node = repo.lookup_klass(&"Node".to_string());
+    let nodes = repo.array_klass(BOOTSTRAP_LOADER, "[LNode;").unwrap();
+    for _ in 0..5 {
+        repo.heap_mut().allocate_obj(&node);
+    }
+    repo.heap_mut().allocate_obj_arr(&nodes, 2).unwrap();
+
+    let histo = repo.class_histogram();
+    let entry = histo.get("Node").unwrap();
+    assert_eq!(5, entry.instances);
+    // Header and two fields
+    assert_eq!(5 * 32, entry.bytes);
+    assert_eq!(1, histo.get("[LNode;").unwrap().instances);
+    assert_eq!(repo.heap().live_count(), histo.total_instances());
+    assert_eq!(repo.heap().stats().used_bytes, histo.total_bytes());
+    assert!(histo.entries.windows(2).all(|w| w[0].bytes >= w[1].bytes));
+    assert!(histo.to_string().contains("  [LNode;\n"));
+
+    let args = InterpLocalVars::of(1);
+    let total = native_methods::java_lang_Runtime__totalMemory(&mut repo, &args).unwrap().as_long().unwrap();
+    let free = native_methods::java_lang_Runtime__freeMemory(&mut repo, &args).unwrap().as_long().unwrap();
+    assert_eq!(repo.heap().stats().used_bytes as i64, total - free);
+    assert!(free >= 0);
+}
+
+// A bootstrapped repo with room for two of the arrays Limits makes, but not three
+fn limited_repo() -> SharedKlassRepo {
+    let mut repo = init_repo();
+    let used = repo.heap().stats().used_bytes;
+    repo.set_heap_limits(used, used + 1024 * 1024 + 1024);
+    repo.add_klass(&simple_parse_klass("heap/Limits".to_string()));
+    repo
+}
+
+#[test]
+fn heap_limit_collects_before_giving_up() {
+    let mut repo = limited_repo();
+    assert_eq!(Some(100000), exec_in(&mut repo, "Limits", "garbage:()I", &[]).unwrap().and_then(|v| v.as_int()));
+    let stats = repo.heap().stats();
+    assert!(stats.used_bytes <= stats.max_bytes);
+    assert!(stats.committed_bytes <= stats.max_bytes);
+    assert!(stats.allocated_bytes > stats.max_bytes);
+}
+
+#[test]
+fn heap_limit_throws_out_of_memory_error() {
+    let mut repo = limited_repo();
+    let ret = exec_in(&mut repo, "Limits", "held:()I", &[]);
+    assert_eq!("java/lang/OutOfMemoryError", thrown_klass_name(&repo, ret));
+    // The third array was never allocated
+    assert!(repo.heap().stats().used_bytes <= repo.heap().stats().max_bytes);
+
+    // Java code can recover from it
+    assert_eq!(Some(100001), exec_in(&mut repo, "Limits", "caught:()I", &[]).unwrap().and_then(|v| v.as_int()));
+}
+
+fn exec_static_int(repo: &mut SharedKlassRepo, meth: &OtMethod) -> i32 {
+    let mut vars = InterpLocalVars::of(5);
+    match exec_method(repo, meth, &mut vars).unwrap().unwrap() {
+        JvmValue::Int(i) => i,
+        _ => panic!("Error executing {} - non-int value returned", meth),
+    }
+}
+
+fn redefine_bytes(version: &str) -> Vec<u8> {
+    file_to_bytes(Path::new(&format!("./resources/test/redefine/{}/Counter.class", version)))
+        .unwrap_or_else(|_| panic!("Error reading Counter {}", version))
+}
+
+#[test]
+fn redefine_replaces_method_bodies() {
+    let mut repo = init_repo();
+    let mut parser = klass_parser::OtKlassParser::of(redefine_bytes("v1"), "Counter.class".to_string());
+    parser.parse();
+    repo.add_klass(&parser.klass());
+
+    let counter = "Counter".to_string();
+    let next = "Counter.next:()I".to_string();
+    let old_next = repo.lookup_method_exact(BOOTSTRAP_LOADER, &counter, next.clone()).unwrap();
+    assert_eq!(11, exec_static_int(&mut repo, &old_next));
+
+    let id = repo.lookup_klass(&counter).get_id();
+    repo.redefine_klass(redefine_bytes("v2")).unwrap();
+    assert_eq!(id, repo.lookup_klass(&counter).get_id());
+
+    // New invocations see the new code
+    let new_next = repo.lookup_method_exact(BOOTSTRAP_LOADER, &counter, next).unwrap();
+    assert_eq!(222, exec_static_int(&mut repo, &new_next));
+
+    // Code that was already running keeps its own constant pool, where #7 is
+    // value() rather than other(), but calls into the new value()
+    assert_eq!(12, exec_static_int(&mut repo, &old_next));
+}
+
+#[test]
+fn redefine_rejects_schema_changes() {
+    let mut repo = init_repo();
+    assert!(repo.redefine_klass(redefine_bytes("v1")).is_err());
+
+    let mut parser = klass_parser::OtKlassParser::of(redefine_bytes("v1"), "Counter.class".to_string());
+    parser.parse();
+    repo.add_klass(&parser.klass());
+    let err = repo.redefine_klass(redefine_bytes("schema")).unwrap_err();
+    assert!(err.contains("add, delete"), "{}", err);
+    assert!(repo.redefine_klass(vec![0xca, 0xfe]).is_err());
+
+    let value = repo.lookup_method_exact(BOOTSTRAP_LOADER, &"Counter".to_string(), "Counter.value:()I".to_string()).unwrap();
+    assert_eq!(1, exec_static_int(&mut repo, &value));
+}
+
+fn indy_repo() -> SharedKlassRepo {
+    let mut repo = init_repo();
+    let cnames = ["Lambdas", "IntOp", "IntSource", "PtMaker", "PtGetter", "Mixer", "Action", "Pt"];
+    for cname in cnames {
+        repo.add_klass(&simple_parse_klass(format!("indy/{}", cname)));
+    }
+    repo
+}
+
+fn exec_lambdas(repo: &mut SharedKlassRepo, name_desc: &str) -> Option<JvmValue> {
+    exec_in(repo, "Lambdas", name_desc, &[]).unwrap_or_else(|t| panic!("{} threw {:?}", name_desc, t))
+}
+
+fn exec_concat(repo: &mut SharedKlassRepo, name: &str) -> String {
+    match exec_lambdas(repo, &format!("{}:()Ljava/lang/String;", name)) {
+        Some(JvmValue::ObjRef(s_id)) => repo.string_to_rust(s_id),
+        other => panic!("{} returned {:?}", name, other),
+    }
+}
+
+#[test]
+fn lambdas_are_linked_by_invokedynamic() {
+    let mut repo = indy_repo();
+    let cases = [
+        ("nonCapturing", 7),
+        ("capturing", 15),
+        ("capturingThis", 22),
+        ("staticRef", 42),
+        ("constructorRef", 9),
+        ("unboundRef", 11),
+        ("wideArgs", 14),
+        ("voidSam", 6),
+    ];
+    for (name, expected) in cases.iter() {
+        let ret = exec_lambdas(&mut repo, &format!("{}:()I", name));
+        assert_eq!(Some(*expected), ret.and_then(|v| v.as_int()), "{}", name);
+    }
+
+    // The lambda klass is spun in the caller's loader and implements the interface
+    let lambda = repo.lookup_klass(&"Lambdas$$Lambda$0".to_string());
+    assert!(repo.is_assignable(BOOTSTRAP_LOADER, &lambda.get_name(), "IntOp"));
+    assert_eq!(0, lambda.get_instance_fields().len());
+    assert_eq!(1, repo.lookup_klass(&"Lambdas$$Lambda$1".to_string()).get_instance_fields().len());
+}
+
+#[test]
+fn call_sites_are_linked_once() {
+    let mut repo = indy_repo();
+    assert_eq!(Some(30), exec_lambdas(&mut repo, "sameSiteTwice:()I").and_then(|v| v.as_int()));
+    let linked = repo.call_site_count();
+    assert_eq!(1, linked);
+
+    assert_eq!(Some(30), exec_lambdas(&mut repo, "sameSiteTwice:()I").and_then(|v| v.as_int()));
+    assert_eq!(linked, repo.call_site_count());
+    assert!(repo.find_klass_in(BOOTSTRAP_LOADER, "Lambdas$$Lambda$1").is_none());
+}
+
+#[test]
+fn string_concat_follows_the_recipe() {
+    let mut repo = indy_repo();
+    let cases = [
+        ("concatInt", "n=42"),
+        ("concatStrings", "foo-foo"),
+        ("concatChar", "cx"),
+        ("concatNull", "s=null,o=null"),
+        ("concatPrims", "true,1,2.0,1.5"),
+        ("concatDoubles", "1.0E10,0.001,1.0E-4"),
+        // The literal holds the recipe's tag char, so it is passed as a constant
+        ("concatConstant", "a\u{1}b7"),
+    ];
+    for (name, expected) in cases.iter() {
+        assert_eq!(*expected, exec_concat(&mut repo, name), "{}", name);
+    }
+}
+
+// javac passes objects through String.valueOf() before concatenating them,
+// so a call site that takes them as they are is built by hand
+fn indy_caller() -> OtKlass {
+    let factory = "java/lang/invoke/StringConcatFactory";
+    let lookup = "Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/invoke/MethodType;";
+    let cp_entries = vec![
+        CpEntry::Integer(0),
+        CpEntry::Utf8("IndyCaller".to_string()),
+        CpEntry::Class(ClassRef(1)),
+        CpEntry::Utf8(factory.to_string()),
+        CpEntry::Class(ClassRef(3)),
+        CpEntry::Utf8("makeConcatWithConstants".to_string()),
+        CpEntry::Utf8(format!("({}Ljava/lang/String;[Ljava/lang/Object;)Ljava/lang/invoke/CallSite;", lookup)),
+        CpEntry::NameAndType(NameAndType::new(5, 6)),
+        CpEntry::MethodRef(MethodRef::new(4, 7)),
+        CpEntry::MethodHandle(MethodHandleRef::new(REF_INVOKESTATIC, 8)),
+        CpEntry::Utf8("makeConcat".to_string()),
+        CpEntry::Utf8(format!("({})Ljava/lang/invoke/CallSite;", lookup)),
+        CpEntry::NameAndType(NameAndType::new(10, 11)),
+        CpEntry::MethodRef(MethodRef::new(4, 12)),
+        CpEntry::MethodHandle(MethodHandleRef::new(REF_INVOKESTATIC, 13)),
+        CpEntry::Utf8("p=\u{1},\u{1}".to_string()),
+        CpEntry::String(StringRef(15)),
+        CpEntry::Utf8("concat".to_string()),
+        CpEntry::Utf8("(LPt;I)Ljava/lang/String;".to_string()),
+        CpEntry::NameAndType(NameAndType::new(17, 18)),
+        CpEntry::InvokeDynamic(InvokeDynamicRef::new(0, 19)),
+        CpEntry::InvokeDynamic(InvokeDynamicRef::new(1, 19)),
+    ];
+    let mut k = OtKlass::of("IndyCaller".to_string(), "java/lang/Object".to_string(), ACC_PUBLIC, &cp_entries, &vec![], &vec![]);
+    k.set_bootstrap_methods(vec![
+        BootstrapMethod { method_idx: 9, arg_idxs: vec![16] },
+        BootstrapMethod { method_idx: 14, arg_idxs: vec![] },
+    ]);
+    k
+}
+
+fn exec_indy_caller(repo: &mut SharedKlassRepo, cp_idx: u8, pt: usize) -> String {
+    let code = [opcode::ALOAD_0, opcode::ILOAD_1, opcode::INVOKEDYNAMIC, 0, cp_idx, 0, 0, opcode::ARETURN];
+    let mut vars = InterpLocalVars::of(2);
+    vars.store(0, JvmValue::ObjRef(pt));
+    vars.store(1, JvmValue::Int(5));
+    match exec_bytecode_method(repo, BOOTSTRAP_LOADER, "IndyCaller".to_string(), &code, &mut vars) {
+        Ok(Some(JvmValue::ObjRef(s_id))) => repo.string_to_rust(s_id),
+        other => panic!("Concat returned {:?}", other),
+    }
+}
+
+#[test]
+fn string_concat_calls_to_string() {
+    let mut repo = indy_repo();
+    repo.add_klass(&indy_caller());
+
+    let pt_klass = repo.lookup_klass(&"Pt".to_string());
+    let pt = repo.heap_mut().allocate_obj(&pt_klass);
+    let x = pt_klass.get_instance_field_by_name_and_desc(&"Pt.x:I".to_string()).unwrap().clone();
+    repo.heap().put_field(pt, x, JvmValue::Int(3));
+
+    // Pt.toString() is itself a concatenation
+    assert_eq!("p=Pt(3),5", exec_indy_caller(&mut repo, 20, pt));
+    assert_eq!("Pt(3)5", exec_indy_caller(&mut repo, 21, pt));
+    assert_eq!("p=null,5", exec_indy_caller(&mut repo, 20, 0));
+}
+
+#[test]
+fn bc_tableswitch_offsets_are_signed() {
+    // 0: GOTO 6; 3: BIPUSH 42; IRETURN; 6: ICONST_x; 7: TABLESWITCH 1..2
+    // where case 2 jumps back to 3
+    let switch = |key: u8| {
+        let mut buf = vec![opcode::GOTO, 0, 6, opcode::BIPUSH, 42, opcode::IRETURN, key, opcode::TABLESWITCH];
+        for operand in [21i32, 1, 2, 23, -4] {
+            buf.extend_from_slice(&operand.to_be_bytes());
+        }
+        buf.extend_from_slice(&[opcode::ICONST_1, opcode::IRETURN, opcode::BIPUSH, 7, opcode::IRETURN]);
+        execute_simple_bytecode(&buf).as_int()
+    };
+    assert_eq!(Some(7), switch(opcode::ICONST_1));
+    assert_eq!(Some(42), switch(opcode::ICONST_2));
+    assert_eq!(Some(1), switch(opcode::ICONST_3));
+}
+
+fn exec_switch(repo: &mut SharedKlassRepo, name_desc: &str, arg: JvmValue) -> i32 {
+    match exec_in(repo, "Switches", name_desc, &[arg]) {
+        Ok(Some(JvmValue::Int(i))) => i,
+        other => panic!("{} returned {:?}", name_desc, other),
+    }
+}
+
+#[test]
+fn int_switches_take_the_matching_branch() {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("switches/Switches".to_string()));
+    let cases = [
+        ("table", 1, 10),
+        ("table", 4, 40),
+        ("table", 0, -1),
+        ("table", 5, -1),
+        ("table", -1, -1),
+        ("lookup", -100000, 1),
+        ("lookup", -5, 2),
+        ("lookup", 7, 3),
+        ("lookup", 1000, 4),
+        ("lookup", 100000, 5),
+        ("lookup", 8, 0),
+        ("fallThrough", 1, 11),
+        ("fallThrough", 2, 10),
+        ("fallThrough", 3, 1100),
+        ("fallThrough", 9, 1000),
+        ("machine", 0, 2),
+        ("machine", 2, 1),
+        ("machine", 1, 0),
+        ("machine", 3, -1),
+    ];
+    for (name, arg, expected) in cases.iter() {
+        let ret = exec_switch(&mut repo, &format!("{}:(I)I", name), JvmValue::Int(*arg));
+        assert_eq!(*expected, ret, "{}({})", name, arg);
+    }
+}
+
+#[test]
+fn char_and_string_switches_take_the_matching_branch() {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("switches/Switches".to_string()));
+    let cases = [
+        ("denseChars", 'a', 1),
+        ("denseChars", 'c', 3),
+        ("denseChars", 'd', 0),
+        ("sparseChars", '0', 100),
+        ("sparseChars", 'A', 200),
+        ("sparseChars", 'z', 300),
+        ("sparseChars", 'y', 0),
+    ];
+    for (name, arg, expected) in cases.iter() {
+        let ret = exec_switch(&mut repo, &format!("{}:(C)I", name), JvmValue::Int(*arg as i32));
+        assert_eq!(*expected, ret, "{}({})", name, arg);
+    }
+
+    let cases = [("foo", 1), ("bar", 2), ("Aa", 3), ("BB", 4), ("baz", 0), ("", 0)];
+    for (arg, expected) in cases.iter() {
+        let s = repo.new_string_from_rust(arg);
+        let ret = exec_switch(&mut repo, "strings:(Ljava/lang/String;)I", JvmValue::ObjRef(s));
+        assert_eq!(*expected, ret, "strings({:?})", arg);
+    }
+}
+
+fn exec_wide_bytecode(buf: &[u8]) -> Option<i32> {
+    let mut repo = init_repo();
+    let mut lvt = InterpLocalVars::of(400);
+    exec_bytecode_method(&mut repo, BOOTSTRAP_LOADER, "DUMMY".to_string(), buf, &mut lvt)
+        .unwrap()
+        .and_then(|v| v.as_int())
+}
+
+#[test]
+fn bc_wide_reaches_high_locals() {
+    // 300 is 0x012c
+    let buf = vec![
+        opcode::BIPUSH, 5,
+        opcode::WIDE, opcode::ISTORE, 0x01, 0x2c,
+        opcode::WIDE, opcode::ILOAD, 0x01, 0x2c,
+        opcode::WIDE, opcode::ILOAD, 0x01, 0x2c,
+        opcode::IADD,
+        opcode::IRETURN,
+    ];
+    assert_eq!(Some(10), exec_wide_bytecode(&buf));
+
+    // +1000 then -2000 as 16-bit constants, then -1 as an 8-bit one
+    let buf = vec![
+        opcode::BIPUSH, 5,
+        opcode::WIDE, opcode::ISTORE, 0x01, 0x2c,
+        opcode::WIDE, opcode::IINC, 0x01, 0x2c, 0x03, 0xe8,
+        opcode::WIDE, opcode::IINC, 0x01, 0x2c, 0xf8, 0x30,
+        opcode::ICONST_0,
+        opcode::ISTORE_0,
+        opcode::IINC, 0, 0xff,
+        opcode::ILOAD_0,
+        opcode::WIDE, opcode::ILOAD, 0x01, 0x2c,
+        opcode::IADD,
+        opcode::IRETURN,
+    ];
+    assert_eq!(Some(-996), exec_wide_bytecode(&buf));
+}
+
+#[test]
+fn methods_with_many_locals_run() {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("wide/ManyLocals".to_string()));
+    let k = repo.lookup_klass(&"ManyLocals".to_string());
+    let many = k.get_method_by_name_and_desc(&"ManyLocals.many:()I".to_string()).unwrap();
+    assert!(many.get_local_var_size() > 256);
+
+    for (name, expected) in [("many", -397), ("bigIncrement", 29800)] {
+        let meth = k.get_method_by_name_and_desc(&format!("ManyLocals.{}:()I", name)).unwrap();
+        let mut vars = InterpLocalVars::of(meth.get_local_var_size());
+        let ret = exec_method(&mut repo, meth, &mut vars).unwrap_or_else(|t| panic!("{} threw {:?}", name, t));
+        assert_eq!(Some(expected), ret.and_then(|v| v.as_int()), "{}", name);
+    }
+}
+
+#[test]
+fn ldc_w_loads_high_constant_pool_indices() {
+    let mut repo = init_repo();
+    repo.add_klass(&simple_parse_klass("ldc/ManyConstants".to_string()));
+    let last = match exec_in(&mut repo, "ManyConstants", "last:()Ljava/lang/String;", &[]) {
+        Ok(Some(JvmValue::ObjRef(s_id))) => s_id,
+        ret => panic!("last() returned {:?}", ret),
+    };
+    assert_eq!("s299", repo.string_to_rust(last));
+    // Literals are interned, so the same one comes back every time
+    let again = exec_in(&mut repo, "ManyConstants", "last:()Ljava/lang/String;", &[]).unwrap();
+    assert_eq!(Some(last), again.and_then(|v| v.as_objref()));
+    assert_eq!(Some(123456), exec_in(&mut repo, "ManyConstants", "bigInt:()I", &[]).unwrap().and_then(|v| v.as_int()));
+    match exec_in(&mut repo, "ManyConstants", "bigFloat:()F", &[]) {
+        Ok(Some(JvmValue::Float(f))) => assert_f32_near!(2.5, f),
+        ret => panic!("bigFloat() returned {:?}", ret),
+    }
+}
@@ -1,7 +1,8 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::fs::File;
 use zip::ZipArchive;
-use std::io::{Read, Seek};
+use std::io::{Cursor, Read, Seek};
 use zip::result::ZipResult;
 
 pub fn file_to_bytes(path: &Path) -> Result<Vec<u8>, std::io::Error> {
@@ -47,3 +48,370 @@ impl ZipFiles<File> {
         ZipFiles { i: 0, archive }
     }
 }
+
+impl ZipFiles<Cursor<Vec<u8>>> {
+    // A jmod is a zip file behind a 4-byte header of 'J' 'M' and a major / minor version
+    pub fn jmod(file_name: &str) -> ZipFiles<Cursor<Vec<u8>>> {
+        let zip_bytes = jmod_zip_bytes(file_name);
+
+        let archive = ZipArchive::new(Cursor::new(zip_bytes))
+            .unwrap_or_else(|_| panic!("Problem reading archive {}", file_name));
+
+        ZipFiles { i: 0, archive }
+    }
+}
+
+// The zip file inside a jmod
+fn jmod_zip_bytes(file_name: &str) -> Vec<u8> {
+    let mut bytes = file_to_bytes(Path::new(file_name))
+        .unwrap_or_else(|_| panic!("Couldn't open file {}", file_name));
+    if bytes.len() < JMOD_HEADER.len() || bytes[..2] != JMOD_HEADER[..2] {
+        panic!("{} does not have a jmod header", file_name);
+    }
+    bytes.split_off(JMOD_HEADER.len())
+}
+
+// Entry names in archive order, read without inflating anything
+fn zip_entry_names(zip_bytes: &[u8], file_name: &str) -> Vec<String> {
+    let mut archive = ZipArchive::new(Cursor::new(zip_bytes))
+        .unwrap_or_else(|_| panic!("Problem reading archive {}", file_name));
+    (0..archive.len())
+        .map(|i| archive.by_index_raw(i).map(|f| f.name().to_owned()).unwrap_or_default())
+        .collect()
+}
+
+// Inflates the wanted (name, entry index) pairs across the worker pool. Each
+// worker reads the archive through its own handle, and an entry that can't be
+// read comes back with the reason, for the loader to report.
+fn inflate_entries(zip_bytes: &[u8], wanted: Vec<(String, usize)>) -> Vec<ClassEntry> {
+    parallel_map(
+        worker_count(),
+        &wanted,
+        || ZipArchive::new(Cursor::new(zip_bytes)).map_err(|e| e.to_string()),
+        |archive, (name, idx)| {
+            let inflated = archive.as_mut().map_err(|e| e.clone()).and_then(|archive| {
+                let mut file = archive.by_index(*idx).map_err(|e| e.to_string())?;
+                let mut content = Vec::new();
+                file.read_to_end(&mut content).map_err(|e| e.to_string())?;
+                Ok(content)
+            });
+            (name.clone(), inflated)
+        },
+    )
+}
+
+//////////// WORKER POOL
+
+// One worker per core
+pub fn worker_count() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+// Maps f over items on up to workers threads, handing back the results in the
+// order of items. Each worker gets a contiguous run of items and its own state
+// from init, e.g. a handle on an archive.
+pub fn parallel_map<T, S, R, I, F>(workers: usize, items: &[T], init: I, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    I: Fn() -> S + Sync,
+    F: Fn(&mut S, &T) -> R + Sync,
+{
+    let workers = workers.clamp(1, items.len().max(1));
+    if workers == 1 {
+        let mut state = init();
+        return items.iter().map(|t| f(&mut state, t)).collect();
+    }
+    let run = items.len().div_ceil(workers);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(run)
+            .map(|chunk| {
+                let (init, f) = (&init, &f);
+                scope.spawn(move || {
+                    let mut state = init();
+                    chunk.iter().map(|t| f(&mut state, t)).collect::<Vec<R>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    })
+}
+
+//////////// JAR FILES
+
+pub const MANIFEST_NAME: &str = "META-INF/MANIFEST.MF";
+pub const JMOD_HEADER: [u8; 4] = [b'J', b'M', 0x01, 0x00];
+pub const JMOD_CLASSES_PREFIX: &str = "classes/";
+pub const MODULE_INFO: &str = "module-info.class";
+pub const VERSIONS_PREFIX: &str = "META-INF/versions/";
+
+// The main section of a jar manifest - per-entry sections are ignored
+pub struct Manifest {
+    attributes: HashMap<String, String>,
+}
+
+impl Manifest {
+    pub fn parse(bytes: &[u8]) -> Manifest {
+        let text = String::from_utf8_lossy(bytes);
+        let mut attributes = HashMap::new();
+        let mut last_key: Option<String> = None;
+        for line in text.lines() {
+            // A blank line ends the main section
+            if line.is_empty() {
+                break;
+            }
+            // Lines are wrapped at 72 bytes, continuations start with a single space
+            if let Some(rest) = line.strip_prefix(' ') {
+                if let Some(k) = &last_key {
+                    attributes.entry(k.clone()).and_modify(|v: &mut String| v.push_str(rest));
+                }
+                continue;
+            }
+            if let Some(idx) = line.find(':') {
+                let key = line[..idx].trim().to_string();
+                let value = line[idx + 1..].trim_start().to_string();
+                attributes.insert(key.clone(), value);
+                last_key = Some(key);
+            }
+        }
+        Manifest { attributes }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.attributes.get(key)
+    }
+
+    // In internal form, i.e. with slashes rather than dots
+    pub fn main_class(&self) -> Option<String> {
+        self.get("Main-Class").map(|c| c.replace('.', "/"))
+    }
+
+    pub fn class_path(&self) -> Vec<String> {
+        match self.get("Class-Path") {
+            Some(cp) => cp.split_whitespace().map(|s| s.to_string()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn is_multi_release(&self) -> bool {
+        self.get("Multi-Release")
+            .is_some_and(|v| v.eq_ignore_ascii_case("true"))
+    }
+}
+
+pub fn read_manifest(jar_name: &str) -> Option<Manifest> {
+    let file = File::open(jar_name).unwrap_or_else(|_| panic!("Couldn't open file {}", jar_name));
+    let mut archive =
+        ZipArchive::new(file).unwrap_or_else(|_| panic!("Problem reading archive {}", jar_name));
+    let mut bytes = Vec::new();
+    archive.by_name(MANIFEST_NAME).ok()?.read_to_end(&mut bytes).ok()?;
+    Some(Manifest::parse(&bytes))
+}
+
+// The class entries of a jar as (name, bytes). For a multi-release jar, an entry
+// under META-INF/versions/N/ replaces the base entry of the same name when N is
+// the highest version that is no later than release.
+pub fn jar_class_entries(jar_name: &str, release: u16) -> Vec<ClassEntry> {
+    let multi_release = read_manifest(jar_name).is_some_and(|m| m.is_multi_release());
+
+    let zip_bytes = file_to_bytes(Path::new(jar_name))
+        .unwrap_or_else(|_| panic!("Couldn't open file {}", jar_name));
+
+    // Entries are picked by name alone, and only the chosen ones are inflated
+    let mut out: Vec<(String, usize)> = Vec::new();
+    let mut chosen: HashMap<String, (usize, u16)> = HashMap::new();
+    for (idx, name) in zip_entry_names(&zip_bytes, jar_name).into_iter().enumerate() {
+        if !name.ends_with(".class") || name.ends_with("module-info.class") {
+            continue;
+        }
+        let (klass_file, version) = match name.strip_prefix(VERSIONS_PREFIX) {
+            Some(versioned) => {
+                let idx = match versioned.find('/') {
+                    Some(i) => i,
+                    None => continue,
+                };
+                match versioned[..idx].parse::<u16>() {
+                    Ok(v) if multi_release && v <= release => (versioned[idx + 1..].to_string(), v),
+                    _ => continue,
+                }
+            }
+            None => (name, 0),
+        };
+        match chosen.get(&klass_file) {
+            Some((_, v)) if *v >= version => (),
+            Some((i, _)) => {
+                let i = *i;
+                out[i] = (klass_file.clone(), idx);
+                chosen.insert(klass_file, (i, version));
+            }
+            None => {
+                chosen.insert(klass_file.clone(), (out.len(), version));
+                out.push((klass_file, idx));
+            }
+        }
+    }
+    inflate_entries(&zip_bytes, out)
+}
+
+// A jar followed by everything reachable through the Class-Path attributes of
+// the manifests, in search order. Relative entries are resolved against the
+// directory of the jar that names them, and entries that don't exist are skipped.
+pub fn jar_with_class_path(jar_name: &str) -> Vec<String> {
+    let mut out = vec![jar_name.to_string()];
+    let mut i = 0;
+    while i < out.len() {
+        let current = out[i].clone();
+        i += 1;
+        let manifest = match read_manifest(&current) {
+            Some(m) => m,
+            None => continue,
+        };
+        let base = Path::new(&current).parent().unwrap_or_else(|| Path::new(""));
+        for entry in manifest.class_path() {
+            let path = base.join(&entry);
+            let resolved = path.to_string_lossy().to_string();
+            if path.is_file() && !out.contains(&resolved) {
+                out.push(resolved);
+            }
+        }
+    }
+    out
+}
+
+//////////// CLASS SOURCES
+
+// Somewhere classes can be loaded from. Entries are (file name, bytes) where the
+// file name is the internal class name plus ".class", e.g. "java/lang/Object.class"
+pub trait ClassSource {
+    fn class_entries(&self) -> Vec<ClassEntry>;
+}
+
+// The bytes of an entry, or why it couldn't be read
+pub type ClassEntry = (String, Result<Vec<u8>, String>);
+
+pub struct JarSource {
+    jar_name: String,
+    release: u16,
+}
+
+impl JarSource {
+    pub fn of(jar_name: &str, release: u16) -> JarSource {
+        JarSource { jar_name: jar_name.to_string(), release }
+    }
+}
+
+impl ClassSource for JarSource {
+    fn class_entries(&self) -> Vec<ClassEntry> {
+        jar_class_entries(&self.jar_name, self.release)
+    }
+}
+
+pub struct JmodSource {
+    jmod_name: String,
+}
+
+impl JmodSource {
+    pub fn of(jmod_name: &str) -> JmodSource {
+        JmodSource { jmod_name: jmod_name.to_string() }
+    }
+}
+
+impl ClassSource for JmodSource {
+    fn class_entries(&self) -> Vec<ClassEntry> {
+        let zip_bytes = jmod_zip_bytes(&self.jmod_name);
+        // Everything else in a jmod (native libs, config, legal notices) is ignored
+        let wanted = zip_entry_names(&zip_bytes, &self.jmod_name)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(idx, name)| {
+                name.strip_prefix(JMOD_CLASSES_PREFIX)
+                    .filter(|n| n.ends_with(".class") && *n != MODULE_INFO)
+                    .map(|n| (n.to_string(), idx))
+            })
+            .collect();
+        inflate_entries(&zip_bytes, wanted)
+    }
+}
+
+// An exploded module is a directory with module-info.class at its root and the
+// classes below it as pkg/Name.class. The root given here can either be a module
+// itself or a directory of modules laid out as module/pkg/Name.class
+pub struct ExplodedModulesSource {
+    root: PathBuf,
+}
+
+impl ExplodedModulesSource {
+    pub fn of(root: &str) -> ExplodedModulesSource {
+        ExplodedModulesSource { root: PathBuf::from(root) }
+    }
+
+    pub fn module_dirs(&self) -> Vec<PathBuf> {
+        if self.root.join(MODULE_INFO).is_file() {
+            return vec![self.root.clone()];
+        }
+        let mut out: Vec<PathBuf> = match std::fs::read_dir(&self.root) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.join(MODULE_INFO).is_file())
+                .collect(),
+            Err(_) => panic!("Couldn't read directory {}", self.root.display()),
+        };
+        out.sort();
+        out
+    }
+}
+
+impl ClassSource for ExplodedModulesSource {
+    fn class_entries(&self) -> Vec<ClassEntry> {
+        let mut out = Vec::new();
+        for module_dir in self.module_dirs() {
+            collect_class_files(&module_dir, &module_dir, &mut out);
+        }
+        out
+    }
+}
+
+fn collect_class_files(module_dir: &Path, dir: &Path, out: &mut Vec<ClassEntry>) {
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => panic!("Couldn't read directory {}", dir.display()),
+    };
+    // Keep the load order stable whatever order the filesystem hands entries back in
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            collect_class_files(module_dir, &path, out);
+            continue;
+        }
+        let rel = match path.strip_prefix(module_dir) {
+            Ok(r) => r.components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<String>>()
+                .join("/"),
+            Err(_) => continue,
+        };
+        if rel.ends_with(".class") && rel != MODULE_INFO {
+            let bytes = file_to_bytes(&path).map_err(|e| e.to_string());
+            out.push((rel, bytes));
+        }
+    }
+}
+
+// Picks the backend for one classpath / module path element
+pub fn class_source_for(path: &str, release: u16) -> Box<dyn ClassSource> {
+    if Path::new(path).is_dir() {
+        Box::new(ExplodedModulesSource::of(path))
+    } else if path.ends_with(".jmod") {
+        Box::new(JmodSource::of(path))
+    } else {
+        Box::new(JarSource::of(path, release))
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,97 @@
+use super::*;
+
+#[test]
+fn parse_manifest_main_section() {
+    let mf = "Manifest-Version: 1.0\r\nMain-Class: com.example.Main\r\nClass-Path: a.jar\r\n  lib/b.jar\r\n\r\nName: Foo.class\r\nMain-Class: Ignored\r\n";
+    let manifest = Manifest::parse(mf.as_bytes());
+    assert_eq!(Some("com/example/Main".to_string()), manifest.main_class());
+    assert_eq!(vec!["a.jar", "lib/b.jar"], manifest.class_path());
+    assert!(!manifest.is_multi_release());
+}
+
+#[test]
+fn multi_release_entries() {
+    let jar = "../resources/test/mrjar/app.jar";
+    let manifest = read_manifest(jar).expect("app.jar has a manifest");
+    assert!(manifest.is_multi_release());
+    assert_eq!(Some("App".to_string()), manifest.main_class());
+
+    let base = jar_class_entries(jar, 8);
+    let names: Vec<&str> = base.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(vec!["App.class", "Version.class"], names);
+
+    // The versioned entry replaces the base one, under the base name
+    let v11 = jar_class_entries(jar, 11);
+    let names: Vec<&str> = v11.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(vec!["App.class", "Version.class"], names);
+    assert_ne!(base[1].1, v11[1].1);
+}
+
+#[test]
+fn unreadable_entries_are_kept() {
+    let entries = jar_class_entries("../resources/test/jar/corrupt.jar", 8);
+    let names: Vec<&str> = entries.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(vec!["Foo.class", "Foo2.class"], names);
+    assert!(entries[0].1.is_err());
+    assert!(entries[1].1.is_ok());
+}
+
+#[test]
+fn class_path_is_relative_to_jar() {
+    let jars = jar_with_class_path("../resources/test/mrjar/app.jar");
+    assert_eq!(
+        vec!["../resources/test/mrjar/app.jar", "../resources/test/mrjar/lib/lib.jar"],
+        jars
+    );
+}
+
+#[test]
+fn jmod_class_entries() {
+    let source = JmodSource::of("../resources/test/modules/greet.jmod");
+    let mut names: Vec<String> = source.class_entries().into_iter().map(|(n, _)| n).collect();
+    names.sort();
+    assert_eq!(
+        vec!["com/example/greet/Greeter.class", "com/example/greet/Helper.class"],
+        names
+    );
+}
+
+#[test]
+fn exploded_module_class_entries() {
+    // Both a directory of modules and a single module directory work
+    for root in &[
+        "../resources/test/modules/exploded",
+        "../resources/test/modules/exploded/com.example.greet",
+    ] {
+        let source = ExplodedModulesSource::of(root);
+        assert_eq!(1, source.module_dirs().len());
+        let names: Vec<String> = source.class_entries().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(
+            vec!["com/example/greet/Greeter.class", "com/example/greet/Helper.class"],
+            names
+        );
+    }
+}
+
+#[test]
+fn same_bytes_from_every_backend() {
+    let from_jmod = class_source_for("../resources/test/modules/greet.jmod", 11).class_entries();
+    let from_dir = class_source_for("../resources/test/modules/exploded", 11).class_entries();
+    for (name, bytes) in from_dir {
+        let (_, jmod_bytes) = from_jmod.iter().find(|(n, _)| *n == name).unwrap();
+        assert_eq!(jmod_bytes, &bytes);
+    }
+}
+
+#[test]
+fn parallel_map_keeps_item_order() {
+    let items: Vec<usize> = (0..100).collect();
+    // Each worker counts the items it has seen, so runs are contiguous
+    let out = parallel_map(4, &items, || 0, |seen, i| {
+        *seen += 1;
+        (*i * 2, *seen)
+    });
+    assert_eq!(items.iter().map(|i| i * 2).collect::<Vec<usize>>(), out.iter().map(|(d, _)| *d).collect::<Vec<usize>>());
+    assert_eq!(4, out.iter().filter(|(_, seen)| *seen == 1).count());
+    assert_eq!(Some(25), out.iter().map(|(_, seen)| *seen).max());
+}